@@ -0,0 +1,59 @@
+// Cook-Torrance microfacet BRDF, see https://learnopengl.com/PBR/Theory and
+// https://learnopengl.com/PBR/IBL/Specular-IBL
+
+// Trowbridge-Reitz GGX normal distribution function
+float distribution_ggx(float n_dot_h, float roughness) {
+  float a = roughness * roughness;
+  float a2 = a * a;
+  float denominator = n_dot_h * n_dot_h * (a2 - 1.) + 1.;
+  return a2 / (PI * denominator * denominator);
+}
+
+// Schlick-GGX geometry function, k depends on whether the light is direct or
+// comes from the environment
+float geometry_schlick_ggx(float n_dot_v, float k) {
+  return n_dot_v / (n_dot_v * (1. - k) + k);
+}
+
+float geometry_smith(float n_dot_v, float n_dot_l, float k) {
+  return geometry_schlick_ggx(n_dot_v, k) * geometry_schlick_ggx(n_dot_l, k);
+}
+
+float direct_k(float roughness) { return pow(roughness + 1., 2) / 8.; }
+
+float ibl_k(float roughness) { return roughness * roughness / 2.; }
+
+vec3 fresnel_schlick(float cos_theta, vec3 F0) {
+  return F0 + (1. - F0) * pow(1. - cos_theta, 5.);
+}
+
+// Accounts for the rough surfaces reflecting less at grazing angles
+vec3 fresnel_schlick_roughness(float cos_theta, vec3 F0, float roughness) {
+  return F0 + (max(vec3(1. - roughness), F0) - F0) * pow(1. - cos_theta, 5.);
+}
+
+// ***************************************************************************
+// Importance sampling, used when baking the image based lighting
+
+vec2 hammersley(uint i, uint n) {
+  uint bits = bitfieldReverse(i);
+  return vec2(float(i) / float(n), float(bits) * 2.3283064365386963e-10);
+}
+
+// A halfway vector around `normal`, distributed following GGX
+vec3 importance_sample_ggx(vec2 xi, vec3 normal, float roughness) {
+  float a = roughness * roughness;
+
+  float phi = 2. * PI * xi.x;
+  float cos_theta = sqrt((1. - xi.y) / (1. + (a * a - 1.) * xi.y));
+  float sin_theta = sqrt(1. - cos_theta * cos_theta);
+  vec3 tangent_halfway =
+      vec3(cos(phi) * sin_theta, sin(phi) * sin_theta, cos_theta);
+
+  vec3 up = abs(normal.z) < 0.999 ? vec3(0., 0., 1.) : vec3(1., 0., 0.);
+  vec3 tangent = normalize(cross(up, normal));
+  vec3 bitangent = cross(normal, tangent);
+
+  return normalize(tangent * tangent_halfway.x +
+                   bitangent * tangent_halfway.y + normal * tangent_halfway.z);
+}
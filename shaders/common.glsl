@@ -0,0 +1,24 @@
+// Shared by every program: everything which is the same for the whole frame
+// is in the Frame block, see FrameUniforms in program.rs
+
+uniform mat4 model;
+
+layout(std140, binding = FRAME_UNIFORMS_BINDING) uniform Frame {
+  mat4 view;
+  mat4 projection;
+  vec4 camera_position;
+  vec4 sun_direction;
+  vec2 viewport_size;
+  float time;
+  // How many lights are in the Lights buffer, see lighting.glsl
+  uint light_count;
+  // See fog.glsl
+  vec3 fog_color;
+  float fog_density;
+  float fog_height_falloff;
+  float fog_base_height;
+  float fog_end_distance;
+  float fog_sky;
+};
+
+#define PI 3.141592
@@ -1,69 +1,174 @@
 #version 450 core
 
+#include "common.glsl"
+#include "lighting.glsl"
+#include "brdf.glsl"
+#include "fog.glsl"
+
 layout(location = 0) in vec2 in_texture_uv;
 layout(location = 1) in vec3 in_normal;
 layout(location = 2) in vec3 in_model_position;
+layout(location = 3) flat in uint in_layer;
+layout(location = 4) flat in uint in_surface_layer;
+layout(location = 5) flat in uint in_material;
+layout(location = 6) in vec3 in_tangent;
 
-layout(location = 0) uniform mat4 model;
-layout(location = 1) uniform mat4 view;
-layout(location = 2) uniform mat4 projection;
-layout(location = 3) uniform sampler2D tex;
-layout(location = 4) uniform vec3 camera_position;
-layout(location = 5) uniform vec3 light_position;
-layout(location = 6) uniform samplerCube skybox;
+uniform sampler2DArray tex;
+uniform sampler2DArray normal_maps;
+uniform samplerCube irradiance_map;
+uniform samplerCube prefiltered_map;
+uniform sampler2D brdf_lut;
+uniform sampler2DArray height_maps;
+// When false the normal and height maps are ignored
+uniform bool surface_mapping;
+// True while drawing the blended faces, which are also seen from behind
+uniform bool translucent;
 
 layout(location = 0) out vec4 out_color;
 
-vec3 light_color = vec3(1., 1., 1.);
+// How deep the lowest point of a height map is, in blocks
+#define PARALLAX_DEPTH 0.06
+// How many steps the parallax occlusion mapping takes, more at grazing angles
+#define PARALLAX_MIN_STEPS 8.
+#define PARALLAX_MAX_STEPS 32.
+
+float depth_at(vec2 uv, vec2 duv_dx, vec2 duv_dy) {
+  return 1. -
+         textureGrad(height_maps, vec3(uv, in_surface_layer), duv_dx, duv_dy).r;
+}
+
+// Steps along the view direction in tangent space until it goes below the
+// height map, then interpolates between the last two steps. See
+// https://learnopengl.com/Advanced-Lighting/Parallax-Mapping
+vec2 parallax_occlusion_mapping(vec2 uv, vec3 tangent_view) {
+  // The derivatives of the original uvs, taking them inside the loop would
+  // give garbage
+  vec2 duv_dx = dFdx(uv);
+  vec2 duv_dy = dFdy(uv);
+
+  float steps =
+      mix(PARALLAX_MAX_STEPS, PARALLAX_MIN_STEPS, abs(tangent_view.z));
+  float step_depth = 1. / steps;
+  // Back faces get a flipped normal, so z is positive. Keep it away from zero
+  // at grazing angles
+  vec2 step_uv =
+      tangent_view.xy / max(tangent_view.z, 0.1) * PARALLAX_DEPTH / steps;
 
-float ambient_strength = 0.05;
+  float current_depth = 0.;
+  float depth = depth_at(uv, duv_dx, duv_dy);
+  for (float i = 0.; i < steps && current_depth < depth; i++) {
+    uv -= step_uv;
+    depth = depth_at(uv, duv_dx, duv_dy);
+    current_depth += step_depth;
+  }
 
-// http://wiki.ogre3d.org/tiki-index.php?page=-Point+Light+Attenuation
-float K_c = .1;
-float K_d = 0.0045;
-float K_q = 0.00075;
+  vec2 previous_uv = uv + step_uv;
+  float after = depth - current_depth;
+  float before =
+      depth_at(previous_uv, duv_dx, duv_dy) - current_depth + step_depth;
+  float weight = after / (after - before);
+  return mix(uv, previous_uv, weight);
+}
 
 void main() {
-  vec3 in_normal = normalize(in_normal);
+  Material material = materials[in_material];
 
-  // ***************************************************************************
-  // Ambient lighting
-  vec3 ambient_color = ambient_strength * light_color;
+  vec3 model_to_camera = normalize(camera_position.xyz - in_model_position);
 
   // ***************************************************************************
-  // Diffuse lighting
-  float diffuse_light_distance = length(light_position - in_model_position);
-  vec3 diffuse_light_direction = normalize(light_position - in_model_position);
+  // Parallax and normal mapping, in the tangent frame of the face
+  vec3 normal = normalize(gl_FrontFacing ? in_normal : -in_normal);
+  vec2 texture_uv = in_texture_uv;
+
+  if (surface_mapping) {
+    vec3 tangent = normalize(in_tangent);
+    // Points towards increasing v
+    vec3 bitangent = cross(tangent, normal);
+    mat3 tangent_frame = mat3(tangent, bitangent, normal);
+
+    texture_uv = parallax_occlusion_mapping(
+        texture_uv, transpose(tangent_frame) * model_to_camera);
+
+    vec3 tangent_normal =
+        texture(normal_maps, vec3(texture_uv, in_surface_layer)).xyz * 2. - 1.;
+    // Green points up in the image, which is towards decreasing v
+    tangent_normal.y = -tangent_normal.y;
+    normal = normalize(tangent_frame * tangent_normal);
+  }
 
-  float diffuse_light_attenuation = 1. / (K_c + K_d * diffuse_light_distance +
-                                          K_q * pow(diffuse_light_distance, 2));
-  float diffuse_intensity =
-      diffuse_light_attenuation *
-      clamp(dot(in_normal, diffuse_light_direction), 0., 1.);
-  vec3 diffuse_color = diffuse_intensity * light_color;
+  // The textures are sRGB, they are decoded to linear when sampled
+  vec4 color = texture(tex, vec3(texture_uv, in_layer));
+  vec3 albedo = color.rgb;
+  // The texels of the cutout blocks are either there or not
+  if (!translucent && color.a < 0.5) {
+    discard;
+  }
+  float n_dot_v = max(dot(normal, model_to_camera), 1e-4);
+
+  // Reflectance at normal incidence, dielectrics all get about the same
+  vec3 F0 = mix(vec3(0.04), albedo, material.metalness);
 
   // ***************************************************************************
-  // Specular lighting
-  float specular_strength = 0.5;
-  vec3 model_to_camera = normalize(camera_position - in_model_position);
-  vec3 reflect_direction = reflect(-diffuse_light_direction, in_normal);
-  float specular_intensity =
-      pow(max(dot(model_to_camera, reflect_direction), 0.0), 32);
-  vec3 specular_color = specular_strength * specular_intensity * light_color;
+  // Direct lighting from the lights reaching this fragment's tile
+  vec3 direct_color = vec3(0.);
+
+  uint offset = tile_lights_offset(gl_FragCoord.xy);
+  for (uint i = 0; i < tile_lights[offset]; i++) {
+    PointLight light = lights[tile_lights[offset + 1 + i]];
+    vec3 to_light = light.position - in_model_position;
+
+    float light_distance = length(to_light);
+    vec3 light_direction = to_light / light_distance;
+    vec3 halfway = normalize(model_to_camera + light_direction);
+    float n_dot_l = max(dot(normal, light_direction), 0.);
+
+    vec3 radiance = light.color * attenuation(light_distance) *
+                    falloff(light_distance, light.radius);
+
+    float D =
+        distribution_ggx(max(dot(normal, halfway), 0.), material.roughness);
+    float G = geometry_smith(n_dot_v, n_dot_l, direct_k(material.roughness));
+    vec3 F = fresnel_schlick(max(dot(halfway, model_to_camera), 0.), F0);
+    vec3 specular = D * G * F / max(4. * n_dot_v * n_dot_l, 1e-4);
+
+    // Metals have no diffuse reflection
+    vec3 diffuse = (1. - F) * (1. - material.metalness) * albedo / PI;
+
+    direct_color += (diffuse + specular) * radiance * n_dot_l;
+  }
 
   // ***************************************************************************
-  // Environment mapping: reflection
-  vec3 camera_to_object = in_model_position - camera_position;
-  vec3 reflected = reflect(camera_to_object, in_normal);
-  vec3 reflected_color = texture(skybox, reflected).rgb;
+  // Image based lighting from the environment
+  vec3 F = fresnel_schlick_roughness(n_dot_v, F0, material.roughness);
+
+  vec3 irradiance = texture(irradiance_map, normal).rgb;
+  vec3 ambient_diffuse =
+      (1. - F) * (1. - material.metalness) * irradiance * albedo;
+
+#if ENVIRONMENT_REFLECTIONS
+  vec3 reflected = reflect(-model_to_camera, normal);
+  vec3 prefiltered =
+      textureLod(prefiltered_map, reflected,
+                 material.roughness * (PREFILTERED_MIP_LEVELS - 1))
+          .rgb;
+  vec2 brdf = texture(brdf_lut, vec2(n_dot_v, material.roughness)).rg;
+  vec3 ambient_specular = prefiltered * (F * brdf.x + brdf.y);
+#else
+  vec3 ambient_specular = vec3(0.);
+#endif
 
   // ***************************************************************************
   // Sum up all light contributions
+  vec3 emitted_color = material.emissive * albedo;
   vec3 result =
-      (ambient_color + diffuse_color + specular_color + reflected_color) *
-      vec3(texture(tex, in_texture_uv));
+      direct_color + ambient_diffuse + ambient_specular + emitted_color;
 
   // ***************************************************************************
-  // Gamma correction
-  out_color = vec4(pow(result, vec3(1. / 2.2)), 1.0);
+  // Fade into the fog, the sky seen behind the fragment is the environment
+  vec3 sky_color = textureLod(prefiltered_map, -model_to_camera, 0.).rgb;
+  result = apply_fog(result, in_model_position, sky_color);
+
+  // Linear and unbounded, it is tone mapped and gamma corrected after post
+  // processing
+  out_color = vec4(result, translucent ? color.a : 1.);
 }
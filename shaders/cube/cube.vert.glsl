@@ -1,23 +1,32 @@
 #version 450 core
 
+#include "common.glsl"
+
 layout(location = 0) in vec3 in_position;
 layout(location = 1) in vec2 in_texture_uv;
 layout(location = 2) in vec3 in_normal;
-layout(location = 3) in vec3 in_view_offset;
-
-layout(location = 0) uniform mat4 model;
-layout(location = 1) uniform mat4 view;
-layout(location = 2) uniform mat4 projection;
+layout(location = 3) in uint in_layer;
+layout(location = 4) in uint in_surface_layer;
+layout(location = 5) in uint in_material;
+layout(location = 6) in vec3 in_tangent;
 
 layout(location = 0) out vec2 out_texture_uv;
 layout(location = 1) out vec3 out_normal;
 layout(location = 2) out vec3 out_model_position;
+layout(location = 3) flat out uint out_layer;
+layout(location = 4) flat out uint out_surface_layer;
+layout(location = 5) flat out uint out_material;
+layout(location = 6) out vec3 out_tangent;
 
 void main() {
-  vec4 model_position = model * vec4(in_position + in_view_offset.xyz, 1.0);
+  vec4 model_position = model * vec4(in_position, 1.0);
   gl_Position = projection * view * model_position;
 
   out_texture_uv = in_texture_uv;
   out_normal = in_normal;
+  out_tangent = in_tangent;
   out_model_position = vec3(model_position);
+  out_layer = in_layer;
+  out_surface_layer = in_surface_layer;
+  out_material = in_material;
 }
@@ -0,0 +1,34 @@
+// Exponential height fog, see Fog in fog.rs for the parameters in the Frame
+// block and https://iquilezles.org/articles/fog/
+
+// How much of what is at `position` is hidden by the fog
+float fog_amount(vec3 position) {
+  vec3 ray = position - camera_position.xyz;
+  float distance = length(ray);
+
+  // The density integrated along the ray, it decreases exponentially with
+  // the height
+  float rise = fog_height_falloff * ray.z;
+  float density = fog_density * exp(-fog_height_falloff *
+                                    (camera_position.z - fog_base_height));
+  float optical_depth = density * distance;
+  if (abs(rise) > 1e-4) {
+    optical_depth *= (1. - exp(-rise)) / rise;
+  }
+  float amount = 1. - exp(-optical_depth);
+
+  // Whatever the density, nothing past the end distance can be seen
+  float fade = smoothstep(FOG_FADE_START * fog_end_distance, fog_end_distance,
+                          distance);
+  return max(amount, fade);
+}
+
+// `sky_color` is the sky seen in the same direction, the fog takes its color
+// unless it hides the sky too
+vec3 apply_fog(vec3 color, vec3 position, vec3 sky_color) {
+  vec3 color_of_fog = mix(fog_color, sky_color, fog_sky);
+  return mix(color, color_of_fog, fog_amount(position));
+}
+
+// The sky is infinitely far, so it is either left alone or completely hidden
+vec3 sky_fog(vec3 sky_color) { return mix(fog_color, sky_color, fog_sky); }
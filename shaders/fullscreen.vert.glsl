@@ -0,0 +1,10 @@
+#version 450 core
+
+layout(location = 0) out vec2 out_uv;
+
+void main() {
+  // A triangle covering the whole viewport, with uvs from 0 to 1 over it
+  vec2 position = vec2((gl_VertexID << 1) & 2, gl_VertexID & 2);
+  out_uv = position;
+  gl_Position = vec4(2. * position - 1., 0., 1.);
+}
@@ -0,0 +1,43 @@
+#version 450 core
+
+#include "common.glsl"
+#include "brdf.glsl"
+
+layout(location = 0) in vec2 in_uv;
+
+layout(location = 0) out vec4 out_color;
+
+#define SAMPLE_COUNT 1024u
+
+// x is the cosine of the angle between the normal and the view, y the
+// roughness
+void main() {
+  float n_dot_v = max(in_uv.x, 1e-4);
+  float roughness = in_uv.y;
+
+  vec3 view = vec3(sqrt(1. - n_dot_v * n_dot_v), 0., n_dot_v);
+  vec3 normal = vec3(0., 0., 1.);
+
+  float scale = 0.;
+  float bias = 0.;
+  for (uint i = 0u; i < SAMPLE_COUNT; i++) {
+    vec3 halfway =
+        importance_sample_ggx(hammersley(i, SAMPLE_COUNT), normal, roughness);
+    vec3 light = normalize(2. * dot(view, halfway) * halfway - view);
+
+    float n_dot_l = max(light.z, 0.);
+    float n_dot_h = max(halfway.z, 0.);
+    float v_dot_h = max(dot(view, halfway), 0.);
+
+    if (n_dot_l > 0.) {
+      float G = geometry_smith(n_dot_v, n_dot_l, ibl_k(roughness));
+      float G_visibility = G * v_dot_h / (n_dot_h * n_dot_v);
+      float Fc = pow(1. - v_dot_h, 5.);
+
+      scale += (1. - Fc) * G_visibility;
+      bias += Fc * G_visibility;
+    }
+  }
+
+  out_color = vec4(scale / SAMPLE_COUNT, bias / SAMPLE_COUNT, 0., 1.);
+}
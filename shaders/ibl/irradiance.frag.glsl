@@ -0,0 +1,36 @@
+#version 450 core
+
+#include "common.glsl"
+
+layout(location = 0) in vec3 in_texture_uv;
+
+uniform samplerCube environment;
+
+layout(location = 0) out vec4 out_color;
+
+// The angle between two samples, in both directions
+#define SAMPLE_DELTA 0.1
+
+void main() {
+  vec3 normal = normalize(in_texture_uv);
+  vec3 up = abs(normal.z) < 0.999 ? vec3(0., 0., 1.) : vec3(1., 0., 0.);
+  vec3 right = normalize(cross(up, normal));
+  up = cross(normal, right);
+
+  // ***************************************************************************
+  // Average the environment over the hemisphere around the normal, weighted by
+  // the cosine of the angle with it
+  vec3 irradiance = vec3(0.);
+  float samples = 0.;
+  for (float phi = 0.; phi < 2. * PI; phi += SAMPLE_DELTA) {
+    for (float theta = 0.; theta < 0.5 * PI; theta += SAMPLE_DELTA) {
+      vec3 direction = sin(theta) * cos(phi) * right +
+                       sin(theta) * sin(phi) * up + cos(theta) * normal;
+      vec3 radiance = texture(environment, direction).rgb;
+      irradiance += radiance * cos(theta) * sin(theta);
+      samples++;
+    }
+  }
+
+  out_color = vec4(PI * irradiance / samples, 1.);
+}
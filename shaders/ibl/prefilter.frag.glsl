@@ -0,0 +1,37 @@
+#version 450 core
+
+#include "common.glsl"
+#include "brdf.glsl"
+
+layout(location = 0) in vec3 in_texture_uv;
+
+uniform samplerCube environment;
+uniform float roughness;
+
+layout(location = 0) out vec4 out_color;
+
+#define SAMPLE_COUNT 256u
+
+void main() {
+  // Assume that the view direction is the same as the normal and the
+  // reflection, which loses the stretched reflections at grazing angles
+  vec3 normal = normalize(in_texture_uv);
+  vec3 view = normal;
+
+  vec3 prefiltered = vec3(0.);
+  float total_weight = 0.;
+  for (uint i = 0u; i < SAMPLE_COUNT; i++) {
+    vec3 halfway =
+        importance_sample_ggx(hammersley(i, SAMPLE_COUNT), normal, roughness);
+    vec3 light = normalize(2. * dot(view, halfway) * halfway - view);
+
+    float n_dot_l = dot(normal, light);
+    if (n_dot_l > 0.) {
+      vec3 radiance = texture(environment, light).rgb;
+      prefiltered += radiance * n_dot_l;
+      total_weight += n_dot_l;
+    }
+  }
+
+  out_color = vec4(prefiltered / total_weight, 1.);
+}
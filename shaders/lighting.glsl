@@ -0,0 +1,50 @@
+// http://wiki.ogre3d.org/tiki-index.php?page=-Point+Light+Attenuation
+float K_c = .1;
+float K_d = 0.0045;
+float K_q = 0.00075;
+
+float attenuation(float light_distance) {
+  return 1. / (K_c + K_d * light_distance + K_q * pow(light_distance, 2));
+}
+
+// Smoothly brings a light to zero at its radius, so that culling it away from
+// the tiles it cannot reach does not leave a visible edge
+float falloff(float light_distance, float radius) {
+  float x = light_distance / radius;
+  float window = clamp(1. - x * x * x * x, 0., 1.);
+  return window * window;
+}
+
+// See PointLight in light.rs
+struct PointLight {
+  vec3 position;
+  float radius;
+  vec3 color;
+};
+
+layout(std430, binding = LIGHTS_BINDING) readonly buffer Lights {
+  PointLight lights[];
+};
+
+// For every TILE_SIZE x TILE_SIZE tile of the screen, in rows starting from the
+// bottom left, how many lights reach it followed by their indices in lights
+layout(std430, binding = TILE_LIGHTS_BINDING) buffer TileLights {
+  uint tile_lights[];
+};
+
+uint tile_lights_offset(vec2 pixel) {
+  uvec2 tile = uvec2(pixel) / TILE_SIZE;
+  uint tiles_x = (uint(viewport_size.x) + TILE_SIZE - 1) / TILE_SIZE;
+  return (tile.y * tiles_x + tile.x) * (MAX_LIGHTS_PER_TILE + 1);
+}
+
+// See Material in block.rs, indexed by block id
+struct Material {
+  float roughness;
+  float metalness;
+  float emissive;
+};
+
+layout(std430, binding = MATERIALS_BINDING) readonly buffer Materials {
+  Material materials[];
+};
@@ -0,0 +1,72 @@
+#version 450 core
+
+#include "common.glsl"
+#include "lighting.glsl"
+
+layout(local_size_x = TILE_SIZE, local_size_y = TILE_SIZE) in;
+
+shared uint tile_light_count;
+shared uint tile_light_indices[MAX_LIGHTS_PER_TILE];
+
+// The point of the far plane in view space which ends up on the given pixel
+vec3 far_plane_position(vec2 pixel) {
+  vec2 ndc = 2. * pixel / viewport_size - 1.;
+  vec4 position = inverse(projection) * vec4(ndc, 1., 1.);
+  return position.xyz / position.w;
+}
+
+void main() {
+  if (gl_LocalInvocationIndex == 0) {
+    tile_light_count = 0;
+  }
+  barrier();
+
+  // ***************************************************************************
+  // The side planes of the tile's frustum in view space. They all go through
+  // the camera, so the normals, pointing inside, are enough to describe them
+  vec2 tile_min = vec2(gl_WorkGroupID.xy * TILE_SIZE);
+  vec2 tile_max = tile_min + TILE_SIZE;
+  vec3 bottom_left = far_plane_position(tile_min);
+  vec3 bottom_right = far_plane_position(vec2(tile_max.x, tile_min.y));
+  vec3 top_right = far_plane_position(tile_max);
+  vec3 top_left = far_plane_position(vec2(tile_min.x, tile_max.y));
+
+  vec3 planes[4] = vec3[](normalize(cross(bottom_left, top_left)),
+                          normalize(cross(top_right, bottom_right)),
+                          normalize(cross(bottom_right, bottom_left)),
+                          normalize(cross(top_left, top_right)));
+
+  // ***************************************************************************
+  // Each invocation tests a share of the lights against the tile
+  for (uint i = gl_LocalInvocationIndex; i < light_count;
+       i += TILE_SIZE * TILE_SIZE) {
+    vec3 center = (view * vec4(lights[i].position, 1.)).xyz;
+    float radius = lights[i].radius;
+
+    // The camera looks towards -z
+    bool visible = center.z < radius;
+    for (int plane = 0; plane < 4; plane++) {
+      visible = visible && dot(planes[plane], center) > -radius;
+    }
+
+    if (visible) {
+      uint index = atomicAdd(tile_light_count, 1u);
+      if (index < MAX_LIGHTS_PER_TILE) {
+        tile_light_indices[index] = i;
+      }
+    }
+  }
+  barrier();
+
+  // ***************************************************************************
+  // Write out the tile's list
+  uint offset = tile_lights_offset(tile_min);
+  uint count = min(tile_light_count, MAX_LIGHTS_PER_TILE);
+  if (gl_LocalInvocationIndex == 0) {
+    tile_lights[offset] = count;
+  }
+  for (uint i = gl_LocalInvocationIndex; i < count;
+       i += TILE_SIZE * TILE_SIZE) {
+    tile_lights[offset + 1 + i] = tile_light_indices[i];
+  }
+}
@@ -0,0 +1,115 @@
+#version 450 core
+
+#include "common.glsl"
+
+// Decides which sections of the world mesh are drawn, by writing the indirect
+// draw command of each of them. See occlusion.rs.
+
+layout(local_size_x = CULL_WORK_GROUP_SIZE) in;
+
+// See SectionBounds in mesh.rs
+struct Section {
+  vec3 min;
+  uint first_index;
+  vec3 max;
+  uint index_count;
+  int base_vertex;
+};
+
+layout(std430, binding = SECTIONS_BINDING) readonly buffer Sections {
+  Section sections[];
+};
+
+// The layout glMultiDrawElementsIndirect expects
+struct DrawCommand {
+  uint count;
+  uint instance_count;
+  uint first_index;
+  int base_vertex;
+  uint base_instance;
+};
+
+layout(std430, binding = DRAW_COMMANDS_BINDING) writeonly buffer DrawCommands {
+  DrawCommand draw_commands[];
+};
+
+// The farthest depth of the previous frame, see hi_z.comp.glsl
+uniform sampler2D hi_z;
+// What the previous frame was drawn with
+uniform mat4 previous_view_projection;
+uniform bool frustum_culling;
+uniform bool occlusion_culling;
+
+vec3 corner(Section section, int i) {
+  return mix(section.min, section.max, vec3(bvec3(i & 1, i & 2, i & 4)));
+}
+
+// A box is outside when all of its corners are past the same clip plane
+bool in_frustum(Section section) {
+  mat4 view_projection = projection * view;
+  ivec3 below = ivec3(0);
+  ivec3 above = ivec3(0);
+  for (int i = 0; i < 8; i++) {
+    vec4 clip = view_projection * vec4(corner(section, i), 1.);
+    below += ivec3(lessThan(clip.xyz, -clip.www));
+    above += ivec3(greaterThan(clip.xyz, clip.www));
+  }
+  return !any(equal(below, ivec3(8))) && !any(equal(above, ivec3(8)));
+}
+
+// A box is hidden when its nearest point is behind everything drawn where it
+// was on the screen in the previous frame. Whatever cannot be told from the
+// previous frame is assumed to be visible.
+bool is_occluded(Section section) {
+  vec2 rect_min = vec2(1e9);
+  vec2 rect_max = vec2(-1e9);
+  float nearest = 1.;
+  for (int i = 0; i < 8; i++) {
+    vec4 clip = previous_view_projection * vec4(corner(section, i), 1.);
+    // It crossed the near plane
+    if (clip.w <= 0. || clip.z < -clip.w) {
+      return false;
+    }
+    vec3 ndc = clip.xyz / clip.w;
+    rect_min = min(rect_min, ndc.xy);
+    rect_max = max(rect_max, ndc.xy);
+    nearest = min(nearest, ndc.z * .5 + .5);
+  }
+  // It was not completely on the screen
+  if (any(lessThan(rect_min, vec2(-1.))) ||
+      any(greaterThan(rect_max, vec2(1.)))) {
+    return false;
+  }
+
+  // Pick the level at which the rectangle is at most one texel wide, it then
+  // touches at most 2x2 texels
+  vec2 uv_min = rect_min * .5 + .5;
+  vec2 uv_max = rect_max * .5 + .5;
+  vec2 extent = (uv_max - uv_min) * vec2(textureSize(hi_z, 0));
+  int level = int(ceil(log2(max(max(extent.x, extent.y), 1.))));
+  level = min(level, textureQueryLevels(hi_z) - 1);
+
+  ivec2 level_size = textureSize(hi_z, level);
+  ivec2 first = min(ivec2(uv_min * vec2(level_size)), level_size - 1);
+  ivec2 last = min(ivec2(uv_max * vec2(level_size)), level_size - 1);
+  float farthest = 0.;
+  for (int y = first.y; y <= last.y; y++) {
+    for (int x = first.x; x <= last.x; x++) {
+      farthest = max(farthest, texelFetch(hi_z, ivec2(x, y), level).r);
+    }
+  }
+  return nearest > farthest;
+}
+
+void main() {
+  uint i = gl_GlobalInvocationID.x;
+  if (i >= sections.length()) {
+    return;
+  }
+
+  Section section = sections[i];
+  bool visible = (!frustum_culling || in_frustum(section)) &&
+                 (!occlusion_culling || !is_occluded(section));
+  draw_commands[i] = DrawCommand(section.index_count, uint(visible),
+                                 section.first_index, section.base_vertex, 0);
+}
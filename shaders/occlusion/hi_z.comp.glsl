@@ -0,0 +1,37 @@
+#version 450 core
+
+// Builds one level of the hierarchical Z-buffer, each texel of which holds the
+// farthest depth of the texels of the previous level it covers. The first
+// level is a copy of the depth buffer.
+
+layout(local_size_x = HI_Z_WORK_GROUP_SIZE,
+       local_size_y = HI_Z_WORK_GROUP_SIZE) in;
+
+// Either the depth buffer or the pyramid itself
+uniform sampler2D source;
+uniform int source_level;
+layout(r32f) uniform writeonly image2D destination;
+
+void main() {
+  ivec2 texel = ivec2(gl_GlobalInvocationID.xy);
+  ivec2 destination_size = imageSize(destination);
+  if (any(greaterThanEqual(texel, destination_size))) {
+    return;
+  }
+
+  // The levels are not always exactly half the size of the previous one, so
+  // a texel can cover up to 3x3 texels of the previous level
+  ivec2 source_size = textureSize(source, source_level);
+  ivec2 first = texel * source_size / destination_size;
+  ivec2 last = ((texel + 1) * source_size + destination_size - 1) /
+                   destination_size -
+               1;
+
+  float depth = 0.;
+  for (int y = first.y; y <= last.y; y++) {
+    for (int x = first.x; x <= last.x; x++) {
+      depth = max(depth, texelFetch(source, ivec2(x, y), source_level).r);
+    }
+  }
+  imageStore(destination, texel, vec4(depth));
+}
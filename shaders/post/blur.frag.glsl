@@ -0,0 +1,25 @@
+#version 450 core
+
+layout(location = 0) in vec2 in_uv;
+
+uniform sampler2D source;
+uniform bool horizontal;
+
+layout(location = 0) out vec4 out_color;
+
+// One side of a 9 taps gaussian kernel, the center one first
+const float WEIGHTS[5] =
+    float[](0.227027, 0.1945946, 0.1216216, 0.054054, 0.016216);
+
+// A separable gaussian blur, along one axis at a time
+void main() {
+  vec2 texel = 1. / vec2(textureSize(source, 0));
+  vec2 offset = horizontal ? vec2(texel.x, 0.) : vec2(0., texel.y);
+
+  vec3 color = texture(source, in_uv).rgb * WEIGHTS[0];
+  for (int i = 1; i < 5; i++) {
+    color += texture(source, in_uv + i * offset).rgb * WEIGHTS[i];
+    color += texture(source, in_uv - i * offset).rgb * WEIGHTS[i];
+  }
+  out_color = vec4(color, 1.);
+}
@@ -0,0 +1,17 @@
+#version 450 core
+
+layout(location = 0) in vec2 in_uv;
+
+uniform sampler2D source;
+uniform float threshold;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+  vec3 color = texture(source, in_uv).rgb;
+  // Keep the part of the color above the threshold, by luminance so that the
+  // hue is kept
+  float luminance = dot(color, vec3(0.2126, 0.7152, 0.0722));
+  float bright = max(luminance - threshold, 0.) / max(luminance, 1e-4);
+  out_color = vec4(color * bright, 1.);
+}
@@ -0,0 +1,16 @@
+#version 450 core
+
+layout(location = 0) in vec2 in_uv;
+
+uniform sampler2D source;
+uniform sampler2D bloom;
+uniform float intensity;
+
+layout(location = 0) out vec4 out_color;
+
+void main() {
+  vec3 color = texture(source, in_uv).rgb;
+  // The bloom is at half resolution, the linear filtering upsamples it
+  color += intensity * texture(bloom, in_uv).rgb;
+  out_color = vec4(color, 1.);
+}
@@ -0,0 +1,35 @@
+#version 450 core
+
+layout(location = 0) in vec2 in_uv;
+
+uniform sampler2D source;
+uniform float exposure;
+uniform bool aces;
+
+layout(location = 0) out vec4 out_color;
+
+vec3 reinhard(vec3 color) { return color / (1. + color); }
+
+// The fit by Krzysztof Narkowicz, see
+// https://knarkowicz.wordpress.com/2016/01/06/aces-filmic-tone-mapping-curve/
+vec3 aces_filmic(vec3 color) {
+  const float a = 2.51;
+  const float b = 0.03;
+  const float c = 2.43;
+  const float d = 0.59;
+  const float e = 0.14;
+  return clamp((color * (a * color + b)) / (color * (c * color + d) + e), 0.,
+               1.);
+}
+
+void main() {
+  vec3 color = exposure * texture(source, in_uv).rgb;
+
+  // ***************************************************************************
+  // Tone mapping from HDR to [0, 1]
+  color = aces ? aces_filmic(color) : reinhard(color);
+
+  // ***************************************************************************
+  // Gamma correction, the window is not sRGB
+  out_color = vec4(pow(color, vec3(1. / 2.2)), 1.);
+}
@@ -0,0 +1,303 @@
+#version 450 core
+
+#include "common.glsl"
+#include "lighting.glsl"
+#include "brdf.glsl"
+#include "fog.glsl"
+
+// Draws the world without any mesh, by marching a ray through the blocks for
+// every pixel, see raymarch.rs. The shadows of the point lights and the
+// reflections are traced through the blocks too.
+
+layout(location = 0) in vec2 in_uv;
+
+// The block id of every block of the world
+uniform usampler3D blocks;
+uniform sampler2DArray tex;
+uniform samplerCube irradiance_map;
+uniform samplerCube prefiltered_map;
+uniform sampler2D brdf_lut;
+
+layout(location = 0) out vec4 out_color;
+
+// See VoxelBlock in raymarch.rs, indexed by block id
+struct VoxelBlock {
+  // In the order of FACES in block.rs
+  uint face_layers[6];
+  uint transparency;
+};
+
+layout(std430, binding = VOXEL_BLOCKS_BINDING) readonly buffer VoxelBlocks {
+  VoxelBlock voxel_blocks[];
+};
+
+#define AIR 0u
+// See Transparency in block.rs
+#define OPAQUE 0u
+#define CUTOUT 1u
+#define TRANSLUCENT 2u
+
+// Enough to cross the world diagonally up to the far plane
+#define MAX_STEPS 512
+// How many translucent blocks a ray from the camera goes through at most
+#define MAX_LAYERS 4
+// Keeps the secondary rays from hitting the block they start from
+#define SURFACE_OFFSET 1e-3
+
+struct Hit {
+  bool found;
+  ivec3 voxel;
+  uint block;
+  // Where the ray enters the block
+  vec3 position;
+  float distance;
+  vec3 normal;
+  // The index of the face in FACES
+  uint face;
+  vec2 uv;
+};
+
+// The texture coordinates of a point on a face of a block. The textures are
+// upright on the sides, like on the meshes, but can be mirrored compared to
+// them.
+vec2 face_uv(vec3 position, uint face) {
+  // From 0 to 1 across the block
+  vec3 local = fract(position + .5);
+  if (face < 2) {
+    return vec2(local.y, 1. - local.z);
+  } else if (face < 4) {
+    return vec2(local.x, 1. - local.z);
+  }
+  return local.xy;
+}
+
+vec4 albedo_at(Hit hit) {
+  uint layer = voxel_blocks[hit.block].face_layers[hit.face];
+  // There are no derivatives in the loops of the marching, sample the finest
+  // level
+  return textureLod(tex, vec3(hit.uv, layer), 0.);
+}
+
+// Marches through the blocks along the ray, up to `max_distance`, and stops at
+// the first one which is neither AIR nor `skipped`. The texels of the cutout
+// blocks which are not there let the ray through.
+Hit trace(vec3 origin, vec3 direction, float max_distance, uint skipped) {
+  Hit hit;
+  hit.found = false;
+
+  // Blocks are centered on integer coordinates, so that the block n spans
+  // from n to n + 1 here
+  vec3 start = origin + .5;
+  ivec3 size = textureSize(blocks, 0);
+  // Huge rather than infinite along the axes the ray is parallel to
+  vec3 inverse_direction =
+      1. / mix(vec3(1e-30), direction, notEqual(direction, vec3(0.)));
+
+  // Clip the ray against the world
+  vec3 t_low = -start * inverse_direction;
+  vec3 t_high = (vec3(size) - start) * inverse_direction;
+  vec3 t_near = min(t_low, t_high);
+  vec3 t_far = max(t_low, t_high);
+  float t = max(max(t_near.x, t_near.y), max(t_near.z, 0.));
+  float t_exit = min(min(t_far.x, t_far.y), min(t_far.z, max_distance));
+  if (t >= t_exit) {
+    return hit;
+  }
+
+  ivec3 voxel_step = ivec3(sign(direction));
+  ivec3 voxel = clamp(ivec3(floor(start + direction * t)), ivec3(0), size - 1);
+  vec3 t_delta = abs(inverse_direction);
+  vec3 t_max = (vec3(voxel + max(voxel_step, 0)) - start) * inverse_direction;
+  t_max = mix(vec3(1e30), t_max, notEqual(voxel_step, ivec3(0)));
+  // The axis along which the current block was entered, when the ray started
+  // outside of it
+  int axis = -1;
+  if (t > 0.) {
+    axis = t == t_near.x ? 0 : t == t_near.y ? 1 : 2;
+  }
+
+  for (int i = 0; i < MAX_STEPS && t < t_exit; i++) {
+    uint block = texelFetch(blocks, voxel, 0).r;
+    if (block != AIR && block != skipped) {
+      hit.voxel = voxel;
+      hit.block = block;
+      hit.distance = t;
+      hit.position = origin + direction * t;
+      hit.normal = vec3(0.);
+      if (axis >= 0) {
+        hit.normal[axis] = -float(voxel_step[axis]);
+        hit.face = uint(2 * axis) + (voxel_step[axis] > 0 ? 1u : 0u);
+      } else {
+        // Inside of the block from the start, face the ray
+        hit.normal = -direction;
+        hit.face = 4u;
+      }
+      hit.uv = face_uv(hit.position, hit.face);
+      if (voxel_blocks[block].transparency != CUTOUT ||
+          albedo_at(hit).a >= .5) {
+        hit.found = true;
+        return hit;
+      }
+    }
+
+    axis = t_max.x < t_max.y ? (t_max.x < t_max.z ? 0 : 2)
+                             : (t_max.y < t_max.z ? 1 : 2);
+    t = t_max[axis];
+    voxel[axis] += voxel_step[axis];
+    t_max[axis] += t_delta[axis];
+  }
+  return hit;
+}
+
+// Whether anything but translucent blocks is between the point and the light
+bool in_shadow(vec3 position, vec3 normal, PointLight light) {
+  vec3 origin = position + normal * SURFACE_OFFSET;
+  vec3 to_light = light.position - origin;
+  float light_distance = length(to_light);
+  Hit hit = trace(origin, to_light / light_distance, light_distance, AIR);
+  // The blocks emitting the light do not block it
+  return hit.found &&
+         voxel_blocks[hit.block].transparency != TRANSLUCENT &&
+         hit.voxel != ivec3(round(light.position));
+}
+
+// The light leaving the surface of a block towards `to_camera`, without the
+// reflections of the other blocks. The lights are those of the tile of the
+// pixel, which is only right for what the camera sees directly.
+vec3 shade(Hit hit, vec3 albedo, vec3 to_camera, out vec3 F) {
+  Material material = materials[hit.block];
+  vec3 normal = hit.normal;
+  float n_dot_v = max(dot(normal, to_camera), 1e-4);
+  vec3 F0 = mix(vec3(0.04), albedo, material.metalness);
+
+  vec3 direct_color = vec3(0.);
+  uint offset = tile_lights_offset(gl_FragCoord.xy);
+  for (uint i = 0; i < tile_lights[offset]; i++) {
+    PointLight light = lights[tile_lights[offset + 1 + i]];
+    vec3 to_light = light.position - hit.position;
+    float light_distance = length(to_light);
+    vec3 light_direction = to_light / light_distance;
+    float n_dot_l = max(dot(normal, light_direction), 0.);
+    if (n_dot_l == 0. || in_shadow(hit.position, normal, light)) {
+      continue;
+    }
+
+    vec3 radiance = light.color * attenuation(light_distance) *
+                    falloff(light_distance, light.radius);
+    vec3 halfway = normalize(to_camera + light_direction);
+    float D =
+        distribution_ggx(max(dot(normal, halfway), 0.), material.roughness);
+    float G = geometry_smith(n_dot_v, n_dot_l, direct_k(material.roughness));
+    vec3 F_light = fresnel_schlick(max(dot(halfway, to_camera), 0.), F0);
+    vec3 specular = D * G * F_light / max(4. * n_dot_v * n_dot_l, 1e-4);
+    vec3 diffuse = (1. - F_light) * (1. - material.metalness) * albedo / PI;
+    direct_color += (diffuse + specular) * radiance * n_dot_l;
+  }
+
+  F = fresnel_schlick_roughness(n_dot_v, F0, material.roughness);
+  vec3 irradiance = textureLod(irradiance_map, normal, 0.).rgb;
+  vec3 ambient_diffuse =
+      (1. - F) * (1. - material.metalness) * irradiance * albedo;
+  vec3 emitted_color = material.emissive * albedo;
+  return direct_color + ambient_diffuse + emitted_color;
+}
+
+// The environment seen in a direction, blurrier on rougher surfaces
+vec3 environment(vec3 direction, float roughness) {
+  return textureLod(prefiltered_map, direction,
+                    roughness * (PREFILTERED_MIP_LEVELS - 1))
+      .rgb;
+}
+
+// Shades a block seen from the camera, with what it reflects
+vec3 shade_with_reflection(Hit hit, vec3 albedo, vec3 direction) {
+  Material material = materials[hit.block];
+  vec3 to_camera = -direction;
+  vec3 F;
+  vec3 color = shade(hit, albedo, to_camera, F);
+
+#if ENVIRONMENT_REFLECTIONS
+  // One bounce, the rougher the surface the more the traced reflection fades
+  // into the blurred environment
+  vec3 reflected = reflect(direction, hit.normal);
+  vec3 blurred = environment(reflected, material.roughness);
+  vec3 reflection = blurred;
+  Hit reflected_hit = trace(hit.position + hit.normal * SURFACE_OFFSET,
+                            reflected, FAR_DISTANCE, AIR);
+  if (reflected_hit.found) {
+    vec3 reflected_albedo = albedo_at(reflected_hit).rgb;
+    vec3 unused_F;
+    vec3 traced = shade(reflected_hit, reflected_albedo, -reflected, unused_F);
+    traced = apply_fog(traced, reflected_hit.position,
+                       environment(reflected, 0.));
+    reflection = mix(traced, blurred, material.roughness);
+  }
+  float n_dot_v = max(dot(hit.normal, to_camera), 1e-4);
+  vec2 brdf = textureLod(brdf_lut, vec2(n_dot_v, material.roughness), 0.).rg;
+  color += reflection * (F * brdf.x + brdf.y);
+#endif
+
+  return color;
+}
+
+void main() {
+  vec4 far = inverse(projection * view) * vec4(in_uv * 2. - 1., 1., 1.);
+  vec3 origin = camera_position.xyz;
+  vec3 direction = normalize(far.xyz / far.w - origin);
+  float max_distance = min(fog_end_distance, FAR_DISTANCE);
+  vec3 sky_color = environment(direction, 0.);
+
+  // Looking out of a translucent block, like from under water, its inside
+  // faces are not seen
+  uint skipped = AIR;
+  ivec3 camera_voxel = ivec3(round(origin));
+  if (all(greaterThanEqual(camera_voxel, ivec3(0))) &&
+      all(lessThan(camera_voxel, textureSize(blocks, 0)))) {
+    uint block = texelFetch(blocks, camera_voxel, 0).r;
+    if (voxel_blocks[block].transparency == TRANSLUCENT) {
+      skipped = block;
+    }
+  }
+
+  // Front to back through the translucent blocks, until an opaque one
+  vec3 color = vec3(0.);
+  float transmittance = 1.;
+  float nearest = -1.;
+  float travelled = 0.;
+  for (int layer = 0; layer < MAX_LAYERS && transmittance > 0.; layer++) {
+    Hit hit = trace(origin, direction, max_distance - travelled, skipped);
+    if (!hit.found) {
+      break;
+    }
+    if (nearest < 0.) {
+      nearest = travelled + hit.distance;
+    }
+
+    vec4 albedo = albedo_at(hit);
+    bool translucent = voxel_blocks[hit.block].transparency == TRANSLUCENT;
+    float alpha = translucent ? albedo.a : 1.;
+    vec3 shaded = shade_with_reflection(hit, albedo.rgb, direction);
+    color += transmittance * alpha *
+             apply_fog(shaded, hit.position, sky_color);
+    transmittance *= 1. - alpha;
+
+    // Carry on from inside of the translucent block
+    origin = hit.position;
+    travelled += hit.distance;
+    skipped = hit.block;
+  }
+
+  // Nothing in the way of the sky, which was already drawn
+  if (nearest < 0.) {
+    discard;
+  }
+  color += transmittance * sky_fog(sky_color);
+
+  // Hides what is behind, and is hidden by what is in front, like the meshes
+  vec3 position = camera_position.xyz + direction * nearest;
+  vec4 clip = projection * view * vec4(position, 1.);
+  gl_FragDepth = clip.z / clip.w * .5 + .5;
+
+  // Linear and unbounded, see cube.frag.glsl
+  out_color = vec4(color, 1.);
+}
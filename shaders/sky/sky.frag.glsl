@@ -0,0 +1,117 @@
+#version 450 core
+
+#include "common.glsl"
+#include "fog.glsl"
+
+layout(location = 0) in vec3 in_texture_uv;
+
+layout(location = 0) out vec4 out_color;
+
+// Single scattering atmosphere, see
+// https://developer.nvidia.com/gpugems/gpugems2/part-ii-shading-lighting-and-shadows/chapter-16-accurate-atmospheric-scattering
+// and https://github.com/wwwtyro/glsl-atmosphere
+
+#define PRIMARY_STEPS 16
+#define SECONDARY_STEPS 8
+
+float sun_intensity = 22.0;
+float planet_radius = 6371e3;
+float atmosphere_radius = 6471e3;
+// The observer stands 1km above the ground
+vec3 ray_origin = vec3(0., 0., 6372e3);
+
+vec3 rayleigh_scattering = vec3(5.5e-6, 13.0e-6, 22.4e-6);
+float mie_scattering = 21e-6;
+float rayleigh_scale_height = 8e3;
+float mie_scale_height = 1.2e3;
+// Mie preferred scattering direction
+float mie_g = 0.758;
+
+// Returns the distances along the ray at which it enters and exits the sphere
+// centered at the origin with radius r, x > y if it misses
+vec2 ray_sphere_intersection(vec3 origin, vec3 direction, float r) {
+  float a = dot(direction, direction);
+  float b = 2.0 * dot(direction, origin);
+  float c = dot(origin, origin) - (r * r);
+  float d = (b * b) - 4.0 * a * c;
+  if (d < 0.0) {
+    return vec2(1e5, -1e5);
+  }
+  return vec2((-b - sqrt(d)) / (2.0 * a), (-b + sqrt(d)) / (2.0 * a));
+}
+
+vec3 atmosphere(vec3 direction, vec3 sun) {
+  vec2 p = ray_sphere_intersection(ray_origin, direction, atmosphere_radius);
+  if (p.x > p.y) {
+    return vec3(0.);
+  }
+  p.y = min(p.y, ray_sphere_intersection(ray_origin, direction, planet_radius).x);
+  float primary_step_size = (p.y - p.x) / float(PRIMARY_STEPS);
+
+  float primary_time = 0.0;
+  vec3 total_rayleigh = vec3(0.);
+  vec3 total_mie = vec3(0.);
+  float primary_optical_depth_rayleigh = 0.0;
+  float primary_optical_depth_mie = 0.0;
+
+  float mu = dot(direction, sun);
+  float mumu = mu * mu;
+  float gg = mie_g * mie_g;
+  float phase_rayleigh = 3.0 / (16.0 * PI) * (1.0 + mumu);
+  float phase_mie = 3.0 / (8.0 * PI) * ((1.0 - gg) * (mumu + 1.0)) /
+                    (pow(1.0 + gg - 2.0 * mu * mie_g, 1.5) * (2.0 + gg));
+
+  for (int i = 0; i < PRIMARY_STEPS; i++) {
+    vec3 primary_position =
+        ray_origin + direction * (primary_time + primary_step_size * 0.5);
+    float primary_height = length(primary_position) - planet_radius;
+
+    float optical_depth_rayleigh =
+        exp(-primary_height / rayleigh_scale_height) * primary_step_size;
+    float optical_depth_mie =
+        exp(-primary_height / mie_scale_height) * primary_step_size;
+    primary_optical_depth_rayleigh += optical_depth_rayleigh;
+    primary_optical_depth_mie += optical_depth_mie;
+
+    float secondary_step_size =
+        ray_sphere_intersection(primary_position, sun, atmosphere_radius).y /
+        float(SECONDARY_STEPS);
+    float secondary_time = 0.0;
+    float secondary_optical_depth_rayleigh = 0.0;
+    float secondary_optical_depth_mie = 0.0;
+
+    for (int j = 0; j < SECONDARY_STEPS; j++) {
+      vec3 secondary_position =
+          primary_position + sun * (secondary_time + secondary_step_size * 0.5);
+      float secondary_height = length(secondary_position) - planet_radius;
+      secondary_optical_depth_rayleigh +=
+          exp(-secondary_height / rayleigh_scale_height) * secondary_step_size;
+      secondary_optical_depth_mie +=
+          exp(-secondary_height / mie_scale_height) * secondary_step_size;
+      secondary_time += secondary_step_size;
+    }
+
+    vec3 attenuation = exp(
+        -(mie_scattering *
+              (primary_optical_depth_mie + secondary_optical_depth_mie) +
+          rayleigh_scattering * (primary_optical_depth_rayleigh +
+                                 secondary_optical_depth_rayleigh)));
+
+    total_rayleigh += optical_depth_rayleigh * attenuation;
+    total_mie += optical_depth_mie * attenuation;
+
+    primary_time += primary_step_size;
+  }
+
+  return sun_intensity * (phase_rayleigh * rayleigh_scattering * total_rayleigh +
+                          phase_mie * mie_scattering * total_mie);
+}
+
+void main() {
+  vec3 color =
+      atmosphere(normalize(in_texture_uv), normalize(sun_direction.xyz));
+
+  // Linear HDR radiance, the exposure and the tone mapping are applied by the
+  // post-processing like for the rest of the scene
+  out_color = vec4(sky_fog(color), 1.0);
+}
@@ -1,12 +1,14 @@
 #version 450 core
 
+#include "common.glsl"
+#include "fog.glsl"
+
 layout(location = 0) in vec3 in_texture_uv;
 
-layout(location = 0) uniform mat4 model;
-layout(location = 1) uniform mat4 view;
-layout(location = 2) uniform mat4 projection;
-layout(location = 3) uniform samplerCube skybox;
+uniform samplerCube skybox;
 
 layout(location = 0) out vec4 out_color;
 
-void main() { out_color = texture(skybox, in_texture_uv); }
+void main() {
+  out_color = vec4(sky_fog(texture(skybox, in_texture_uv).rgb), 1.);
+}
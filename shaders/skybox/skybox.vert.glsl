@@ -1,17 +1,20 @@
 #version 450 core
 
+#include "common.glsl"
+
 layout(location = 0) in vec3 in_position;
 // layout(location = 1) in vec2 in_texture_uv;
 // layout(location = 2) in vec3 in_normal;
 
-layout(location = 0) uniform mat4 model;
-layout(location = 1) uniform mat4 view;
-layout(location = 2) uniform mat4 projection;
-
 layout(location = 0) out vec3 out_texture_uv;
 
 void main() {
-  gl_Position = projection * view * model * vec4(in_position, 1.0);
+  // Only keep the rotation of the camera, so that the skybox is always
+  // centered around it. The projection is the one of the world, whose far
+  // plane would cut the cube, so put the cube on the far plane instead.
+  vec4 position =
+      projection * mat4(mat3(view)) * model * vec4(in_position, 1.0);
+  gl_Position = position.xyww;
 
   // Since the cube is centered at the origin, each one of its position
   // vectors is also a direction vector from the origin, what we need to sample
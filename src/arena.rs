@@ -0,0 +1,312 @@
+use gl::types::*;
+use std::ops::Range;
+
+use crate::buffer::{Buffer, VertexArray};
+use crate::vertex::Vertex;
+
+// Hands out ranges of elements of a buffer of `capacity` elements, first fit
+pub struct Allocator {
+    capacity: usize,
+    // Sorted, and never touching each other
+    free: Vec<Range<usize>>,
+}
+
+impl Allocator {
+    pub fn new(capacity: usize) -> Self {
+        let mut free = vec![];
+        if capacity > 0 {
+            free.push(0..capacity);
+        }
+        Self { capacity, free }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.capacity
+    }
+
+    // The offset of `len` free elements, if any range is long enough
+    pub fn allocate(&mut self, len: usize) -> Option<usize> {
+        if len == 0 {
+            return Some(0);
+        }
+        let i = self.free.iter().position(|range| range.len() >= len)?;
+        let offset = self.free[i].start;
+        self.free[i].start += len;
+        if self.free[i].is_empty() {
+            self.free.remove(i);
+        }
+        Some(offset)
+    }
+
+    // Gives back a range returned by allocate, merging it with its neighbours
+    pub fn free(&mut self, offset: usize, len: usize) {
+        if len == 0 {
+            return;
+        }
+        let i = self.free.partition_point(|range| range.start < offset);
+        debug_assert!(i == 0 || self.free[i - 1].end <= offset, "double free");
+        debug_assert!(
+            i == self.free.len() || offset + len <= self.free[i].start,
+            "double free"
+        );
+
+        let merges_previous = i > 0 && self.free[i - 1].end == offset;
+        let merges_next = i < self.free.len() && self.free[i].start == offset + len;
+        match (merges_previous, merges_next) {
+            (true, true) => {
+                self.free[i - 1].end = self.free[i].end;
+                self.free.remove(i);
+            }
+            (true, false) => self.free[i - 1].end += len,
+            (false, true) => self.free[i].start = offset,
+            (false, false) => self.free.insert(i, offset..offset + len),
+        }
+    }
+
+    pub fn free_len(&self) -> usize {
+        self.free.iter().map(|range| range.len()).sum()
+    }
+}
+
+// Where the mesh of a slot is in the arena. The indices are relative to the
+// first vertex, which is what the base vertex of indexed draws is for.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct MeshAllocation {
+    pub first_vertex: usize,
+    pub vertex_count: usize,
+    pub first_index: usize,
+    pub index_count: usize,
+}
+
+// Many indexed meshes packed into one vertex and one index buffer, so that
+// they can all be drawn by a single multi draw indirect call. Each mesh has a
+// slot, and can be replaced by a bigger or a smaller one at any time.
+pub struct MeshArena<V: Vertex> {
+    vertices: Buffer<V>,
+    indices: Buffer<GLuint>,
+    vertex_array: VertexArray,
+    vertex_allocator: Allocator,
+    index_allocator: Allocator,
+    allocations: Vec<Option<MeshAllocation>>,
+}
+
+impl<V: Vertex> MeshArena<V> {
+    pub fn new(vertex_capacity: usize, index_capacity: usize) -> Self {
+        let vertices = Buffer::new(vertex_capacity);
+        let indices = Buffer::new(index_capacity);
+        let vertex_array = VertexArray::with_vertex_buffer(&vertices);
+        vertex_array.set_element_buffer(&indices);
+        Self {
+            vertices,
+            indices,
+            vertex_array,
+            vertex_allocator: Allocator::new(vertex_capacity),
+            index_allocator: Allocator::new(index_capacity),
+            allocations: vec![],
+        }
+    }
+
+    pub fn allocation(&self, slot: usize) -> Option<MeshAllocation> {
+        self.allocations.get(slot).copied().flatten()
+    }
+
+    // Replaces the mesh of a slot. A smaller mesh stays where the previous one
+    // was, a bigger one is moved, after compacting the arena or growing it if
+    // there is no room left.
+    pub fn set(&mut self, slot: usize, vertices: &[V], indices: &[GLuint]) {
+        if slot >= self.allocations.len() {
+            self.allocations.resize(slot + 1, None);
+        }
+
+        let allocation = match self.allocations[slot] {
+            Some(previous)
+                if vertices.len() <= previous.vertex_count
+                    && indices.len() <= previous.index_count =>
+            {
+                self.vertex_allocator.free(
+                    previous.first_vertex + vertices.len(),
+                    previous.vertex_count - vertices.len(),
+                );
+                self.index_allocator.free(
+                    previous.first_index + indices.len(),
+                    previous.index_count - indices.len(),
+                );
+                MeshAllocation {
+                    vertex_count: vertices.len(),
+                    index_count: indices.len(),
+                    ..previous
+                }
+            }
+            _ => {
+                self.remove(slot);
+                self.allocate(vertices.len(), indices.len())
+            }
+        };
+
+        self.vertices.write(allocation.first_vertex, vertices);
+        self.indices.write(allocation.first_index, indices);
+        self.allocations[slot] = Some(allocation);
+    }
+
+    pub fn remove(&mut self, slot: usize) {
+        if let Some(allocation) = self.allocations.get_mut(slot).and_then(Option::take) {
+            self.vertex_allocator
+                .free(allocation.first_vertex, allocation.vertex_count);
+            self.index_allocator
+                .free(allocation.first_index, allocation.index_count);
+        }
+    }
+
+    fn allocate(&mut self, vertex_count: usize, index_count: usize) -> MeshAllocation {
+        if let Some(allocation) = self.try_allocate(vertex_count, index_count) {
+            return allocation;
+        }
+
+        // Only grow when compacting would not leave enough room, by at least
+        // half of the current size so that growing one mesh at a time does
+        // not copy everything every time
+        let vertex_capacity = self.vertex_allocator.capacity();
+        let index_capacity = self.index_allocator.capacity();
+        let vertex_capacity = if self.vertex_allocator.free_len() < vertex_count {
+            (vertex_capacity - self.vertex_allocator.free_len() + vertex_count)
+                .max(vertex_capacity + vertex_capacity / 2)
+        } else {
+            vertex_capacity
+        };
+        let index_capacity = if self.index_allocator.free_len() < index_count {
+            (index_capacity - self.index_allocator.free_len() + index_count)
+                .max(index_capacity + index_capacity / 2)
+        } else {
+            index_capacity
+        };
+        self.relocate(vertex_capacity, index_capacity);
+
+        self.try_allocate(vertex_count, index_count)
+            .expect("there is room after compacting the arena")
+    }
+
+    fn try_allocate(&mut self, vertex_count: usize, index_count: usize) -> Option<MeshAllocation> {
+        let first_vertex = self.vertex_allocator.allocate(vertex_count)?;
+        let first_index = match self.index_allocator.allocate(index_count) {
+            Some(first_index) => first_index,
+            None => {
+                self.vertex_allocator.free(first_vertex, vertex_count);
+                return None;
+            }
+        };
+        Some(MeshAllocation {
+            first_vertex,
+            vertex_count,
+            first_index,
+            index_count,
+        })
+    }
+
+    // Moves every mesh, in order, to the start of new buffers of the given
+    // capacities, copying them on the GPU. This also removes the holes left
+    // between them.
+    fn relocate(&mut self, vertex_capacity: usize, index_capacity: usize) {
+        let vertices = Buffer::new(vertex_capacity);
+        let indices = Buffer::new(index_capacity);
+        let mut vertex_allocator = Allocator::new(vertex_capacity);
+        let mut index_allocator = Allocator::new(index_capacity);
+
+        let mut slots: Vec<usize> = (0..self.allocations.len())
+            .filter(|&slot| self.allocations[slot].is_some())
+            .collect();
+        slots.sort_by_key(|&slot| self.allocations[slot].unwrap().first_vertex);
+        for slot in slots {
+            let previous = self.allocations[slot].unwrap();
+            let allocation = MeshAllocation {
+                first_vertex: vertex_allocator.allocate(previous.vertex_count).unwrap(),
+                first_index: index_allocator.allocate(previous.index_count).unwrap(),
+                ..previous
+            };
+            self.vertices.copy_to(
+                &vertices,
+                previous.first_vertex,
+                allocation.first_vertex,
+                allocation.vertex_count,
+            );
+            self.indices.copy_to(
+                &indices,
+                previous.first_index,
+                allocation.first_index,
+                allocation.index_count,
+            );
+            self.allocations[slot] = Some(allocation);
+        }
+
+        // The vertex array has to point to the new buffers
+        self.vertex_array = VertexArray::with_vertex_buffer(&vertices);
+        self.vertex_array.set_element_buffer(&indices);
+        self.vertices = vertices;
+        self.indices = indices;
+        self.vertex_allocator = vertex_allocator;
+        self.index_allocator = index_allocator;
+    }
+
+    pub fn bind(&self) {
+        self.vertex_array.bind();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn allocates_one_after_the_other() {
+        let mut allocator = Allocator::new(10);
+        assert_eq!(allocator.allocate(3), Some(0));
+        assert_eq!(allocator.allocate(4), Some(3));
+        assert_eq!(allocator.allocate(3), Some(7));
+        assert_eq!(allocator.allocate(1), None);
+        assert_eq!(allocator.free_len(), 0);
+    }
+
+    #[test]
+    fn empty_allocations_take_no_room() {
+        let mut allocator = Allocator::new(0);
+        assert_eq!(allocator.allocate(0), Some(0));
+        allocator.free(0, 0);
+        assert_eq!(allocator.allocate(1), None);
+    }
+
+    #[test]
+    fn reuses_the_first_hole_big_enough() {
+        let mut allocator = Allocator::new(10);
+        let a = allocator.allocate(2).unwrap();
+        let _b = allocator.allocate(2).unwrap();
+        let c = allocator.allocate(4).unwrap();
+        allocator.free(a, 2);
+        allocator.free(c, 4);
+        // Too big for the first hole
+        assert_eq!(allocator.allocate(3), Some(4));
+        assert_eq!(allocator.allocate(2), Some(0));
+        assert_eq!(allocator.allocate(3), Some(7));
+    }
+
+    #[test]
+    fn merges_freed_neighbours() {
+        let mut allocator = Allocator::new(9);
+        let ranges: Vec<usize> = (0..3).map(|_| allocator.allocate(3).unwrap()).collect();
+        // Free the ends first, then the middle which joins them
+        allocator.free(ranges[0], 3);
+        allocator.free(ranges[2], 3);
+        assert_eq!(allocator.allocate(4), None);
+        allocator.free(ranges[1], 3);
+        assert_eq!(allocator.free.len(), 1);
+        assert_eq!(allocator.free[0], 0..9);
+        assert_eq!(allocator.allocate(9), Some(0));
+    }
+
+    #[test]
+    fn shrinking_frees_the_tail() {
+        let mut allocator = Allocator::new(8);
+        let a = allocator.allocate(6).unwrap();
+        allocator.free(a + 4, 2);
+        assert_eq!(allocator.free.len(), 1);
+        assert_eq!(allocator.free[0], 4..8);
+    }
+}
@@ -0,0 +1,395 @@
+use gl::types::*;
+use noise::{Fbm, MultiFractal, NoiseFn, Seedable};
+
+use crate::chunk::{
+    CHUNK_X_SIZE, CHUNK_Y_SIZE, CHUNK_Z_SIZE, COBBLESTONE, DIRT, GRASS, SAND, SNOW,
+};
+use crate::decoration::{Decoration, Feature};
+
+// Above this height the ground is covered with snow, whatever the biome
+const SNOW_LINE: GLuint = 40;
+
+// The seeds of the noise fields, the detail is the one the terrain always had
+const DETAIL_SEED: u32 = 0;
+const TEMPERATURE_SEED: u32 = 1;
+const HUMIDITY_SEED: u32 = 2;
+
+// The climate changes over a few chunks, the ground over a few blocks. The
+// noises are sampled at the world coordinates divided by the size of a chunk.
+const CLIMATE_FREQUENCY: f64 = 0.75;
+const DETAIL_FREQUENCY: f64 = 5.;
+
+// Mixed into the hash of the columns picking their biome
+const BIOME_SALT: u64 = 0;
+
+// How far apart two climates are for their biomes to blend, see weights
+const BLEND_DISTANCE: f64 = 0.15;
+
+pub struct Biome {
+    // Where it sits in the plane of (temperature, humidity), whose noises are
+    // mostly between -0.7 and 0.7
+    climate: [f64; 2],
+    // The height of the ground for a value of the detail noise, which is
+    // mostly between -1 and 1
+    height: fn(f64) -> f64,
+    // How many blocks the 3D noise moves the ground up or down, see Terrain
+    overhang: f64,
+    // The top block of the columns, then the blocks under it down to
+    // subsurface_depth, then cobblestone
+    pub surface: GLuint,
+    pub subsurface: GLuint,
+    pub subsurface_depth: GLuint,
+    // The top block of the columns under water
+    pub underwater: GLuint,
+    // Tried in order on every column, the first one chosen is placed
+    pub decorations: &'static [Decoration],
+}
+
+pub const BIOMES: [Biome; 5] = [
+    // Plains
+    Biome {
+        climate: [0., 0.],
+        height: |detail| 13. + 4. * detail,
+        overhang: 2.,
+        surface: GRASS,
+        subsurface: DIRT,
+        subsurface_depth: 3,
+        underwater: DIRT,
+        decorations: &[
+            Decoration {
+                feature: Feature::Structure("ruin"),
+                chance: 1. / 20000.,
+            },
+            Decoration {
+                feature: Feature::Tree,
+                chance: 1. / 200.,
+            },
+            Decoration {
+                feature: Feature::Boulder,
+                chance: 1. / 1500.,
+            },
+            Decoration {
+                feature: Feature::OreVein,
+                chance: 1. / 300.,
+            },
+            Decoration {
+                feature: Feature::Glowstone,
+                chance: 1. / 64.,
+            },
+            Decoration {
+                feature: Feature::Shrub,
+                chance: 1. / 32.,
+            },
+        ],
+    },
+    // Desert
+    Biome {
+        climate: [0.5, -0.4],
+        height: |detail| 14. + 3. * detail,
+        overhang: 1.5,
+        surface: SAND,
+        subsurface: SAND,
+        subsurface_depth: 5,
+        underwater: SAND,
+        decorations: &[
+            Decoration {
+                feature: Feature::Structure("tower"),
+                chance: 1. / 30000.,
+            },
+            Decoration {
+                feature: Feature::Boulder,
+                chance: 1. / 2000.,
+            },
+            Decoration {
+                feature: Feature::OreVein,
+                chance: 1. / 300.,
+            },
+            Decoration {
+                feature: Feature::Glowstone,
+                chance: 1. / 128.,
+            },
+        ],
+    },
+    // Mountains
+    Biome {
+        climate: [-0.1, -0.5],
+        height: |detail| 18. + 40. * detail.abs(),
+        overhang: 8.,
+        surface: COBBLESTONE,
+        subsurface: COBBLESTONE,
+        subsurface_depth: 0,
+        underwater: COBBLESTONE,
+        decorations: &[
+            Decoration {
+                feature: Feature::Structure("tower"),
+                chance: 1. / 20000.,
+            },
+            Decoration {
+                feature: Feature::Boulder,
+                chance: 1. / 400.,
+            },
+            Decoration {
+                feature: Feature::OreVein,
+                chance: 1. / 100.,
+            },
+            Decoration {
+                feature: Feature::Glowstone,
+                chance: 1. / 96.,
+            },
+        ],
+    },
+    // Tundra
+    Biome {
+        climate: [-0.5, 0.],
+        height: |detail| 14. + 5. * detail,
+        overhang: 3.,
+        surface: SNOW,
+        subsurface: DIRT,
+        subsurface_depth: 3,
+        underwater: DIRT,
+        decorations: &[
+            Decoration {
+                feature: Feature::Structure("ruin"),
+                chance: 1. / 30000.,
+            },
+            Decoration {
+                feature: Feature::Tree,
+                chance: 1. / 300.,
+            },
+            Decoration {
+                feature: Feature::OreVein,
+                chance: 1. / 300.,
+            },
+            Decoration {
+                feature: Feature::Glowstone,
+                chance: 1. / 64.,
+            },
+        ],
+    },
+    // Ocean
+    Biome {
+        climate: [0.1, 0.5],
+        height: |detail| 6. + 3. * detail,
+        overhang: 1.,
+        surface: SAND,
+        subsurface: SAND,
+        subsurface_depth: 2,
+        underwater: SAND,
+        decorations: &[
+            Decoration {
+                feature: Feature::OreVein,
+                chance: 1. / 300.,
+            },
+            Decoration {
+                feature: Feature::Glowstone,
+                chance: 1. / 64.,
+            },
+        ],
+    },
+];
+
+// How much each biome counts at a climate, adding up to 1. They fall off
+// smoothly with the distance to the climate of the biome, so that the
+// terrain blends from one biome to the next.
+fn weights(climate: [f64; 2]) -> [f64; BIOMES.len()] {
+    let mut weights = [0.; BIOMES.len()];
+    for (weight, biome) in weights.iter_mut().zip(BIOMES.iter()) {
+        let (dt, dh) = (climate[0] - biome.climate[0], climate[1] - biome.climate[1]);
+        *weight = (-(dt * dt + dh * dh) / (BLEND_DISTANCE * BLEND_DISTANCE)).exp();
+    }
+    let total: f64 = weights.iter().sum();
+    // Far from every biome the weights underflow, the closest one wins
+    if total == 0. {
+        let closest = closest_biome(climate);
+        weights[closest] = 1.;
+        return weights;
+    }
+    for weight in weights.iter_mut() {
+        *weight /= total;
+    }
+    weights
+}
+
+fn closest_biome(climate: [f64; 2]) -> usize {
+    let distance = |biome: &Biome| {
+        (climate[0] - biome.climate[0]).powi(2) + (climate[1] - biome.climate[1]).powi(2)
+    };
+    (0..BIOMES.len())
+        .min_by(|&a, &b| {
+            distance(&BIOMES[a])
+                .partial_cmp(&distance(&BIOMES[b]))
+                .unwrap()
+        })
+        .unwrap()
+}
+
+// Looks random, the same for a column and a salt every time
+pub fn column_hash(x: GLuint, y: GLuint, salt: u64) -> u64 {
+    let mut hash = (u64::from(x) << 32 | u64::from(y)) ^ salt.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    // The finalizer of MurmurHash3
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xff51_afd7_ed55_8ccd);
+    hash ^= hash >> 33;
+    hash = hash.wrapping_mul(0xc4ce_b9fe_1a85_ec53);
+    hash ^= hash >> 33;
+    hash
+}
+
+// Same as column_hash, from 0 to 1
+pub fn column_random(x: GLuint, y: GLuint, salt: u64) -> f64 {
+    (column_hash(x, y, salt) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+pub struct Column {
+    // The number of blocks of ground, the top one is at height - 1
+    pub height: GLuint,
+    // Blended like the height
+    pub overhang: f64,
+    pub biome: &'static Biome,
+}
+
+impl Column {
+    // The block of the ground at `z`, `depth` blocks under the surface
+    pub fn block(&self, z: GLuint, depth: GLuint, underwater: bool) -> GLuint {
+        if depth == 0 {
+            if underwater {
+                self.biome.underwater
+            } else if z >= SNOW_LINE {
+                SNOW
+            } else {
+                self.biome.surface
+            }
+        } else if depth <= self.biome.subsurface_depth {
+            self.biome.subsurface
+        } else {
+            COBBLESTONE
+        }
+    }
+}
+
+// The noise fields the terrain is made from. They are sampled at world
+// coordinates, so the chunks continue each other.
+pub struct BiomeMap {
+    temperature: Fbm,
+    humidity: Fbm,
+    detail: Fbm,
+}
+
+impl BiomeMap {
+    pub fn new() -> Self {
+        Self {
+            temperature: Fbm::new()
+                .set_seed(TEMPERATURE_SEED)
+                .set_frequency(CLIMATE_FREQUENCY),
+            humidity: Fbm::new()
+                .set_seed(HUMIDITY_SEED)
+                .set_frequency(CLIMATE_FREQUENCY),
+            detail: Fbm::new()
+                .set_seed(DETAIL_SEED)
+                .set_frequency(DETAIL_FREQUENCY),
+        }
+    }
+
+    // The column at the given world coordinates. Its height blends those of
+    // the biomes around, its blocks come from one of them, picked at random in
+    // proportion to their weights, which frays the borders between biomes.
+    pub fn column(&self, x: GLuint, y: GLuint) -> Column {
+        let point = [
+            x as f64 / CHUNK_X_SIZE as f64,
+            y as f64 / CHUNK_Y_SIZE as f64,
+        ];
+        let climate = [self.temperature.get(point), self.humidity.get(point)];
+        let detail = self.detail.get(point);
+
+        let weights = weights(climate);
+        let blend = |property: &dyn Fn(&Biome) -> f64| -> f64 {
+            weights
+                .iter()
+                .zip(BIOMES.iter())
+                .map(|(weight, biome)| weight * property(biome))
+                .sum()
+        };
+        let height = blend(&|biome| (biome.height)(detail));
+
+        let mut pick = column_random(x, y, BIOME_SALT);
+        let mut biome = closest_biome(climate);
+        for (i, weight) in weights.iter().enumerate() {
+            if pick < *weight {
+                biome = i;
+                break;
+            }
+            pick -= weight;
+        }
+
+        Column {
+            height: (height.round() as GLuint).clamp(1, CHUNK_Z_SIZE - 1),
+            overhang: blend(&|biome| biome.overhang),
+            biome: &BIOMES[biome],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn weights_add_up_to_one() {
+        for &climate in [[0., 0.], [0.3, -0.2], [-0.7, 0.7], [5., 5.]].iter() {
+            let total: f64 = weights(climate).iter().sum();
+            assert!((total - 1.).abs() < 1e-9, "{:?}", climate);
+        }
+    }
+
+    #[test]
+    fn biomes_win_at_their_own_climate() {
+        for (i, biome) in BIOMES.iter().enumerate() {
+            let weights = weights(biome.climate);
+            assert!(weights[i] > 0.9, "{}: {:?}", i, weights);
+            assert_eq!(closest_biome(biome.climate), i);
+        }
+    }
+
+    #[test]
+    fn heights_blend_between_biomes() {
+        // Halfway between the plains and the ocean both count the same
+        let (plains, ocean) = (&BIOMES[0], &BIOMES[4]);
+        let middle = [
+            (plains.climate[0] + ocean.climate[0]) / 2.,
+            (plains.climate[1] + ocean.climate[1]) / 2.,
+        ];
+        let weights = weights(middle);
+        assert!((weights[0] - weights[4]).abs() < 1e-9);
+        assert!(weights[0] > 0.4);
+    }
+
+    #[test]
+    fn columns_are_the_same_every_time() {
+        let (a, b) = (BiomeMap::new(), BiomeMap::new());
+        for i in 0..64 {
+            let (x, y) = (i * 37 % 500, i * 91 % 500);
+            let (column_a, column_b) = (a.column(x, y), b.column(x, y));
+            assert_eq!(column_a.height, column_b.height);
+            assert!(std::ptr::eq(column_a.biome, column_b.biome));
+        }
+    }
+
+    #[test]
+    fn columns_are_topped_by_their_surface() {
+        let column = Column {
+            height: 10,
+            overhang: 0.,
+            biome: &BIOMES[0],
+        };
+        assert_eq!(column.block(9, 0, false), GRASS);
+        assert_eq!(column.block(9, 0, true), DIRT);
+        assert_eq!(column.block(6, 3, false), DIRT);
+        assert_eq!(column.block(5, 4, false), COBBLESTONE);
+        let peak = Column {
+            height: SNOW_LINE + 1,
+            overhang: 0.,
+            biome: &BIOMES[2],
+        };
+        assert_eq!(peak.block(SNOW_LINE, 0, false), SNOW);
+    }
+}
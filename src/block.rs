@@ -0,0 +1,486 @@
+use gl::types::*;
+
+use crate::chunk::{
+    AIR, COBBLESTONE, DIRT, GLASS, GLOWSTONE, GOLD_ORE, GRASS, LEAVES, LOG, SAND, SNOW,
+    STAINED_GLASS, WATER,
+};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Face {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    // Top
+    PositiveZ,
+    // Bottom
+    NegativeZ,
+}
+
+pub const FACES: [Face; 6] = [
+    Face::PositiveX,
+    Face::NegativeX,
+    Face::PositiveY,
+    Face::NegativeY,
+    Face::PositiveZ,
+    Face::NegativeZ,
+];
+
+impl Face {
+    pub fn normal(self) -> [GLint; 3] {
+        match self {
+            Face::PositiveX => [1, 0, 0],
+            Face::NegativeX => [-1, 0, 0],
+            Face::PositiveY => [0, 1, 0],
+            Face::NegativeY => [0, -1, 0],
+            Face::PositiveZ => [0, 0, 1],
+            Face::NegativeZ => [0, 0, -1],
+        }
+    }
+}
+
+// Texture file names, relative to textures/
+pub struct FaceTextures {
+    pub top: &'static str,
+    pub side: &'static str,
+    pub bottom: &'static str,
+}
+
+impl FaceTextures {
+    fn file(&self, face: Face) -> &'static str {
+        match face {
+            Face::PositiveZ => self.top,
+            Face::NegativeZ => self.bottom,
+            _ => self.side,
+        }
+    }
+}
+
+// A point light at the center of the block, see light.rs
+#[derive(Debug, Copy, Clone)]
+pub struct BlockLight {
+    pub color: [GLfloat; 3],
+    pub radius: GLfloat,
+}
+
+// How the alpha of the textures is used
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Transparency {
+    // Hides whatever is behind it
+    Opaque,
+    // The texels are either fully opaque or discarded, so it is drawn along
+    // with the opaque blocks
+    Cutout,
+    // Blended over what is behind it, drawn after the opaque blocks from back
+    // to front
+    Translucent,
+}
+
+// The fog seen from inside a fluid block, see fog.rs
+#[derive(Debug, Copy, Clone)]
+pub struct FluidFog {
+    pub color: [GLfloat; 3],
+    pub density: GLfloat,
+}
+
+// The shader storage buffer binding of the materials, passed to the shaders as
+// a define
+pub const MATERIALS_BINDING: GLuint = 3;
+
+// Parameters of the physically based shading, the albedo comes from the
+// textures. Follows the std430 layout of Material in shaders/lighting.glsl
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct Material {
+    pub roughness: GLfloat,
+    pub metalness: GLfloat,
+    // How much of the albedo is emitted regardless of the lighting
+    pub emissive: GLfloat,
+}
+
+pub struct BlockDefinition {
+    pub name: &'static str,
+    // None for blocks which are never drawn
+    pub textures: Option<FaceTextures>,
+    // Tangent space normal maps, derived from the height maps when None
+    pub normal_maps: Option<FaceTextures>,
+    // Used for the parallax mapping, white is the outer surface of the block.
+    // None for flat faces
+    pub height_maps: Option<FaceTextures>,
+    pub material: Material,
+    pub transparency: Transparency,
+    // None for blocks which do not emit light
+    pub light: Option<BlockLight>,
+    // None for solid blocks
+    pub fluid: Option<FluidFog>,
+}
+
+// Indexed by block id
+pub const BLOCKS: [BlockDefinition; 13] = [
+    BlockDefinition {
+        name: "air",
+        textures: None,
+        normal_maps: None,
+        height_maps: None,
+        material: Material {
+            roughness: 1.,
+            metalness: 0.,
+            emissive: 0.,
+        },
+        // Never drawn, but everything behind it can be seen
+        transparency: Transparency::Translucent,
+        light: None,
+        fluid: None,
+    },
+    BlockDefinition {
+        name: "cobblestone",
+        textures: Some(FaceTextures {
+            top: "mossy_cobblestone.png",
+            side: "mossy_cobblestone.png",
+            bottom: "mossy_cobblestone.png",
+        }),
+        normal_maps: Some(FaceTextures {
+            top: "mossy_cobblestone_normal.png",
+            side: "mossy_cobblestone_normal.png",
+            bottom: "mossy_cobblestone_normal.png",
+        }),
+        height_maps: Some(FaceTextures {
+            top: "mossy_cobblestone_height.png",
+            side: "mossy_cobblestone_height.png",
+            bottom: "mossy_cobblestone_height.png",
+        }),
+        material: Material {
+            roughness: 0.85,
+            metalness: 0.,
+            emissive: 0.,
+        },
+        transparency: Transparency::Opaque,
+        light: None,
+        fluid: None,
+    },
+    BlockDefinition {
+        name: "grass",
+        textures: Some(FaceTextures {
+            top: "grass_top.png",
+            side: "grass_side.png",
+            bottom: "dirt.png",
+        }),
+        normal_maps: None,
+        height_maps: None,
+        material: Material {
+            roughness: 0.9,
+            metalness: 0.,
+            emissive: 0.,
+        },
+        transparency: Transparency::Opaque,
+        light: None,
+        fluid: None,
+    },
+    BlockDefinition {
+        name: "dirt",
+        textures: Some(FaceTextures {
+            top: "dirt.png",
+            side: "dirt.png",
+            bottom: "dirt.png",
+        }),
+        normal_maps: None,
+        height_maps: Some(FaceTextures {
+            top: "dirt_height.png",
+            side: "dirt_height.png",
+            bottom: "dirt_height.png",
+        }),
+        material: Material {
+            roughness: 0.95,
+            metalness: 0.,
+            emissive: 0.,
+        },
+        transparency: Transparency::Opaque,
+        light: None,
+        fluid: None,
+    },
+    BlockDefinition {
+        name: "glowstone",
+        textures: Some(FaceTextures {
+            top: "glowstone.png",
+            side: "glowstone.png",
+            bottom: "glowstone.png",
+        }),
+        normal_maps: None,
+        height_maps: None,
+        material: Material {
+            roughness: 0.4,
+            metalness: 0.,
+            emissive: 1.,
+        },
+        transparency: Transparency::Opaque,
+        light: Some(BlockLight {
+            color: [0.6, 0.45, 0.25],
+            radius: 12.,
+        }),
+        fluid: None,
+    },
+    BlockDefinition {
+        name: "water",
+        textures: Some(FaceTextures {
+            top: "water.png",
+            side: "water.png",
+            bottom: "water.png",
+        }),
+        normal_maps: None,
+        height_maps: None,
+        material: Material {
+            roughness: 0.1,
+            metalness: 0.,
+            emissive: 0.,
+        },
+        transparency: Transparency::Translucent,
+        light: None,
+        fluid: Some(FluidFog {
+            color: [0.02, 0.08, 0.12],
+            density: 0.15,
+        }),
+    },
+    BlockDefinition {
+        name: "glass",
+        textures: Some(FaceTextures {
+            top: "glass.png",
+            side: "glass.png",
+            bottom: "glass.png",
+        }),
+        normal_maps: None,
+        height_maps: None,
+        material: Material {
+            roughness: 0.05,
+            metalness: 0.,
+            emissive: 0.,
+        },
+        transparency: Transparency::Cutout,
+        light: None,
+        fluid: None,
+    },
+    BlockDefinition {
+        name: "leaves",
+        textures: Some(FaceTextures {
+            top: "leaves.png",
+            side: "leaves.png",
+            bottom: "leaves.png",
+        }),
+        normal_maps: None,
+        height_maps: None,
+        material: Material {
+            roughness: 0.8,
+            metalness: 0.,
+            emissive: 0.,
+        },
+        transparency: Transparency::Cutout,
+        light: None,
+        fluid: None,
+    },
+    BlockDefinition {
+        name: "stained_glass",
+        textures: Some(FaceTextures {
+            top: "stained_glass.png",
+            side: "stained_glass.png",
+            bottom: "stained_glass.png",
+        }),
+        normal_maps: None,
+        height_maps: None,
+        material: Material {
+            roughness: 0.05,
+            metalness: 0.,
+            emissive: 0.,
+        },
+        transparency: Transparency::Translucent,
+        light: None,
+        fluid: None,
+    },
+    BlockDefinition {
+        name: "sand",
+        textures: Some(FaceTextures {
+            top: "sand.png",
+            side: "sand.png",
+            bottom: "sand.png",
+        }),
+        normal_maps: None,
+        height_maps: None,
+        material: Material {
+            roughness: 0.95,
+            metalness: 0.,
+            emissive: 0.,
+        },
+        transparency: Transparency::Opaque,
+        light: None,
+        fluid: None,
+    },
+    // Dirt covered with snow, like grass
+    BlockDefinition {
+        name: "snow",
+        textures: Some(FaceTextures {
+            top: "snow.png",
+            side: "snow_side.png",
+            bottom: "dirt.png",
+        }),
+        normal_maps: None,
+        height_maps: None,
+        material: Material {
+            roughness: 0.7,
+            metalness: 0.,
+            emissive: 0.,
+        },
+        transparency: Transparency::Opaque,
+        light: None,
+        fluid: None,
+    },
+    BlockDefinition {
+        name: "log",
+        textures: Some(FaceTextures {
+            top: "log_top.png",
+            side: "log_side.png",
+            bottom: "log_top.png",
+        }),
+        normal_maps: None,
+        height_maps: None,
+        material: Material {
+            roughness: 0.85,
+            metalness: 0.,
+            emissive: 0.,
+        },
+        transparency: Transparency::Opaque,
+        light: None,
+        fluid: None,
+    },
+    // The flecks of gold make the whole block a little metallic
+    BlockDefinition {
+        name: "gold_ore",
+        textures: Some(FaceTextures {
+            top: "gold_ore.png",
+            side: "gold_ore.png",
+            bottom: "gold_ore.png",
+        }),
+        normal_maps: None,
+        height_maps: None,
+        material: Material {
+            roughness: 0.6,
+            metalness: 0.3,
+            emissive: 0.,
+        },
+        transparency: Transparency::Opaque,
+        light: None,
+        fluid: None,
+    },
+];
+
+// Returns the index of `file` in `files`, adding it at the end if missing
+fn layer_of<T: PartialEq>(files: &mut Vec<T>, file: T) -> GLuint {
+    match files.iter().position(|f| *f == file) {
+        Some(layer) => layer as GLuint,
+        None => {
+            files.push(file);
+            (files.len() - 1) as GLuint
+        }
+    }
+}
+
+// The normal and height map files of a face
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct SurfaceFiles {
+    pub normal_map: Option<&'static str>,
+    pub height_map: Option<&'static str>,
+}
+
+// Assigns a texture array layer to every distinct texture used by the blocks,
+// and a surface layer, shared by the normal and height maps, to every
+// distinct pair of them
+pub struct BlockRegistry {
+    texture_files: Vec<&'static str>,
+    face_layers: Vec<[GLuint; 6]>,
+    surface_files: Vec<SurfaceFiles>,
+    face_surface_layers: Vec<[GLuint; 6]>,
+}
+
+impl BlockRegistry {
+    pub fn new() -> Self {
+        debug_assert_eq!(BLOCKS[AIR as usize].name, "air");
+        debug_assert_eq!(BLOCKS[COBBLESTONE as usize].name, "cobblestone");
+        debug_assert_eq!(BLOCKS[GRASS as usize].name, "grass");
+        debug_assert_eq!(BLOCKS[DIRT as usize].name, "dirt");
+        debug_assert_eq!(BLOCKS[GLOWSTONE as usize].name, "glowstone");
+        debug_assert_eq!(BLOCKS[WATER as usize].name, "water");
+        debug_assert_eq!(BLOCKS[GLASS as usize].name, "glass");
+        debug_assert_eq!(BLOCKS[LEAVES as usize].name, "leaves");
+        debug_assert_eq!(BLOCKS[STAINED_GLASS as usize].name, "stained_glass");
+        debug_assert_eq!(BLOCKS[SAND as usize].name, "sand");
+        debug_assert_eq!(BLOCKS[SNOW as usize].name, "snow");
+        debug_assert_eq!(BLOCKS[LOG as usize].name, "log");
+        debug_assert_eq!(BLOCKS[GOLD_ORE as usize].name, "gold_ore");
+
+        let mut texture_files = vec![];
+        // The first surface layer is flat, used by the faces without any map
+        let flat = SurfaceFiles {
+            normal_map: None,
+            height_map: None,
+        };
+        let mut surface_files = vec![flat];
+
+        let mut face_layers = Vec::with_capacity(BLOCKS.len());
+        let mut face_surface_layers = Vec::with_capacity(BLOCKS.len());
+        for block in BLOCKS.iter() {
+            let mut layers = [0; 6];
+            let mut surface_layers = [0; 6];
+            for (i, &face) in FACES.iter().enumerate() {
+                if let Some(textures) = &block.textures {
+                    layers[i] = layer_of(&mut texture_files, textures.file(face));
+                }
+                let surface = SurfaceFiles {
+                    normal_map: block.normal_maps.as_ref().map(|maps| maps.file(face)),
+                    height_map: block.height_maps.as_ref().map(|maps| maps.file(face)),
+                };
+                surface_layers[i] = layer_of(&mut surface_files, surface);
+            }
+            face_layers.push(layers);
+            face_surface_layers.push(surface_layers);
+        }
+
+        Self {
+            texture_files,
+            face_layers,
+            surface_files,
+            face_surface_layers,
+        }
+    }
+
+    // The texture file of each layer, in order
+    pub fn texture_files(&self) -> &[&'static str] {
+        &self.texture_files
+    }
+
+    // The normal and height map files of each surface layer, in order
+    pub fn surface_files(&self) -> &[SurfaceFiles] {
+        &self.surface_files
+    }
+
+    pub fn face_layers(&self, block: GLuint) -> [GLuint; 6] {
+        self.face_layers[block as usize]
+    }
+
+    pub fn face_surface_layers(&self, block: GLuint) -> [GLuint; 6] {
+        self.face_surface_layers[block as usize]
+    }
+
+    // Indexed by block id, to be uploaded to the Materials buffer
+    pub fn materials(&self) -> Vec<Material> {
+        BLOCKS.iter().map(|block| block.material).collect()
+    }
+
+    pub fn light(&self, block: GLuint) -> Option<BlockLight> {
+        BLOCKS[block as usize].light
+    }
+
+    pub fn transparency(&self, block: GLuint) -> Transparency {
+        BLOCKS[block as usize].transparency
+    }
+
+    pub fn fluid(&self, block: GLuint) -> Option<FluidFog> {
+        BLOCKS[block as usize].fluid
+    }
+}
@@ -0,0 +1,160 @@
+use gl::types::*;
+use std::marker::PhantomData;
+
+use crate::vertex::Vertex;
+
+// A GL buffer holding up to `capacity` elements of type T, deleted on drop
+pub struct Buffer<T: Copy> {
+    id: GLuint,
+    capacity: usize,
+    len: usize,
+    _data: PhantomData<T>,
+}
+
+impl<T: Copy> Buffer<T> {
+    pub fn new(capacity: usize) -> Self {
+        let mut id = 0;
+        unsafe {
+            gl::CreateBuffers(1, &mut id);
+            gl::NamedBufferData(
+                id,
+                (capacity * std::mem::size_of::<T>()) as GLsizeiptr,
+                std::ptr::null(),
+                gl::DYNAMIC_DRAW,
+            );
+        }
+        Self {
+            id,
+            capacity,
+            len: 0,
+            _data: PhantomData,
+        }
+    }
+
+    pub fn from_slice(data: &[T]) -> Self {
+        let mut buffer = Self::new(data.len());
+        buffer.upload(data);
+        buffer
+    }
+
+    pub fn id(&self) -> GLuint {
+        self.id
+    }
+
+    // The number of elements written by the last upload
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    // Replaces the contents of the buffer, growing its storage if needed. The
+    // name of the buffer stays the same, so vertex arrays do not need to be
+    // set up again.
+    pub fn upload(&mut self, data: &[T]) {
+        if data.len() > self.capacity {
+            // Leave some room to avoid reallocating every time it grows a bit
+            self.capacity = data.len() + data.len() / 2;
+            unsafe {
+                gl::NamedBufferData(
+                    self.id,
+                    (self.capacity * std::mem::size_of::<T>()) as GLsizeiptr,
+                    std::ptr::null(),
+                    gl::DYNAMIC_DRAW,
+                )
+            };
+        }
+        self.write(0, data);
+        self.len = data.len();
+    }
+
+    // Overwrites the elements starting at `offset`, which must fit in the
+    // current storage
+    pub fn write(&self, offset: usize, data: &[T]) {
+        assert!(
+            offset + data.len() <= self.capacity,
+            "writing elements {}..{} of a buffer of {} elements",
+            offset,
+            offset + data.len(),
+            self.capacity
+        );
+        if data.is_empty() {
+            return;
+        }
+        unsafe {
+            gl::NamedBufferSubData(
+                self.id,
+                (offset * std::mem::size_of::<T>()) as GLintptr,
+                std::mem::size_of_val(data) as GLsizeiptr,
+                data.as_ptr() as *const GLvoid,
+            )
+        };
+    }
+
+    // Copies `len` elements starting at `offset` to `destination_offset` in
+    // another buffer, without going through the CPU
+    pub fn copy_to(
+        &self,
+        destination: &Buffer<T>,
+        offset: usize,
+        destination_offset: usize,
+        len: usize,
+    ) {
+        assert!(offset + len <= self.capacity && destination_offset + len <= destination.capacity);
+        if len == 0 {
+            return;
+        }
+        let size = std::mem::size_of::<T>();
+        unsafe {
+            gl::CopyNamedBufferSubData(
+                self.id,
+                destination.id,
+                (offset * size) as GLintptr,
+                (destination_offset * size) as GLintptr,
+                (len * size) as GLsizeiptr,
+            )
+        };
+    }
+
+    pub fn bind_base(&self, target: GLenum, binding: GLuint) {
+        unsafe { gl::BindBufferBase(target, binding, self.id) };
+    }
+}
+
+impl<T: Copy> Drop for Buffer<T> {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteBuffers(1, &self.id) };
+    }
+}
+
+pub struct VertexArray {
+    id: GLuint,
+}
+
+impl VertexArray {
+    pub fn new() -> Self {
+        let mut id = 0;
+        unsafe { gl::CreateVertexArrays(1, &mut id) };
+        Self { id }
+    }
+
+    // Sources the attributes of V from `buffer`, as described by V::ATTRIBUTES
+    pub fn with_vertex_buffer<V: Vertex>(buffer: &Buffer<V>) -> Self {
+        let vertex_array = Self::new();
+        V::vertex_specification(vertex_array.id, buffer.id());
+        vertex_array
+    }
+
+    // Indexed draws read their indices from `buffer`
+    pub fn set_element_buffer(&self, buffer: &Buffer<GLuint>) {
+        unsafe { gl::VertexArrayElementBuffer(self.id, buffer.id()) };
+    }
+
+    pub fn bind(&self) {
+        unsafe { gl::BindVertexArray(self.id) };
+    }
+}
+
+impl Drop for VertexArray {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteVertexArrays(1, &self.id) };
+    }
+}
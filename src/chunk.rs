@@ -1,8 +1,21 @@
 use gl::types::*;
-use noise::{Fbm, MultiFractal, NoiseFn};
+
+use crate::decoration::{decorate, Structures};
+use crate::terrain::Terrain;
 
 pub const AIR: GLuint = 0;
 pub const COBBLESTONE: GLuint = 1;
+pub const GRASS: GLuint = 2;
+pub const DIRT: GLuint = 3;
+pub const GLOWSTONE: GLuint = 4;
+pub const WATER: GLuint = 5;
+pub const GLASS: GLuint = 6;
+pub const LEAVES: GLuint = 7;
+pub const STAINED_GLASS: GLuint = 8;
+pub const SAND: GLuint = 9;
+pub const SNOW: GLuint = 10;
+pub const LOG: GLuint = 11;
+pub const GOLD_ORE: GLuint = 12;
 
 pub struct Chunk {
     pub blocks: Vec<GLuint>,
@@ -14,34 +27,44 @@ pub const CHUNK_Z_SIZE: GLuint = 128;
 pub const CHUNK_BLOCKS: usize = (CHUNK_X_SIZE * CHUNK_Y_SIZE * CHUNK_Z_SIZE) as usize;
 pub const CHUNK_AREA: usize = (CHUNK_X_SIZE * CHUNK_Y_SIZE) as usize;
 
-const BASE_HEIGHT: GLuint = 10;
+// Columns of transparent blocks standing on the ground of the first chunk, in
+// front of the starting camera, as (x, y, block)
+const SHOWCASE: [(GLuint, GLuint, GLuint); 3] =
+    [(1, 8, GLASS), (3, 8, STAINED_GLASS), (5, 8, LEAVES)];
+const SHOWCASE_HEIGHT: GLuint = 3;
 
 impl Chunk {
-    // TODO(andrea): make this much much cooler.
-    // See: Perlin noise, Simplex noise, Value noise, Gradient noise, fractional Brownian Motion
-    // Support multiple chunks, and make terrain generation consistent
-    pub fn new() -> Self {
-        let mut blocks: Vec<GLuint> = vec![AIR; CHUNK_BLOCKS];
-        let mut height: Vec<GLuint> = vec![BASE_HEIGHT; CHUNK_AREA];
+    // The chunk at (chunk_x, chunk_y) in the grid of chunks of the world, its
+    // terrain and features continue the ones of its neighbours
+    pub fn new(chunk_x: GLuint, chunk_y: GLuint, structures: &Structures) -> Self {
+        Self::generate([chunk_x * CHUNK_X_SIZE, chunk_y * CHUNK_Y_SIZE], structures)
+    }
 
-        let fbm = Fbm::new().set_frequency(5.);
+    // The blocks of the size of a chunk starting at the given world
+    // coordinates, which can be anywhere
+    fn generate(origin: [GLuint; 2], structures: &Structures) -> Self {
+        let mut blocks: Vec<GLuint> = vec![AIR; CHUNK_BLOCKS];
 
-        // Create a half-air, half-cobblestone chunk
+        let terrain = Terrain::new();
+        let mut columns = Vec::with_capacity(CHUNK_AREA);
+        let mut column_blocks = vec![AIR; CHUNK_Z_SIZE as usize];
         for y in 0..CHUNK_Y_SIZE {
             for x in 0..CHUNK_X_SIZE {
-                height[(y * CHUNK_X_SIZE + x) as usize] += (10.
-                    * fbm.get([
-                        (x as f64 / CHUNK_X_SIZE as f64),
-                        (y as f64 / CHUNK_Y_SIZE as f64),
-                    ])) as GLuint;
+                columns.push(terrain.column(origin[0] + x, origin[1] + y, &mut column_blocks));
+                for (z, &block) in column_blocks.iter().enumerate() {
+                    blocks[z * CHUNK_AREA + (y * CHUNK_X_SIZE + x) as usize] = block;
+                }
             }
         }
 
-        for y in 0..CHUNK_Y_SIZE {
-            for x in 0..CHUNK_X_SIZE {
-                for z in 0..height[(y * CHUNK_X_SIZE + x) as usize] {
+        decorate(origin, &mut blocks, &columns, &terrain, structures);
+
+        if origin == [0, 0] {
+            for &(x, y, block) in SHOWCASE.iter() {
+                let ground = columns[(y * CHUNK_X_SIZE + x) as usize].height;
+                for z in ground..ground + SHOWCASE_HEIGHT {
                     blocks[(z * CHUNK_Y_SIZE * CHUNK_X_SIZE + y * CHUNK_X_SIZE + x) as usize] =
-                        COBBLESTONE;
+                        block;
                 }
             }
         }
@@ -54,3 +77,46 @@ impl Chunk {
         self.blocks[(z * CHUNK_Y_SIZE * CHUNK_X_SIZE + y * CHUNK_X_SIZE + x) as usize]
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_are_the_same_every_time() {
+        let structures = Structures::new();
+        assert!(Chunk::new(1, 2, &structures).blocks == Chunk::new(1, 2, &structures).blocks);
+    }
+
+    #[test]
+    fn chunks_continue_across_their_borders() {
+        let structures = Structures::new();
+        let chunk = Chunk::new(0, 0, &structures);
+        let east = Chunk::new(1, 0, &structures);
+        let north = Chunk::new(0, 1, &structures);
+        // Half in each of the chunks along x, then along y
+        for &origin in [[CHUNK_X_SIZE / 2, 0], [0, CHUNK_Y_SIZE / 2]].iter() {
+            let straddling = Chunk::generate(origin, &structures);
+            for z in 0..CHUNK_Z_SIZE {
+                for y in 0..CHUNK_Y_SIZE {
+                    for x in 0..CHUNK_X_SIZE {
+                        let (world_x, world_y) = (origin[0] + x, origin[1] + y);
+                        let neighbour = match (world_x / CHUNK_X_SIZE, world_y / CHUNK_Y_SIZE) {
+                            (0, 0) => &chunk,
+                            (1, 0) => &east,
+                            _ => &north,
+                        };
+                        assert_eq!(
+                            straddling.get(x, y, z),
+                            neighbour.get(world_x % CHUNK_X_SIZE, world_y % CHUNK_Y_SIZE, z),
+                            "{} {} {}",
+                            world_x,
+                            world_y,
+                            z
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
@@ -3,5 +3,25 @@ pub const MOUSE_SENSITIVITY: f32 = 0.1;
 pub const INITIAL_WIDTH: u32 = 1920;
 pub const INITIAL_HEIGHT: u32 = 1920;
 pub const NEAR_DISTANCE: f32 = 0.1;
-pub const FAR_DISTANCE: f32 = 128.;
+pub const FAR_DISTANCE: f32 = 256.;
 
+// How many rays are cast through the view to find the visible blocks, when
+// the world is not drawn from its meshes. Can be changed with
+// --raycast-resolution=WIDTHxHEIGHT
+pub const RAYCAST_RESOLUTION: (u32, u32) = (320, 180);
+
+pub const ENVIRONMENT_REFLECTIONS: bool = true;
+
+// How steep the normal maps derived from height maps are
+pub const NORMAL_MAP_STRENGTH: f32 = 2.;
+
+// How much the exposure is multiplied or divided by at each key press
+pub const EXPOSURE_STEP: f32 = 1.25;
+
+// Exponential height fog, its density is FOG_DENSITY per block at
+// FOG_BASE_HEIGHT and decreases going up. It also thickens from
+// FOG_FADE_START * FAR_DISTANCE to FAR_DISTANCE, where nothing is visible.
+pub const FOG_DENSITY: f32 = 0.01;
+pub const FOG_HEIGHT_FALLOFF: f32 = 0.1;
+pub const FOG_BASE_HEIGHT: f32 = 10.;
+pub const FOG_FADE_START: f32 = 0.75;
@@ -0,0 +1,529 @@
+use gl::types::*;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use std::collections::HashMap;
+
+use crate::biome::{column_hash, column_random, Column, BIOMES};
+use crate::block::BLOCKS;
+use crate::chunk::{
+    AIR, CHUNK_X_SIZE, CHUNK_Y_SIZE, CHUNK_Z_SIZE, COBBLESTONE, GLOWSTONE, GOLD_ORE, LEAVES, LOG,
+    WATER,
+};
+use crate::resource_pack::ResourcePacks;
+use crate::terrain::{Terrain, WATER_LEVEL};
+use crate::vox::VoxModel;
+
+// What can grow on the ground of a biome, see Biome::decorations
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Feature {
+    // A light source
+    Glowstone,
+    // A single block of leaves, only above water
+    Shrub,
+    // A trunk of logs topped by leaves, only above water
+    Tree,
+    // A lump of cobblestone half buried in the ground
+    Boulder,
+    // Gold ore winding through the cobblestone deep under the column
+    OreVein,
+    // A prefab standing on the ground, only above water. Named after its file
+    // in structures/, without the .vox
+    Structure(&'static str),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Decoration {
+    pub feature: Feature,
+    // Of each column of the biome
+    pub chance: f64,
+}
+
+// Mixed into the hashes of the columns deciding which ones are decorated, and
+// seeding the shapes of their features
+const DECORATION_SALT: u64 = 1;
+const FEATURE_SALT: u64 = 2;
+
+// How far from their column the features reach, the structures aside
+const FEATURE_REACH: GLuint = 6;
+
+// The blocks of trunk, the top one being inside of the leaves
+const TREE_HEIGHT: (GLint, GLint) = (4, 7);
+const LEAVES_RADIUS: GLint = 2;
+const BOULDER_RADIUS: (f64, f64) = (1.2, 2.5);
+const ORE_VEIN_SIZE: GLuint = 6;
+// How many blocks of ground are left at least above the ore veins
+const ORE_DEPTH: GLint = 8;
+// How far down the ground is filled under the structures
+const FOUNDATION_DEPTH: GLint = 8;
+
+fn is_open(block: GLuint) -> bool {
+    block == AIR || block == WATER
+}
+
+fn is_open_or_leaves(block: GLuint) -> bool {
+    is_open(block) || block == LEAVES
+}
+
+fn is_stone(block: GLuint) -> bool {
+    block == COBBLESTONE
+}
+
+fn is_anything(_: GLuint) -> bool {
+    true
+}
+
+// The blocks of the chunk being decorated. The features are placed in world
+// coordinates, their blocks outside of the chunk being left to the neighbours,
+// which place the same features.
+struct ChunkView<'a> {
+    origin: [GLint; 2],
+    blocks: &'a mut [GLuint],
+}
+
+impl<'a> ChunkView<'a> {
+    fn index(&self, position: [GLint; 3]) -> Option<usize> {
+        let (x, y, z) = (
+            position[0] - self.origin[0],
+            position[1] - self.origin[1],
+            position[2],
+        );
+        if x < 0
+            || y < 0
+            || z < 0
+            || x >= CHUNK_X_SIZE as GLint
+            || y >= CHUNK_Y_SIZE as GLint
+            || z >= CHUNK_Z_SIZE as GLint
+        {
+            return None;
+        }
+        Some(((z * CHUNK_Y_SIZE as GLint + y) * CHUNK_X_SIZE as GLint + x) as usize)
+    }
+
+    fn get(&self, position: [GLint; 3]) -> Option<GLuint> {
+        self.index(position).map(|index| self.blocks[index])
+    }
+
+    // Only when the block already there is one that `replaces` accepts
+    fn set(&mut self, position: [GLint; 3], block: GLuint, replaces: fn(GLuint) -> bool) {
+        if let Some(index) = self.index(position) {
+            if replaces(self.blocks[index]) {
+                self.blocks[index] = block;
+            }
+        }
+    }
+}
+
+pub struct Structure {
+    size: [u32; 3],
+    // AIR where the model is empty
+    blocks: Vec<GLuint>,
+}
+
+impl Structure {
+    // The color indices of the model are block ids
+    pub fn from_vox(model: &VoxModel) -> Result<Self, String> {
+        let [x_size, y_size, z_size] = model.size;
+        let volume = x_size
+            .checked_mul(y_size)
+            .and_then(|area| area.checked_mul(z_size))
+            .ok_or_else(|| format!("model of size {:?} is too large", model.size))?;
+        let mut blocks = vec![AIR; volume as usize];
+        for voxel in model.voxels.iter() {
+            if usize::from(voxel.color_index) >= BLOCKS.len() {
+                return Err(format!("color index {} is not a block", voxel.color_index));
+            }
+            let [x, y, z] = voxel.position;
+            blocks[((u32::from(z) * y_size + u32::from(y)) * x_size + u32::from(x)) as usize] =
+                GLuint::from(voxel.color_index);
+        }
+        Ok(Self {
+            size: model.size,
+            blocks,
+        })
+    }
+
+    fn get(&self, x: u32, y: u32, z: u32) -> GLuint {
+        self.blocks[((z * self.size[1] + y) * self.size[0] + x) as usize]
+    }
+
+    // Centered on `ground`, the bottom layer replacing it. The empty blocks of
+    // the model carve the terrain, and the ground is filled up to the bottom
+    // layer.
+    fn place(&self, view: &mut ChunkView, ground: [GLint; 3]) {
+        let min = [
+            ground[0] - (self.size[0] / 2) as GLint,
+            ground[1] - (self.size[1] / 2) as GLint,
+            ground[2] - 1,
+        ];
+        for z in 0..self.size[2] {
+            for y in 0..self.size[1] {
+                for x in 0..self.size[0] {
+                    let position = [
+                        min[0] + x as GLint,
+                        min[1] + y as GLint,
+                        min[2] + z as GLint,
+                    ];
+                    view.set(position, self.get(x, y, z), is_anything);
+                }
+            }
+        }
+
+        for y in 0..self.size[1] {
+            for x in 0..self.size[0] {
+                if self.get(x, y, 0) == AIR {
+                    continue;
+                }
+                for depth in 1..=FOUNDATION_DEPTH {
+                    let position = [min[0] + x as GLint, min[1] + y as GLint, min[2] - depth];
+                    match view.get(position) {
+                        Some(block) if is_open(block) => view.set(position, COBBLESTONE, is_open),
+                        _ => break,
+                    }
+                }
+            }
+        }
+    }
+}
+
+// The prefabs placed by Feature::Structure, by name
+pub struct Structures {
+    structures: HashMap<&'static str, Structure>,
+}
+
+impl Structures {
+    pub fn new() -> Self {
+        Self {
+            structures: HashMap::new(),
+        }
+    }
+
+    // Loads structures/<name>.vox for every structure found in the biomes
+    pub fn load(resource_packs: &ResourcePacks) -> Result<Self, String> {
+        let mut structures = Self::new();
+        for biome in BIOMES.iter() {
+            for decoration in biome.decorations.iter() {
+                if let Feature::Structure(name) = decoration.feature {
+                    if structures.structures.contains_key(name) {
+                        continue;
+                    }
+                    let path = format!("structures/{}.vox", name);
+                    let structure = VoxModel::parse(&resource_packs.read(&path)?)
+                        .and_then(|model| Structure::from_vox(&model))
+                        .map_err(|e| format!("{}: {}", path, e))?;
+                    structures.insert(name, structure);
+                }
+            }
+        }
+        Ok(structures)
+    }
+
+    pub fn insert(&mut self, name: &'static str, structure: Structure) {
+        self.structures.insert(name, structure);
+    }
+
+    // How far from their column the features reach
+    fn reach(&self) -> GLuint {
+        self.structures
+            .values()
+            .map(|structure| structure.size[0].max(structure.size[1]) / 2 + 1)
+            .fold(FEATURE_REACH, GLuint::max)
+    }
+}
+
+// Places `feature` on the column whose first block above the ground is
+// `ground`, shaping it with `rng`
+fn place(
+    view: &mut ChunkView,
+    feature: Feature,
+    ground: [GLint; 3],
+    rng: &mut StdRng,
+    structures: &Structures,
+) {
+    let [x, y, z] = ground;
+    let dry = z >= WATER_LEVEL as GLint;
+    match feature {
+        Feature::Glowstone => view.set(ground, GLOWSTONE, is_open),
+        Feature::Shrub if dry => view.set(ground, LEAVES, is_open),
+        Feature::Tree if dry => {
+            let height = rng.gen_range(TREE_HEIGHT.0, TREE_HEIGHT.1);
+            for dz in -LEAVES_RADIUS..=1 {
+                for dy in -LEAVES_RADIUS..=LEAVES_RADIUS {
+                    for dx in -LEAVES_RADIUS..=LEAVES_RADIUS {
+                        // Rounded, with a few of the corners missing
+                        let corner = dx.abs() == LEAVES_RADIUS && dy.abs() == LEAVES_RADIUS;
+                        let top = dz == 1;
+                        if (top && dx.abs() + dy.abs() > 1) || (corner && rng.gen_bool(0.5)) {
+                            continue;
+                        }
+                        view.set([x + dx, y + dy, z + height - 1 + dz], LEAVES, is_open);
+                    }
+                }
+            }
+            for dz in 0..height {
+                view.set([x, y, z + dz], LOG, is_open_or_leaves);
+            }
+        }
+        Feature::Boulder => {
+            let radius = rng.gen_range(BOULDER_RADIUS.0, BOULDER_RADIUS.1);
+            let extent = radius.ceil() as GLint;
+            for dz in -extent..=extent {
+                for dy in -extent..=extent {
+                    for dx in -extent..=extent {
+                        // A little flattened
+                        let distance = f64::from(dx * dx + dy * dy) + f64::from(dz * dz) * 1.5;
+                        if distance <= radius * radius {
+                            view.set([x + dx, y + dy, z + dz], COBBLESTONE, is_open);
+                        }
+                    }
+                }
+            }
+        }
+        Feature::OreVein if z > ORE_DEPTH + 1 => {
+            let mut position = [x, y, rng.gen_range(1, z - ORE_DEPTH)];
+            for _ in 0..ORE_VEIN_SIZE {
+                view.set(position, GOLD_ORE, is_stone);
+                position[rng.gen_range(0, 3)] += if rng.gen_bool(0.5) { 1 } else { -1 };
+            }
+        }
+        Feature::Structure(name) if dry => {
+            if let Some(structure) = structures.structures.get(name) {
+                structure.place(view, ground);
+            }
+        }
+        _ => {}
+    }
+}
+
+// Places the features of the columns of the chunk starting at `origin`, and
+// of the columns around it reaching into it. Whether a column has a feature,
+// and its shape, only depend on the world coordinates of the column, so that
+// the neighbouring chunks place the same ones. `columns` are those of the
+// chunk, row by row.
+pub fn decorate(
+    origin: [GLuint; 2],
+    blocks: &mut [GLuint],
+    columns: &[Column],
+    terrain: &Terrain,
+    structures: &Structures,
+) {
+    let reach = structures.reach() as GLint;
+    let origin = [origin[0] as GLint, origin[1] as GLint];
+    let mut view = ChunkView { origin, blocks };
+    let mut column_blocks = vec![AIR; CHUNK_Z_SIZE as usize];
+
+    // Row by row over the world, so that the overlapping features are placed
+    // in the same order by every chunk
+    for y in (origin[1] - reach).max(0)..origin[1] + CHUNK_Y_SIZE as GLint + reach {
+        for x in (origin[0] - reach).max(0)..origin[0] + CHUNK_X_SIZE as GLint + reach {
+            let (world_x, world_y) = (x as GLuint, y as GLuint);
+            let (local_x, local_y) = (x - origin[0], y - origin[1]);
+            let column = if local_x >= 0
+                && local_y >= 0
+                && local_x < CHUNK_X_SIZE as GLint
+                && local_y < CHUNK_Y_SIZE as GLint
+            {
+                Some(&columns[(local_y * CHUNK_X_SIZE as GLint + local_x) as usize])
+            } else {
+                None
+            };
+
+            let biome = match column {
+                Some(column) => column.biome,
+                None => terrain.biome(world_x, world_y),
+            };
+            let decoration = biome
+                .decorations
+                .iter()
+                .enumerate()
+                .find(|(i, decoration)| {
+                    column_random(world_x, world_y, DECORATION_SALT + *i as u64) < decoration.chance
+                });
+            let feature = match decoration {
+                Some((_, decoration)) => decoration.feature,
+                None => continue,
+            };
+
+            let height = match column {
+                Some(column) => column.height,
+                None => terrain.column(world_x, world_y, &mut column_blocks).height,
+            };
+            let mut rng = StdRng::seed_from_u64(column_hash(world_x, world_y, FEATURE_SALT));
+            place(
+                &mut view,
+                feature,
+                [x, y, height as GLint],
+                &mut rng,
+                structures,
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::CHUNK_BLOCKS;
+    use crate::resource_pack::{base_pack_path, ResourcePack};
+
+    // A hollow box of cobblestone with a glowstone in the middle of its floor
+    fn hut() -> Structure {
+        let size = [5, 5, 4];
+        let mut blocks = vec![AIR; 5 * 5 * 4];
+        for z in 0..4 {
+            for y in 0..5 {
+                for x in 0..5 {
+                    if z == 0 || x == 0 || x == 4 || y == 0 || y == 4 {
+                        blocks[(z * 5 + y) * 5 + x] = COBBLESTONE;
+                    }
+                }
+            }
+        }
+        // In the middle of the floor
+        blocks[2 * 5 + 2] = GLOWSTONE;
+        Structure { size, blocks }
+    }
+
+    // A flat ground of cobblestone up to `height`
+    fn flat(height: GLuint) -> Vec<GLuint> {
+        let mut blocks = vec![AIR; CHUNK_BLOCKS];
+        for block in
+            blocks[..height as usize * CHUNK_X_SIZE as usize * CHUNK_Y_SIZE as usize].iter_mut()
+        {
+            *block = COBBLESTONE;
+        }
+        blocks
+    }
+
+    fn place_in(origin: [GLint; 2], feature: Feature, ground: [GLint; 3]) -> Vec<GLuint> {
+        let mut structures = Structures::new();
+        structures.insert("hut", hut());
+        let mut blocks = flat(ground[2] as GLuint);
+        let mut view = ChunkView {
+            origin,
+            blocks: &mut blocks,
+        };
+        let mut rng = StdRng::seed_from_u64(7);
+        place(&mut view, feature, ground, &mut rng, &structures);
+        blocks
+    }
+
+    fn block_at(blocks: &[GLuint], origin: [GLint; 2], position: [GLint; 3]) -> GLuint {
+        let (x, y, z) = (
+            position[0] - origin[0],
+            position[1] - origin[1],
+            position[2],
+        );
+        blocks[((z * CHUNK_Y_SIZE as GLint + y) * CHUNK_X_SIZE as GLint + x) as usize]
+    }
+
+    #[test]
+    fn features_span_chunk_borders() {
+        let size = CHUNK_X_SIZE as GLint;
+        // On the border between two chunks, and in a third one straddling it
+        let origins = [[0, 0], [size, 0], [size / 2, 0]];
+        let ground = [size, 20, 20];
+        for &feature in [
+            Feature::Tree,
+            Feature::Boulder,
+            Feature::OreVein,
+            Feature::Structure("hut"),
+        ]
+        .iter()
+        {
+            let chunks: Vec<Vec<GLuint>> = origins
+                .iter()
+                .map(|&origin| place_in(origin, feature, ground))
+                .collect();
+            let mut placed = 0;
+            for z in 0..40 {
+                for y in 10..30 {
+                    for x in size - 10..size + 10 {
+                        let position = [x, y, z];
+                        let side = if x < size { 0 } else { 1 };
+                        let block = block_at(&chunks[side], origins[side], position);
+                        assert_eq!(
+                            block,
+                            block_at(&chunks[2], origins[2], position),
+                            "{:?} {:?}",
+                            feature,
+                            position
+                        );
+                        let ground_block = if z < ground[2] { COBBLESTONE } else { AIR };
+                        placed += (block != ground_block) as usize;
+                    }
+                }
+            }
+            assert!(placed > 0, "{:?}", feature);
+        }
+    }
+
+    #[test]
+    fn structures_stand_on_foundations() {
+        let origin = [0, 0];
+        let mut structures = Structures::new();
+        structures.insert("hut", hut());
+        // Half of the hut hangs over a hole
+        let mut blocks = flat(20);
+        let mut view = ChunkView {
+            origin,
+            blocks: &mut blocks,
+        };
+        for z in 15..20 {
+            for y in 0..40 {
+                for x in 40..60 {
+                    view.set([x, y, z], AIR, is_anything);
+                }
+            }
+        }
+        let mut rng = StdRng::seed_from_u64(0);
+        place(
+            &mut view,
+            Feature::Structure("hut"),
+            [40, 20, 20],
+            &mut rng,
+            &structures,
+        );
+
+        assert_eq!(view.get([40, 20, 19]), Some(GLOWSTONE));
+        assert_eq!(view.get([40, 20, 21]), Some(AIR));
+        assert_eq!(view.get([42, 20, 22]), Some(COBBLESTONE));
+        assert_eq!(view.get([42, 20, 15]), Some(COBBLESTONE));
+        assert_eq!(view.get([43, 20, 15]), Some(AIR));
+    }
+
+    #[test]
+    fn loads_the_structures_of_the_biomes() {
+        let base = ResourcePack::open(&base_pack_path()).unwrap();
+        let structures = Structures::load(&ResourcePacks::new(base)).unwrap();
+        assert!(structures.structures.contains_key("ruin"));
+        assert!(structures.structures.contains_key("tower"));
+    }
+
+    #[test]
+    fn structures_are_loaded_from_vox_models() {
+        let model = VoxModel {
+            size: [1, 1, 2],
+            voxels: vec![crate::vox::Voxel {
+                position: [0, 0, 1],
+                color_index: GLOWSTONE as u8,
+            }],
+        };
+        let structure = Structure::from_vox(&model).unwrap();
+        assert_eq!(structure.get(0, 0, 0), AIR);
+        assert_eq!(structure.get(0, 0, 1), GLOWSTONE);
+
+        let model = VoxModel {
+            size: [1, 1, 1],
+            voxels: vec![crate::vox::Voxel {
+                position: [0, 0, 0],
+                color_index: 255,
+            }],
+        };
+        assert!(Structure::from_vox(&model).is_err());
+
+        let model = VoxModel {
+            size: [1 << 16, 1 << 16, 2],
+            voxels: vec![],
+        };
+        assert!(Structure::from_vox(&model).is_err());
+    }
+}
@@ -0,0 +1,77 @@
+use gl::types::*;
+
+use crate::block::{BlockRegistry, FluidFog};
+use crate::constants::*;
+use crate::world::World;
+
+// Follows the std140 layout of the fog members of the Frame block in
+// shaders/common.glsl, see shaders/fog.glsl for how they are used
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct Fog {
+    color: [GLfloat; 3],
+    // Per block at base_height
+    density: GLfloat,
+    // How quickly the density decreases going up from base_height
+    height_falloff: GLfloat,
+    base_height: GLfloat,
+    // Everything is completely fogged past this distance, which hides where
+    // the world ends
+    end_distance: GLfloat,
+    // 1 to blend towards the sky behind the fog rather than towards its color
+    sky: GLfloat,
+}
+
+impl Fog {
+    // The default of the frame uniforms, used when baking the sky
+    pub fn none() -> Self {
+        Self {
+            color: [0., 0., 0.],
+            density: 0.,
+            height_falloff: 0.,
+            base_height: 0.,
+            end_distance: GLfloat::MAX,
+            sky: 1.,
+        }
+    }
+
+    // Thin fog lying on the terrain, fading into the sky
+    pub fn distance() -> Self {
+        Self {
+            color: [0., 0., 0.],
+            density: FOG_DENSITY,
+            height_falloff: FOG_HEIGHT_FALLOFF,
+            base_height: FOG_BASE_HEIGHT,
+            end_distance: FAR_DISTANCE,
+            sky: 1.,
+        }
+    }
+
+    // Dense fog of a uniform color, which also hides the sky
+    pub fn fluid(fluid: &FluidFog) -> Self {
+        Self {
+            color: fluid.color,
+            density: fluid.density,
+            height_falloff: 0.,
+            base_height: 0.,
+            end_distance: FAR_DISTANCE,
+            sky: 0.,
+        }
+    }
+
+    // The fog seen by a camera at `position`, which depends on whether it is
+    // inside a fluid block
+    pub fn at(position: &glm::Vec3, world: &World, block_registry: &BlockRegistry) -> Self {
+        // Blocks are centered on integer coordinates
+        let block = glm::round(position);
+        let (x, y, z) = (block.x as GLint, block.y as GLint, block.z as GLint);
+        if !World::contains(x, y, z) {
+            return Self::distance();
+        }
+
+        match block_registry.fluid(world.get(x as GLuint, y as GLuint, z as GLuint)) {
+            Some(fluid) => Self::fluid(&fluid),
+            None => Self::distance(),
+        }
+    }
+}
@@ -0,0 +1,85 @@
+use gl::types::*;
+
+use crate::texture::Texture2D;
+
+// An offscreen render target with a single color texture and optionally a
+// depth texture, deleted on drop
+pub struct Framebuffer {
+    id: GLuint,
+    color: Texture2D,
+    depth: Option<Texture2D>,
+    width: u32,
+    height: u32,
+}
+
+impl Framebuffer {
+    pub fn new(width: u32, height: u32, color_format: GLenum, with_depth: bool) -> Self {
+        let color = Texture2D::empty(width, height, color_format);
+
+        let mut id = 0;
+        unsafe {
+            gl::CreateFramebuffers(1, &mut id);
+            gl::NamedFramebufferTexture(id, gl::COLOR_ATTACHMENT0, color.name(), 0);
+        }
+
+        // A texture rather than a renderbuffer, so that it can be read back
+        // by the occlusion culling
+        let depth = if with_depth {
+            let depth = Texture2D::empty(width, height, gl::DEPTH_COMPONENT32F);
+            unsafe { gl::NamedFramebufferTexture(id, gl::DEPTH_ATTACHMENT, depth.name(), 0) };
+            Some(depth)
+        } else {
+            None
+        };
+
+        let status = unsafe { gl::CheckNamedFramebufferStatus(id, gl::FRAMEBUFFER) };
+        assert_eq!(
+            status,
+            gl::FRAMEBUFFER_COMPLETE,
+            "incomplete framebuffer: {:#x}",
+            status
+        );
+
+        Self {
+            id,
+            color,
+            depth,
+            width,
+            height,
+        }
+    }
+
+    // Draws to this framebuffer from now on, over all of it
+    pub fn bind(&self) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, self.id);
+            gl::Viewport(0, 0, self.width as GLsizei, self.height as GLsizei);
+        }
+    }
+
+    // Draws to the window from now on
+    pub fn bind_default(width: u32, height: u32) {
+        unsafe {
+            gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+            gl::Viewport(0, 0, width as GLsizei, height as GLsizei);
+        }
+    }
+
+    pub fn color(&self) -> &Texture2D {
+        &self.color
+    }
+
+    pub fn depth(&self) -> Option<&Texture2D> {
+        self.depth.as_ref()
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+}
+
+impl Drop for Framebuffer {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteFramebuffers(1, &self.id) };
+    }
+}
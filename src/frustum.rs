@@ -0,0 +1,197 @@
+// The six planes bounding what a camera can see, see "Fast Extraction of
+// Viewing Frustum Planes from the World-View-Projection Matrix" by Gribb and
+// Hartmann
+
+// The points p for which dot(normal, p) + distance >= 0 are on the inside
+#[derive(Debug, Copy, Clone)]
+pub struct Plane {
+    pub normal: glm::Vec3,
+    pub distance: f32,
+}
+
+impl Plane {
+    // From the coefficients of its equation, normalized so that
+    // signed_distance is in world units
+    fn new(coefficients: &glm::Vec4) -> Self {
+        let normal = glm::vec3(coefficients.x, coefficients.y, coefficients.z);
+        let length = glm::length(&normal);
+        Self {
+            normal: normal / length,
+            distance: coefficients.w / length,
+        }
+    }
+
+    pub fn signed_distance(&self, point: &glm::Vec3) -> f32 {
+        glm::dot(&self.normal, point) + self.distance
+    }
+}
+
+pub struct Frustum {
+    // Left, right, bottom, top, near, far
+    pub planes: [Plane; 6],
+}
+
+impl Frustum {
+    // Takes projection * view, the planes are then in world coordinates
+    pub fn from_matrix(view_projection: &glm::Mat4) -> Self {
+        // A point is inside when -w <= x, y, z <= w in clip space, and each of
+        // the inequalities is a plane
+        let row = |i: usize| -> glm::Vec4 { view_projection.row(i).transpose() };
+        let (x, y, z, w) = (row(0), row(1), row(2), row(3));
+        Self {
+            planes: [
+                Plane::new(&(w + x)),
+                Plane::new(&(w - x)),
+                Plane::new(&(w + y)),
+                Plane::new(&(w - y)),
+                Plane::new(&(w + z)),
+                Plane::new(&(w - z)),
+            ],
+        }
+    }
+
+    pub fn intersects_sphere(&self, center: &glm::Vec3, radius: f32) -> bool {
+        self.planes
+            .iter()
+            .all(|plane| plane.signed_distance(center) >= -radius)
+    }
+
+    // Conservative: a box near a corner of the frustum can be outside of it
+    // while being partly inside every plane
+    pub fn intersects_aabb(&self, min: &glm::Vec3, max: &glm::Vec3) -> bool {
+        self.planes.iter().all(|plane| {
+            // The corner of the box the farthest along the normal
+            let corner = glm::vec3(
+                if plane.normal.x >= 0. { max.x } else { min.x },
+                if plane.normal.y >= 0. { max.y } else { min.y },
+                if plane.normal.z >= 0. { max.z } else { min.z },
+            );
+            plane.signed_distance(&corner) >= 0.
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f32 = 1e-4;
+
+    fn assert_vec3_eq(a: &glm::Vec3, b: &glm::Vec3) {
+        assert!(glm::distance(a, b) < EPSILON, "{:?} != {:?}", a, b);
+    }
+
+    // Looking along +y from the origin with z up, like the camera in main
+    fn camera_frustum(near: f32, far: f32) -> Frustum {
+        let view = glm::look_at(
+            &glm::vec3(0., 0., 0.),
+            &glm::vec3(0., 1., 0.),
+            &glm::vec3(0., 0., 1.),
+        );
+        let projection = glm::perspective(1., glm::half_pi(), near, far);
+        Frustum::from_matrix(&(projection * view))
+    }
+
+    #[test]
+    fn identity_gives_the_clip_cube() {
+        let frustum = Frustum::from_matrix(&glm::identity());
+        let normals = [
+            glm::vec3(1., 0., 0.),
+            glm::vec3(-1., 0., 0.),
+            glm::vec3(0., 1., 0.),
+            glm::vec3(0., -1., 0.),
+            glm::vec3(0., 0., 1.),
+            glm::vec3(0., 0., -1.),
+        ];
+        for (plane, normal) in frustum.planes.iter().zip(normals.iter()) {
+            assert_vec3_eq(&plane.normal, normal);
+            assert!((plane.distance - 1.).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn planes_are_normalized() {
+        let frustum = camera_frustum(0.1, 128.);
+        for plane in frustum.planes.iter() {
+            assert!((glm::length(&plane.normal) - 1.).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn near_and_far_planes_face_each_other_along_the_view() {
+        let frustum = camera_frustum(0.5, 100.);
+        let (near, far) = (&frustum.planes[4], &frustum.planes[5]);
+
+        assert_vec3_eq(&near.normal, &glm::vec3(0., 1., 0.));
+        assert_vec3_eq(&far.normal, &glm::vec3(0., -1., 0.));
+        assert!(near.signed_distance(&glm::vec3(0., 0.5, 0.)).abs() < EPSILON);
+        assert!(far.signed_distance(&glm::vec3(0., 100., 0.)).abs() < 1e-2);
+    }
+
+    #[test]
+    fn side_planes_follow_the_field_of_view() {
+        // A square 90 degrees field of view, the side planes are at 45 degrees
+        let frustum = camera_frustum(0.1, 128.);
+        let diagonal = std::f32::consts::FRAC_1_SQRT_2;
+        assert_vec3_eq(
+            &frustum.planes[0].normal,
+            &glm::vec3(diagonal, diagonal, 0.),
+        );
+        assert_vec3_eq(
+            &frustum.planes[1].normal,
+            &glm::vec3(-diagonal, diagonal, 0.),
+        );
+        assert_vec3_eq(
+            &frustum.planes[2].normal,
+            &glm::vec3(0., diagonal, diagonal),
+        );
+        assert_vec3_eq(
+            &frustum.planes[3].normal,
+            &glm::vec3(0., diagonal, -diagonal),
+        );
+        for plane in frustum.planes[..4].iter() {
+            assert!(plane.distance.abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn points() {
+        let frustum = camera_frustum(0.1, 128.);
+        let contains = |x, y, z| frustum.intersects_sphere(&glm::vec3(x, y, z), 0.);
+        assert!(contains(0., 10., 0.));
+        assert!(contains(9., 10., -9.));
+        // Behind, past the far plane, and on each side
+        assert!(!contains(0., -10., 0.));
+        assert!(!contains(0., 200., 0.));
+        assert!(!contains(11., 10., 0.));
+        assert!(!contains(-11., 10., 0.));
+        assert!(!contains(0., 10., 11.));
+        assert!(!contains(0., 10., -11.));
+    }
+
+    #[test]
+    fn spheres() {
+        let frustum = camera_frustum(0.1, 128.);
+        assert!(frustum.intersects_sphere(&glm::vec3(0., -1., 0.), 2.));
+        assert!(!frustum.intersects_sphere(&glm::vec3(0., -3., 0.), 2.));
+        assert!(frustum.intersects_sphere(&glm::vec3(12., 10., 0.), 2.));
+        assert!(!frustum.intersects_sphere(&glm::vec3(14., 10., 0.), 2.));
+    }
+
+    #[test]
+    fn boxes() {
+        let frustum = camera_frustum(0.1, 128.);
+        let aabb = |min: [f32; 3], max: [f32; 3]| {
+            frustum.intersects_aabb(&glm::make_vec3(&min), &glm::make_vec3(&max))
+        };
+        // Inside, straddling a side, around the camera and containing it all
+        assert!(aabb([-1., 5., -1.], [1., 7., 1.]));
+        assert!(aabb([8., 9., 0.], [12., 11., 1.]));
+        assert!(aabb([-1., -1., -1.], [1., 1., 1.]));
+        assert!(aabb([-500., -500., -500.], [500., 500., 500.]));
+        // Completely behind, to the side and past the far plane
+        assert!(!aabb([-1., -7., -1.], [1., -5., 1.]));
+        assert!(!aabb([12., 9., 0.], [14., 11., 1.]));
+        assert!(!aabb([-1., 130., -1.], [1., 140., 1.]));
+    }
+}
@@ -0,0 +1,93 @@
+use gl::types::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::program::Program;
+use crate::resource_pack::ResourcePacks;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+// Watches the .glsl files under shaders/ of every resource pack loaded from a
+// directory, archives are not expected to change while running
+pub struct ShaderWatcher {
+    directories: Vec<PathBuf>,
+    modified: HashMap<PathBuf, SystemTime>,
+    last_poll: Instant,
+}
+
+fn scan(directory: &Path, modified: &mut HashMap<PathBuf, SystemTime>) {
+    let entries = match std::fs::read_dir(directory) {
+        Ok(entries) => entries,
+        Err(_) => return,
+    };
+    for entry in entries.filter_map(Result::ok) {
+        let path = entry.path();
+        if path.is_dir() {
+            scan(&path, modified);
+        } else if path.extension().and_then(|extension| extension.to_str()) == Some("glsl") {
+            if let Ok(time) = entry.metadata().and_then(|metadata| metadata.modified()) {
+                modified.insert(path, time);
+            }
+        }
+    }
+}
+
+impl ShaderWatcher {
+    pub fn new(packs: &ResourcePacks) -> Self {
+        let directories: Vec<PathBuf> = packs
+            .packs()
+            .iter()
+            .filter_map(|pack| pack.directory())
+            .map(|directory| directory.join("shaders"))
+            .collect();
+
+        let mut modified = HashMap::new();
+        for directory in &directories {
+            scan(directory, &mut modified);
+        }
+
+        Self {
+            directories,
+            modified,
+            last_poll: Instant::now(),
+        }
+    }
+
+    // Returns true if a shader was created, modified or deleted since the last
+    // time it returned true
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let mut modified = HashMap::new();
+        for directory in &self.directories {
+            scan(directory, &mut modified);
+        }
+
+        if modified == self.modified {
+            return false;
+        }
+        self.modified = modified;
+        true
+    }
+}
+
+// Recompiles and relinks the program, keeping the old one if that fails
+pub fn reload(
+    program: &mut Program,
+    packs: &ResourcePacks,
+    shaders: &[(&str, GLenum)],
+    defines: &[(&str, String)],
+) {
+    match Program::load(packs, shaders, defines) {
+        Ok(reloaded) => {
+            *program = reloaded;
+            let paths: Vec<&str> = shaders.iter().map(|&(path, _)| path).collect();
+            println!("Reloaded {}", paths.join(", "));
+        }
+        Err(error) => eprintln!("{}", error),
+    }
+}
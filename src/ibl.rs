@@ -0,0 +1,125 @@
+use gl::types::*;
+
+use crate::buffer::VertexArray;
+use crate::program::{FrameUniforms, Program, UniformBuffer};
+use crate::sky::{render_cubemap, SkyboxCube};
+use crate::texture::{Texture2D, TextureCubeMap};
+
+// Image based lighting, see https://learnopengl.com/PBR/IBL/Diffuse-irradiance
+// and https://learnopengl.com/PBR/IBL/Specular-IBL
+
+const IRRADIANCE_SIZE: u32 = 32;
+const PREFILTERED_SIZE: u32 = 128;
+// Passed to the shaders as a define, the roughness goes from 0 at the first
+// level to 1 at the last one
+pub const PREFILTERED_MIP_LEVELS: u32 = 5;
+const BRDF_LUT_SIZE: u32 = 512;
+
+// The texture unit the environment is bound to while baking
+const ENVIRONMENT_TEXTURE_UNIT: GLuint = 0;
+
+// The diffuse and specular light coming from an environment cubemap
+pub struct EnvironmentLighting {
+    // The cosine weighted average of the environment over each hemisphere
+    irradiance: TextureCubeMap,
+    // The environment blurred more and more in each mip level, following the
+    // GGX distribution of increasing roughnesses
+    prefiltered: TextureCubeMap,
+}
+
+impl EnvironmentLighting {
+    pub fn new() -> Self {
+        Self {
+            irradiance: TextureCubeMap::empty(IRRADIANCE_SIZE, 1, gl::RGB16F),
+            prefiltered: TextureCubeMap::empty(
+                PREFILTERED_SIZE,
+                PREFILTERED_MIP_LEVELS,
+                gl::RGB16F,
+            ),
+        }
+    }
+
+    // Has to be called again whenever the environment changes
+    pub fn bake(
+        &self,
+        environment: &TextureCubeMap,
+        irradiance_program: &Program,
+        prefilter_program: &Program,
+        frame_uniforms: &UniformBuffer<FrameUniforms>,
+        skybox_cube: &SkyboxCube,
+    ) {
+        let no_sun = glm::vec3(0., 0., 0.);
+        environment.bind(ENVIRONMENT_TEXTURE_UNIT);
+
+        irradiance_program.use_();
+        irradiance_program.set_uniform_sampler("environment", ENVIRONMENT_TEXTURE_UNIT);
+        render_cubemap(
+            irradiance_program,
+            frame_uniforms,
+            skybox_cube,
+            &no_sun,
+            &self.irradiance,
+            IRRADIANCE_SIZE,
+            0,
+        );
+
+        prefilter_program.use_();
+        prefilter_program.set_uniform_sampler("environment", ENVIRONMENT_TEXTURE_UNIT);
+        for level in 0..PREFILTERED_MIP_LEVELS {
+            let roughness = level as f32 / (PREFILTERED_MIP_LEVELS - 1) as f32;
+            prefilter_program.set_uniform_float("roughness", roughness);
+            render_cubemap(
+                prefilter_program,
+                frame_uniforms,
+                skybox_cube,
+                &no_sun,
+                &self.prefiltered,
+                PREFILTERED_SIZE >> level,
+                level,
+            );
+        }
+    }
+
+    pub fn bind(&self, irradiance_texture_unit: GLuint, prefiltered_texture_unit: GLuint) {
+        self.irradiance.bind(irradiance_texture_unit);
+        self.prefiltered.bind(prefiltered_texture_unit);
+    }
+}
+
+// The scale and bias to the Fresnel reflectance at normal incidence of the
+// specular part of the split sum, by angle and roughness. Does not depend on
+// the environment so it is only computed once.
+pub fn bake_brdf_lut(brdf_program: &Program) -> Texture2D {
+    let lut = Texture2D::empty(BRDF_LUT_SIZE, BRDF_LUT_SIZE, gl::RG16F);
+    // The vertices of the full screen triangle come from gl_VertexID
+    let vertex_array = VertexArray::new();
+
+    let mut previous_viewport: [GLint; 4] = [0; 4];
+    let mut fbo = 0;
+    unsafe {
+        gl::GetIntegerv(gl::VIEWPORT, previous_viewport.as_mut_ptr());
+        gl::CreateFramebuffers(1, &mut fbo);
+        gl::NamedFramebufferTexture(fbo, gl::COLOR_ATTACHMENT0, lut.name(), 0);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        gl::Viewport(0, 0, BRDF_LUT_SIZE as GLsizei, BRDF_LUT_SIZE as GLsizei);
+        gl::Disable(gl::DEPTH_TEST);
+    }
+
+    brdf_program.use_();
+    vertex_array.bind();
+    unsafe {
+        gl::DrawArrays(gl::TRIANGLES, 0, 3);
+
+        gl::Enable(gl::DEPTH_TEST);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        gl::DeleteFramebuffers(1, &fbo);
+        gl::Viewport(
+            previous_viewport[0],
+            previous_viewport[1],
+            previous_viewport[2],
+            previous_viewport[3],
+        );
+    }
+
+    lut
+}
@@ -0,0 +1,122 @@
+use gl::types::*;
+
+use crate::block::BlockRegistry;
+use crate::buffer::Buffer;
+use crate::frustum::Frustum;
+use crate::program::Program;
+use crate::world::{World, WORLD_X_SIZE, WORLD_Y_SIZE, WORLD_Z_SIZE};
+
+// Shader storage buffer bindings, passed to the shaders as defines
+pub const LIGHTS_BINDING: GLuint = 1;
+pub const TILE_LIGHTS_BINDING: GLuint = 2;
+
+// Side in pixels of the square screen tiles, each of them is culled by one
+// work group of shaders/lights/cull.comp.glsl
+pub const TILE_SIZE: u32 = 16;
+
+// Lights past the first MAX_LIGHTS are ignored, and so are the lights past the
+// first MAX_LIGHTS_PER_TILE touching a tile
+pub const MAX_LIGHTS: usize = 1024;
+pub const MAX_LIGHTS_PER_TILE: usize = 128;
+
+// Follows the std430 layout of PointLight in shaders/lighting.glsl
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct PointLight {
+    position: [GLfloat; 3],
+    // The light has no effect past this distance, which is what lets us cull it
+    radius: GLfloat,
+    color: [GLfloat; 3],
+    _padding: GLfloat,
+}
+
+impl PointLight {
+    pub fn new(position: &glm::Vec3, color: [GLfloat; 3], radius: GLfloat) -> Self {
+        Self {
+            position: (*position).into(),
+            radius,
+            color,
+            _padding: 0.,
+        }
+    }
+
+    // Whether the light can reach anything inside the frustum
+    pub fn is_visible(&self, frustum: &Frustum) -> bool {
+        frustum.intersects_sphere(&glm::make_vec3(&self.position), self.radius)
+    }
+}
+
+// One light at the center of every block which emits light
+pub fn block_lights(world: &World, block_registry: &BlockRegistry) -> Vec<PointLight> {
+    let mut lights = vec![];
+    for z in 0..WORLD_Z_SIZE {
+        for y in 0..WORLD_Y_SIZE {
+            for x in 0..WORLD_X_SIZE {
+                if let Some(light) = block_registry.light(world.get(x, y, z)) {
+                    lights.push(PointLight::new(
+                        &glm::vec3(x as f32, y as f32, z as f32),
+                        light.color,
+                        light.radius,
+                    ));
+                }
+            }
+        }
+    }
+    lights
+}
+
+// The lights of the frame and, for every screen tile, the list of the ones
+// which can reach any of its pixels. Each list is stored as its length
+// followed by MAX_LIGHTS_PER_TILE indices into the lights.
+pub struct TiledLights {
+    lights: Buffer<PointLight>,
+    tile_lights: Buffer<GLuint>,
+    tiles: (u32, u32),
+}
+
+impl TiledLights {
+    pub fn new() -> Self {
+        Self {
+            lights: Buffer::new(MAX_LIGHTS),
+            tile_lights: Buffer::new(0),
+            tiles: (0, 0),
+        }
+    }
+
+    // Uploads the lights and makes room for the tiles of the viewport, returns
+    // how many lights were kept
+    pub fn update(&mut self, lights: &[PointLight], viewport_size: (u32, u32)) -> usize {
+        let light_count = lights.len().min(MAX_LIGHTS);
+        self.lights.write(0, &lights[..light_count]);
+
+        let tiles = (
+            viewport_size.0.div_ceil(TILE_SIZE),
+            viewport_size.1.div_ceil(TILE_SIZE),
+        );
+        if tiles != self.tiles {
+            self.tiles = tiles;
+            self.tile_lights =
+                Buffer::new((tiles.0 * tiles.1) as usize * (MAX_LIGHTS_PER_TILE + 1));
+        }
+
+        light_count
+    }
+
+    pub fn bind(&self) {
+        self.lights
+            .bind_base(gl::SHADER_STORAGE_BUFFER, LIGHTS_BINDING);
+        self.tile_lights
+            .bind_base(gl::SHADER_STORAGE_BUFFER, TILE_LIGHTS_BINDING);
+    }
+
+    // Fills the per tile light lists, the Frame uniforms must be up to date
+    pub fn cull(&self, light_culling_program: &Program) {
+        light_culling_program.use_();
+        self.bind();
+        unsafe {
+            gl::DispatchCompute(self.tiles.0, self.tiles.1, 1);
+            // The fragment shaders read what the compute shader wrote
+            gl::MemoryBarrier(gl::SHADER_STORAGE_BARRIER_BIT);
+        }
+    }
+}
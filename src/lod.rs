@@ -0,0 +1,163 @@
+use gl::types::*;
+
+use crate::block::{BlockRegistry, Transparency};
+use crate::chunk::AIR;
+use crate::world::{World, WORLD_X_SIZE, WORLD_Y_SIZE, WORLD_Z_SIZE};
+
+// Every section is meshed at a single level of detail, the blocks of level n
+// being cubes of 2^n blocks, picked from its distance to the camera: the first
+// level is used up to LOD_DISTANCE, and each of the others up to twice the
+// distance of the previous one. It is meshed again when the camera moves it to
+// another level.
+pub const LOD_LEVELS: usize = 4;
+pub const LOD_DISTANCE: f32 = 24.;
+
+// How far down from the surface the skirts go, which covers the difference of
+// height between two levels
+pub const SKIRT_DEPTH: u32 = 1 << (LOD_LEVELS - 1);
+
+// The level of detail of the box between `min` and `max`, from its distance
+// to the camera
+pub fn lod_level(min: &glm::Vec3, max: &glm::Vec3, camera_position: &glm::Vec3) -> usize {
+    let outside = glm::max(
+        &glm::max2(&(min - camera_position), &(camera_position - max)),
+        0.,
+    );
+    let distance = glm::length(&outside);
+    if distance < LOD_DISTANCE {
+        return 0;
+    }
+    ((distance / LOD_DISTANCE).log2() as usize + 1).min(LOD_LEVELS - 1)
+}
+
+// The opaque and cutout blocks of the world, downsampled by 2^level along each
+// axis. Translucent blocks are left out, they are always drawn at full
+// resolution.
+pub struct LodGrid {
+    pub cell_size: u32,
+    size: [u32; 3],
+    blocks: Vec<GLuint>,
+}
+
+impl LodGrid {
+    // A cell is solid when at least half of the cells of the finer level it
+    // covers are, and takes the block of the highest of them, which keeps the
+    // tops of the hills covered with grass
+    fn downsample(
+        finer_size: [u32; 3],
+        finer_cell_size: u32,
+        finer: impl Fn(u32, u32, u32) -> GLuint,
+    ) -> Self {
+        let size = [finer_size[0] / 2, finer_size[1] / 2, finer_size[2] / 2];
+        let mut blocks = Vec::with_capacity((size[0] * size[1] * size[2]) as usize);
+        for z in 0..size[2] {
+            for y in 0..size[1] {
+                for x in 0..size[0] {
+                    let mut solid = 0;
+                    let mut highest = AIR;
+                    // From the top, so that the highest block is seen first
+                    for dz in (0..2).rev() {
+                        for dy in 0..2 {
+                            for dx in 0..2 {
+                                let block = finer(2 * x + dx, 2 * y + dy, 2 * z + dz);
+                                if block != AIR {
+                                    solid += 1;
+                                    if highest == AIR {
+                                        highest = block;
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    blocks.push(if solid >= 4 { highest } else { AIR });
+                }
+            }
+        }
+        Self {
+            cell_size: finer_cell_size * 2,
+            size,
+            blocks,
+        }
+    }
+
+    // The first coarse level, made from the world
+    pub fn from_world(world: &World, registry: &BlockRegistry) -> Self {
+        Self::downsample([WORLD_X_SIZE, WORLD_Y_SIZE, WORLD_Z_SIZE], 1, |x, y, z| {
+            let block = world.get(x, y, z);
+            match registry.transparency(block) {
+                Transparency::Translucent => AIR,
+                Transparency::Opaque | Transparency::Cutout => block,
+            }
+        })
+    }
+
+    pub fn coarser(&self) -> Self {
+        Self::downsample(self.size, self.cell_size, |x, y, z| {
+            self.blocks[self.index(x, y, z)]
+        })
+    }
+
+    fn index(&self, x: u32, y: u32, z: u32) -> usize {
+        ((z * self.size[1] + y) * self.size[0] + x) as usize
+    }
+
+    // AIR outside of the world
+    pub fn get(&self, x: i32, y: i32, z: i32) -> GLuint {
+        let inside = (0..3).all(|axis| {
+            let coordinate = [x, y, z][axis];
+            coordinate >= 0 && (coordinate as u32) < self.size[axis]
+        });
+        if inside {
+            self.blocks[self.index(x as u32, y as u32, z as u32)]
+        } else {
+            AIR
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn cells_at_least_half_full_are_solid() {
+        const STONE: GLuint = 1;
+        // Three blocks in the first 2x2x2 cell, four in the second
+        let grid = LodGrid::downsample([4, 2, 2], 1, |x, y, z| {
+            let filled = if x < 2 { 3 } else { 4 };
+            if ((x % 2) + 2 * y + 4 * z) < filled {
+                STONE
+            } else {
+                AIR
+            }
+        });
+        assert_eq!(grid.cell_size, 2);
+        assert_eq!(grid.get(0, 0, 0), AIR);
+        assert_eq!(grid.get(1, 0, 0), STONE);
+        assert_eq!(grid.get(2, 0, 0), AIR);
+        assert_eq!(grid.get(-1, 0, 0), AIR);
+    }
+
+    #[test]
+    fn levels_get_coarser_with_the_distance() {
+        let min = glm::vec3(0., 0., 0.);
+        let max = glm::vec3(16., 16., 16.);
+        let level = |x| lod_level(&min, &max, &glm::vec3(x, 8., 8.));
+        // From inside and next to the box
+        assert_eq!(level(8.), 0);
+        assert_eq!(level(16. + LOD_DISTANCE - 1.), 0);
+        assert_eq!(level(16. + LOD_DISTANCE + 1.), 1);
+        assert_eq!(level(16. + 2. * LOD_DISTANCE + 1.), 2);
+        assert_eq!(level(-2. * LOD_DISTANCE - 1.), 2);
+        assert_eq!(level(10000.), LOD_LEVELS - 1);
+    }
+
+    #[test]
+    fn cells_take_the_highest_block() {
+        const DIRT: GLuint = 1;
+        const GRASS: GLuint = 2;
+        let grid = LodGrid::downsample([2, 2, 2], 1, |_, _, z| if z == 1 { GRASS } else { DIRT });
+        assert_eq!(grid.get(0, 0, 0), GRASS);
+        assert_eq!(grid.coarser().size, [0, 0, 0]);
+    }
+}
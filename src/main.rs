@@ -1,61 +1,259 @@
 // TODO(Andrea):
 // - Cube reflections using skybox
-// - Occlusion culling compute shader
 
 use gl::types::*;
 
 extern crate nalgebra_glm as glm;
 
 use glfw::{Action, Context, Key};
+use image::{Rgb, RgbImage, RgbaImage};
 
+mod arena;
+mod biome;
+mod block;
+mod buffer;
 mod chunk;
 mod constants;
 mod debug_message_callback;
+mod decoration;
+mod fog;
+mod framebuffer;
+mod frustum;
+mod hot_reload;
+mod ibl;
+mod light;
+mod lod;
 mod measure_elapsed;
+mod mesh;
+mod occlusion;
+mod post;
+mod preprocessor;
 mod program;
 mod raycasting;
+mod raymarch;
+mod resource_pack;
 mod shader;
+mod sky;
+mod svo;
+mod terrain;
 mod texture;
 mod vertex;
+mod vox;
+mod voxel_ray;
+mod world;
 
-use chunk::Chunk;
+use block::{BlockRegistry, MATERIALS_BINDING};
+use buffer::{Buffer, VertexArray};
+use chunk::GLOWSTONE;
 use constants::*;
+use decoration::Structures;
+use fog::Fog;
+use frustum::Frustum;
+use hot_reload::{reload, ShaderWatcher};
+use ibl::{bake_brdf_lut, EnvironmentLighting, PREFILTERED_MIP_LEVELS};
+use light::{
+    block_lights, PointLight, TiledLights, LIGHTS_BINDING, MAX_LIGHTS_PER_TILE,
+    TILE_LIGHTS_BINDING, TILE_SIZE,
+};
 use measure_elapsed::measure_elapsed;
-use program::Program;
-use raycasting::raycast;
-use shader::Shader;
-use texture::{Texture2D, TextureCubeMap};
-use vertex::{cube, skybox_cube, Vertex, VertexUVNormal};
-
-use std::ffi::CString;
+use mesh::{mesh_blocks, push_cube, sort_back_to_front, BlockMesh, WorldMesh};
+use occlusion::{
+    OcclusionCulling, CULL_WORK_GROUP_SIZE, DRAW_COMMANDS_BINDING, HI_Z_WORK_GROUP_SIZE,
+    SECTIONS_BINDING,
+};
+use post::{PostProcessing, PostSettings};
+use program::{FrameUniforms, Program, UniformBuffer, FRAME_UNIFORMS_BINDING};
+use raycasting::Raycaster;
+use raymarch::{VoxelVolume, VOXEL_BLOCKS_BINDING};
+use resource_pack::{base_pack_path, ResourcePack, ResourcePacks};
+use sky::{bake_atmosphere, sun_direction, SkyMode, SkyboxCube, ATMOSPHERE_CUBEMAP_SIZE};
+use texture::{normal_map_from_height, Texture2D, TextureArray, TextureCubeMap};
+use vertex::{BlockVertex, PositionVertex, Vertex};
+use world::{Storage, Visibility, World};
+
+use std::path::Path;
+
+const CUBE_SHADERS: [(&str, GLenum); 2] = [
+    ("shaders/cube/cube.vert.glsl", gl::VERTEX_SHADER),
+    ("shaders/cube/cube.frag.glsl", gl::FRAGMENT_SHADER),
+];
+const SKYBOX_SHADERS: [(&str, GLenum); 2] = [
+    ("shaders/skybox/skybox.vert.glsl", gl::VERTEX_SHADER),
+    ("shaders/skybox/skybox.frag.glsl", gl::FRAGMENT_SHADER),
+];
+const ATMOSPHERE_SHADERS: [(&str, GLenum); 2] = [
+    ("shaders/skybox/skybox.vert.glsl", gl::VERTEX_SHADER),
+    ("shaders/sky/sky.frag.glsl", gl::FRAGMENT_SHADER),
+];
+const LIGHT_CULLING_SHADERS: [(&str, GLenum); 1] =
+    [("shaders/lights/cull.comp.glsl", gl::COMPUTE_SHADER)];
+const SECTION_CULLING_SHADERS: [(&str, GLenum); 1] =
+    [("shaders/occlusion/cull.comp.glsl", gl::COMPUTE_SHADER)];
+const HI_Z_SHADERS: [(&str, GLenum); 1] =
+    [("shaders/occlusion/hi_z.comp.glsl", gl::COMPUTE_SHADER)];
+const RAYMARCH_SHADERS: [(&str, GLenum); 2] = [
+    ("shaders/fullscreen.vert.glsl", gl::VERTEX_SHADER),
+    ("shaders/raymarch/raymarch.frag.glsl", gl::FRAGMENT_SHADER),
+];
+const IRRADIANCE_SHADERS: [(&str, GLenum); 2] = [
+    ("shaders/skybox/skybox.vert.glsl", gl::VERTEX_SHADER),
+    ("shaders/ibl/irradiance.frag.glsl", gl::FRAGMENT_SHADER),
+];
+const PREFILTER_SHADERS: [(&str, GLenum); 2] = [
+    ("shaders/skybox/skybox.vert.glsl", gl::VERTEX_SHADER),
+    ("shaders/ibl/prefilter.frag.glsl", gl::FRAGMENT_SHADER),
+];
+const BRDF_SHADERS: [(&str, GLenum); 2] = [
+    ("shaders/fullscreen.vert.glsl", gl::VERTEX_SHADER),
+    ("shaders/ibl/brdf.frag.glsl", gl::FRAGMENT_SHADER),
+];
 
 fn degrees_to_radians(degrees: f32) -> f32 {
     degrees * glm::pi::<f32>() / 180.
 }
 
-fn draw(
-    camera_ray: &glm::Vec3,
-    camera_pos: &glm::Vec3,
-    up: &glm::Vec3,
-    width: f64,
-    height: f64,
+// Every program but those of the post processing, which it owns
+struct Programs {
+    cube: Program,
+    skybox: Program,
+    atmosphere: Program,
+    light_culling: Program,
+    section_culling: Program,
+    hi_z: Program,
+    raymarch: Program,
+    irradiance: Program,
+    prefilter: Program,
+    brdf: Program,
+}
 
-    cube_vao: GLuint,
-    cube_bo: GLuint,
+impl Programs {
+    fn load(packs: &ResourcePacks, defines: &[(&str, String)]) -> Result<Self, String> {
+        let load = |shaders: &[(&str, GLenum)]| Program::load(packs, shaders, defines);
+        Ok(Self {
+            cube: load(&CUBE_SHADERS)?,
+            skybox: load(&SKYBOX_SHADERS)?,
+            atmosphere: load(&ATMOSPHERE_SHADERS)?,
+            light_culling: load(&LIGHT_CULLING_SHADERS)?,
+            section_culling: load(&SECTION_CULLING_SHADERS)?,
+            hi_z: load(&HI_Z_SHADERS)?,
+            raymarch: load(&RAYMARCH_SHADERS)?,
+            irradiance: load(&IRRADIANCE_SHADERS)?,
+            prefilter: load(&PREFILTER_SHADERS)?,
+            brdf: load(&BRDF_SHADERS)?,
+        })
+    }
 
-    skybox_vao: GLuint,
-    skybox_bo: GLuint,
+    // A program whose shaders do not compile anymore is kept as it was
+    fn reload(&mut self, packs: &ResourcePacks, defines: &[(&str, String)]) {
+        let programs: Vec<(&mut Program, &[(&str, GLenum)])> = vec![
+            (&mut self.cube, &CUBE_SHADERS),
+            (&mut self.skybox, &SKYBOX_SHADERS),
+            (&mut self.atmosphere, &ATMOSPHERE_SHADERS),
+            (&mut self.light_culling, &LIGHT_CULLING_SHADERS),
+            (&mut self.section_culling, &SECTION_CULLING_SHADERS),
+            (&mut self.hi_z, &HI_Z_SHADERS),
+            (&mut self.raymarch, &RAYMARCH_SHADERS),
+            (&mut self.irradiance, &IRRADIANCE_SHADERS),
+            (&mut self.prefilter, &PREFILTER_SHADERS),
+            (&mut self.brdf, &BRDF_SHADERS),
+        ];
+        for (program, shaders) in programs {
+            reload(program, packs, shaders, defines);
+        }
+    }
+}
 
-    mossy_cobblestone_texture: &Texture2D,
+// The buffers, textures and state draw renders the world with, which live as
+// long as the window
+struct Renderer {
+    mesh_bo: Buffer<BlockVertex>,
+    mesh_vao: VertexArray,
+    skybox_cube: SkyboxCube,
+    block_textures: TextureArray,
+    block_normal_maps: TextureArray,
+    block_height_maps: TextureArray,
+    sky_cubemap_texture: TextureCubeMap,
+    sky_lighting: EnvironmentLighting,
+    atmosphere_lighting: EnvironmentLighting,
+    brdf_lut: Texture2D,
+    frame_uniforms: UniformBuffer<FrameUniforms>,
+    tiled_lights: TiledLights,
+    block_lights: Vec<PointLight>,
+    world_mesh: WorldMesh,
+    voxel_volume: VoxelVolume,
+    raycaster: Raycaster,
+    occlusion: OcclusionCulling,
+}
 
-    sky_cubemap_texture: &TextureCubeMap,
+// What the user controls, which can change from one frame to the next
+#[derive(Debug, Copy, Clone)]
+struct FrameSettings {
+    camera_pos: glm::Vec3,
+    camera_ray: glm::Vec3,
+    up: glm::Vec3,
+    width: f64,
+    height: f64,
+    sky_mode: SkyMode,
+    sun_direction: glm::Vec3,
+    surface_mapping: bool,
+    visibility: Visibility,
+    frustum_culling: bool,
+    occlusion_culling: bool,
+    lod: bool,
+    time: f64,
+}
 
-    textured_phong_cube_program: &Program,
-    skybox_program: &Program,
+fn draw(
+    renderer: &mut Renderer,
+    programs: &Programs,
+    world: &World,
+    block_registry: &BlockRegistry,
+    settings: &FrameSettings,
+) -> glm::Mat4 {
+    let Renderer {
+        mesh_bo,
+        mesh_vao,
+        skybox_cube,
+        block_textures,
+        block_normal_maps,
+        block_height_maps,
+        sky_cubemap_texture,
+        sky_lighting,
+        atmosphere_lighting,
+        brdf_lut,
+        frame_uniforms,
+        tiled_lights,
+        block_lights,
+        world_mesh,
+        voxel_volume,
+        raycaster,
+        occlusion,
+    } = renderer;
+    let Programs {
+        cube: textured_pbr_cube_program,
+        skybox: skybox_program,
+        atmosphere: atmosphere_program,
+        light_culling: light_culling_program,
+        section_culling: section_culling_program,
+        raymarch: raymarch_program,
+        ..
+    } = programs;
+    let FrameSettings {
+        camera_pos,
+        camera_ray,
+        up,
+        width,
+        height,
+        sky_mode,
+        sun_direction,
+        surface_mapping,
+        visibility,
+        frustum_culling,
+        occlusion_culling,
+        lod,
+        time,
+    } = *settings;
 
-    chunk: &Chunk,
-    time: f64,
-) {
     // ************************************************************************
     // Clear screen and depth buffer
     unsafe {
@@ -92,149 +290,291 @@ fn draw(
         &glm::vec3(1.0 / 4., 1.0 / 4., 0.0),
     );
 
-    let skybox_view = glm::mat3_to_mat4(&glm::mat4_to_mat3(&view));
+    // Only what is inside it needs to be drawn, and lit
+    let view_projection = projection * view;
+    let frustum = Frustum::from_matrix(&view_projection);
+    let in_view = |min: &glm::Vec3, max: &glm::Vec3| -> bool {
+        !frustum_culling || frustum.intersects_aabb(min, max)
+    };
+
+    // *************************************************************************
+    // Upload the lights, the moving one and those of the blocks
+    let viewport_size = (width as u32, height as u32);
+    let mut lights = vec![PointLight::new(&light_position, [1., 1., 1.], 48.)];
+    lights.extend(
+        block_lights
+            .iter()
+            .filter(|light| !frustum_culling || light.is_visible(&frustum)),
+    );
+    let light_count = tiled_lights.update(&lights, viewport_size);
 
-    let skybox_projection: glm::Mat4 =
-        glm::perspective(aspect_ratio, fov, NEAR_DISTANCE, 2.0 * FAR_DISTANCE);
+    // *************************************************************************
+    // Upload everything which is the same for all programs once
+    frame_uniforms.update(
+        &FrameUniforms::new(
+            &view,
+            &projection,
+            &camera_pos,
+            &sun_direction,
+            viewport_size,
+            time,
+            light_count,
+        )
+        .with_fog(Fog::at(&camera_pos, world, block_registry)),
+    );
+    frame_uniforms.bind(FRAME_UNIFORMS_BINDING);
 
     // *************************************************************************
-    // Use raycasting to figure out which cubes to display
-    let mut offsets = raycast(aspect_ratio, fov, camera_pos, camera_ray, &up, chunk);
+    // Find which lights reach each tile of the screen
+    tiled_lights.cull(light_culling_program);
+
+    // *************************************************************************
+    // Bring the level of detail of the sections of the world mesh up to date,
+    // then find which ones are in view and not hidden behind what was drawn in
+    // the previous frame
+    if visibility == Visibility::Meshes {
+        world_mesh.update(world, block_registry, &camera_pos, lod);
+        occlusion.cull(
+            section_culling_program,
+            world_mesh,
+            frustum_culling,
+            occlusion_culling,
+        );
+    }
+
+    // *************************************************************************
+    // Either use raycasting to figure out which cubes to display, then build
+    // the mesh of their visible faces, or take the world mesh, whose opaque
+    // faces are culled above. The translucent faces of the sections in view
+    // are sorted together. Ray marching needs no mesh at all.
+    let mut mesh = match visibility {
+        Visibility::Raycast => {
+            let offsets = raycaster.raycast(
+                aspect_ratio,
+                fov,
+                &camera_pos,
+                &camera_ray,
+                &up,
+                world,
+                block_registry,
+            );
+            mesh_blocks(world, &offsets, block_registry)
+        }
+        Visibility::Meshes => BlockMesh {
+            opaque: vec![],
+            translucent: world_mesh
+                .sections
+                .iter()
+                .filter(|section| in_view(&section.min, &section.max))
+                .flat_map(|section| section.translucent.iter().copied())
+                .collect(),
+        },
+        Visibility::Raymarch => BlockMesh {
+            opaque: vec![],
+            translucent: vec![],
+        },
+    };
+    sort_back_to_front(&mut mesh.translucent, &camera_pos);
 
     // *************************************************************************
     // Add an additional cube to draw the light
     // TODO(Andrea): use a different program to draw this
-    offsets.push(light_position.into());
+    push_cube(
+        &mut mesh.opaque,
+        light_position.into(),
+        GLOWSTONE,
+        block_registry,
+    );
+
+    // The lighting baked from the sky
+    let environment_lighting = match sky_mode {
+        SkyMode::CubeMap => sky_lighting,
+        SkyMode::Atmosphere => atmosphere_lighting,
+    };
 
     // Skybox Program
     {
-        unsafe { gl::DepthMask(gl::FALSE) };
+        // The sky is on the far plane, at the depth the buffer is cleared to
+        unsafe {
+            gl::DepthMask(gl::FALSE);
+            gl::DepthFunc(gl::LEQUAL);
+        };
 
-        skybox_program.use_();
+        // The atmosphere is computed per pixel rather than sampled from the
+        // baked cubemap, so that it stays sharp around the sun
+        let skybox_program = match sky_mode {
+            SkyMode::CubeMap => skybox_program,
+            SkyMode::Atmosphere => atmosphere_program,
+        };
 
-        skybox_program.set_uniform_mat4(0, &skybox_model);
-        skybox_program.set_uniform_mat4(1, &skybox_view);
-        skybox_program.set_uniform_mat4(2, &skybox_projection);
+        skybox_program.use_();
 
-        let sky_cubemap_texture_unit = 7;
-        sky_cubemap_texture.bind(sky_cubemap_texture_unit);
-        skybox_program.set_uniform_sampler(3, sky_cubemap_texture_unit);
+        skybox_program.set_uniform_mat4("model", &skybox_model);
 
-        let cube = skybox_cube();
-        unsafe {
-            gl::NamedBufferSubData(
-                skybox_bo,
-                0,
-                (cube.len() * std::mem::size_of::<VertexUVNormal>()) as GLsizeiptr,
-                cube.as_ptr() as *const GLvoid,
-            )
-        };
+        if sky_mode == SkyMode::CubeMap {
+            let sky_cubemap_texture_unit = 7;
+            sky_cubemap_texture.bind(sky_cubemap_texture_unit);
+            skybox_program.set_uniform_sampler("skybox", sky_cubemap_texture_unit);
+        }
 
-        unsafe { gl::BindVertexArray(skybox_vao) };
-        Vertex::vertex_specification(skybox_vao, skybox_bo);
+        skybox_cube.draw();
 
         unsafe {
-            gl::DrawArrays(gl::TRIANGLES, 0, cube.len() as GLsizei);
+            gl::DepthFunc(gl::LESS);
+            gl::DepthMask(gl::TRUE);
         };
+    }
 
-        unsafe { gl::DepthMask(gl::TRUE) };
+    // Ray marching Program
+    if visibility == Visibility::Raymarch {
+        voxel_volume.draw(
+            raymarch_program,
+            block_textures,
+            environment_lighting,
+            brdf_lut,
+        );
     }
 
-    // Phong Cube Program
+    // PBR Cube Program
     {
         // *************************************************************************
         // Use the program
-        textured_phong_cube_program.use_();
+        textured_pbr_cube_program.use_();
 
         // *************************************************************************
-        // Pass the MVP matrices as uniforms to the shaders
-        textured_phong_cube_program.set_uniform_mat4(0, &model);
-        textured_phong_cube_program.set_uniform_mat4(1, &view);
-        textured_phong_cube_program.set_uniform_mat4(2, &projection);
+        // Pass the model matrix as uniform to the shaders, view and projection
+        // are in the frame uniforms
+        textured_pbr_cube_program.set_uniform_mat4("model", &model);
 
         // *************************************************************************
         // Bind textures and pass them to shaders
 
-        let mossy_cobblestone_texture_unit = 12;
-        mossy_cobblestone_texture.bind(mossy_cobblestone_texture_unit);
-        textured_phong_cube_program.set_uniform_sampler(3, mossy_cobblestone_texture_unit);
+        let block_textures_unit = 12;
+        block_textures.bind(block_textures_unit);
+        textured_pbr_cube_program.set_uniform_sampler("tex", block_textures_unit);
 
-        let sky_cubemap_texture_unit = 7;
-        sky_cubemap_texture.bind(sky_cubemap_texture_unit);
-        textured_phong_cube_program.set_uniform_sampler(6, sky_cubemap_texture_unit);
+        let block_normal_maps_unit = 13;
+        block_normal_maps.bind(block_normal_maps_unit);
+        textured_pbr_cube_program.set_uniform_sampler("normal_maps", block_normal_maps_unit);
 
-        // ************************************************************************
-        // Pass additional data to shaders
+        let block_height_maps_unit = 14;
+        block_height_maps.bind(block_height_maps_unit);
+        textured_pbr_cube_program.set_uniform_sampler("height_maps", block_height_maps_unit);
 
-        textured_phong_cube_program.set_uniform_vec3(4, &camera_pos);
-        textured_phong_cube_program.set_uniform_vec3(5, &light_position);
+        textured_pbr_cube_program.set_uniform_bool("surface_mapping", surface_mapping);
 
-        // *************************************************************************
-        // Add cube vertices to their vbo and vao descriptor, then bind the VAO and
-        // set it up
+        let (irradiance_unit, prefiltered_unit) = (8, 9);
+        environment_lighting.bind(irradiance_unit, prefiltered_unit);
+        textured_pbr_cube_program.set_uniform_sampler("irradiance_map", irradiance_unit);
+        textured_pbr_cube_program.set_uniform_sampler("prefiltered_map", prefiltered_unit);
 
-        let cube = cube();
-        unsafe {
-            gl::NamedBufferSubData(
-                cube_bo,
-                0,
-                (cube.len() * std::mem::size_of::<VertexUVNormal>()) as GLsizeiptr,
-                cube.as_ptr() as *const GLvoid,
-            )
-        };
+        let brdf_lut_unit = 10;
+        brdf_lut.bind(brdf_lut_unit);
+        textured_pbr_cube_program.set_uniform_sampler("brdf_lut", brdf_lut_unit);
 
-        unsafe { gl::BindVertexArray(cube_vao) };
-        VertexUVNormal::vertex_specification(cube_vao, cube_bo);
+        // *************************************************************************
+        // Upload the mesh vertices to their vbo, the translucent ones after
+        // the opaque ones, then bind the VAO which was set up to read from it
+        let opaque_count = mesh.opaque.len();
+        let mut vertices = mesh.opaque;
+        vertices.append(&mut mesh.translucent);
+        mesh_bo.upload(&vertices);
+        mesh_vao.bind();
 
         // ************************************************************************
-        // Create cubes offsets buffer and bind them to be used for instanced drawing
-        let mut offsets_bo = 0;
+        // Draw
 
+        textured_pbr_cube_program.set_uniform_bool("translucent", false);
+        if visibility == Visibility::Meshes {
+            occlusion.draw(world_mesh);
+            mesh_vao.bind();
+        }
         unsafe {
-            gl::CreateBuffers(1, &mut offsets_bo);
-            gl::BindBuffer(gl::ARRAY_BUFFER, offsets_bo);
-            gl::BufferData(
-                gl::ARRAY_BUFFER,
-                (offsets.len() * std::mem::size_of::<[GLfloat; 3]>()) as GLsizeiptr,
-                offsets.as_ptr() as *const GLvoid,
-                gl::DYNAMIC_DRAW,
-            );
-
-            let location = 3;
-            // layout (location = 3) in vec4 view_offset;
-            gl::EnableVertexArrayAttrib(cube_vao, location);
-            gl::VertexAttribPointer(
-                location,
-                3,
-                gl::FLOAT,
-                gl::FALSE,
-                (3 * std::mem::size_of::<f32>()) as GLsizei,
-                std::ptr::null(),
-            );
-
-            gl::BindBuffer(gl::ARRAY_BUFFER, 0);
-
-            gl::VertexAttribDivisor(location, 1);
+            gl::DrawArrays(gl::TRIANGLES, 0, opaque_count as GLsizei);
         }
 
-        // ************************************************************************
-        // Draw Instanced
-
+        // The translucent faces are blended over what is behind them, seen
+        // from both sides, and do not hide each other
+        textured_pbr_cube_program.set_uniform_bool("translucent", true);
         unsafe {
-            gl::DrawArraysInstanced(
+            gl::Enable(gl::BLEND);
+            gl::BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            gl::DepthMask(gl::FALSE);
+            gl::Disable(gl::CULL_FACE);
+
+            gl::DrawArrays(
                 gl::TRIANGLES,
-                0,
-                cube.len() as GLsizei,
-                offsets.len() as GLsizei,
+                opaque_count as GLint,
+                (mesh_bo.len() - opaque_count) as GLsizei,
             );
 
-            gl::DeleteBuffers(1, &mut offsets_bo);
+            gl::Enable(gl::CULL_FACE);
+            gl::DepthMask(gl::TRUE);
+            gl::Disable(gl::BLEND);
         }
     }
+
+    view_projection
+}
+
+// A pack which cannot be opened is a mistake of the user, not a bug
+fn open_resource_pack(path: &Path) -> ResourcePack {
+    ResourcePack::open(path).unwrap_or_else(|e| {
+        eprintln!("Could not open the resource pack {}", e);
+        std::process::exit(1)
+    })
+}
+
+// Parses WIDTHxHEIGHT
+fn parse_resolution(resolution: &str) -> Option<(u32, u32)> {
+    let mut dimensions = resolution
+        .split('x')
+        .map(|dimension| dimension.parse().ok());
+    match (dimensions.next()?, dimensions.next()?, dimensions.next()) {
+        (Some(width), Some(height), None) if width > 0 && height > 0 => Some((width, height)),
+        _ => None,
+    }
 }
 
 fn main() {
-    let chunk = Chunk::new();
+    // *************************************************************************
+    // Load the base resource pack, then the ones given on the command line on
+    // top of it, the last one taking precedence
+    let mut hot_reload = false;
+    let mut raycast_resolution = RAYCAST_RESOLUTION;
+    let mut storage = Storage::Dense;
+    let mut resource_packs = ResourcePacks::new(open_resource_pack(&base_pack_path()));
+    for argument in std::env::args().skip(1) {
+        match argument.as_str() {
+            "--hot-reload" => hot_reload = true,
+            "--sparse" => storage = Storage::Sparse,
+            argument if argument.starts_with("--raycast-resolution=") => {
+                let resolution = &argument["--raycast-resolution=".len()..];
+                raycast_resolution = parse_resolution(resolution).unwrap_or_else(|| {
+                    eprintln!(
+                        "The raycast resolution should look like 320x180, not `{}`",
+                        resolution
+                    );
+                    std::process::exit(1)
+                })
+            }
+            path => resource_packs.push(open_resource_pack(Path::new(path))),
+        }
+    }
+    for pack in resource_packs.packs() {
+        println!(
+            "Resource pack: {} ({})",
+            pack.manifest.name, pack.manifest.description
+        );
+    }
+
+    // Like a pack which cannot be opened, a broken structure in a pack is a
+    // mistake of the user
+    let structures = Structures::load(&resource_packs).unwrap_or_else(|e| {
+        eprintln!("Could not load the structure {}", e);
+        std::process::exit(1)
+    });
+    let world = World::new(storage, &structures);
 
     // *************************************************************************
     // Setup window
@@ -273,148 +613,222 @@ fn main() {
     unsafe { gl::FrontFace(gl::CCW) };
     unsafe { gl::CullFace(gl::BACK) };
 
-    // *************************************************************************
-    // Create VBOs for a single cube's vertices
-    let mut cube_bo = 0;
-    unsafe {
-        gl::CreateBuffers(1, &mut cube_bo);
-        gl::NamedBufferData(
-            cube_bo,
-            (cube().len() * std::mem::size_of::<VertexUVNormal>()) as GLsizeiptr,
-            std::ptr::null(),
-            gl::DYNAMIC_DRAW,
-        )
-    };
-
-    let mut skybox_bo = 0;
-    unsafe {
-        gl::CreateBuffers(1, &mut skybox_bo);
-        gl::NamedBufferData(
-            skybox_bo,
-            (cube().len() * std::mem::size_of::<VertexUVNormal>()) as GLsizeiptr,
-            std::ptr::null(),
-            gl::DYNAMIC_DRAW,
-        )
-    };
+    // Filter across the faces of the cubemaps, the blurry mip levels of the
+    // prefiltered environment would show the seams otherwise
+    unsafe { gl::Enable(gl::TEXTURE_CUBE_MAP_SEAMLESS) };
 
     // *************************************************************************
-    // Create VAOs
-    let mut cube_vao = 0;
-    unsafe { gl::CreateVertexArrays(1, &mut cube_vao) };
-
-    let mut skybox_vao = 0;
-    unsafe { gl::CreateVertexArrays(1, &mut skybox_vao) };
+    // Create the VBO for the chunk mesh, whose contents are replaced every
+    // frame, and its VAO. The skybox cube has its own, which never changes.
+    let mesh_bo = Buffer::<BlockVertex>::new(0);
+    let mesh_vao = VertexArray::with_vertex_buffer(&mesh_bo);
+    let skybox_cube = SkyboxCube::new();
 
     // *************************************************************************
     // Create and use shader program
-    let textured_phong_cube_program = {
-        let vertex_shader = Shader::from_source(
-            &CString::new(include_str!(concat!(
-                env!("CARGO_MANIFEST_DIR"),
-                "/shaders/cube/cube.vert.glsl"
-            )))
-            .unwrap(),
-            gl::VERTEX_SHADER,
-        )
-        .unwrap();
-        let fragment_shader = Shader::from_source(
-            &CString::new(include_str!(concat!(
-                env!("CARGO_MANIFEST_DIR"),
-                "/shaders/cube/cube.frag.glsl"
-            )))
-            .unwrap(),
-            gl::FRAGMENT_SHADER,
-        )
-        .unwrap();
-        program::Program::new(vec![
-            (vertex_shader, gl::VERTEX_SHADER),
-            (fragment_shader, gl::FRAGMENT_SHADER),
-        ])
-        .unwrap()
-    };
+    // Added by the preprocessor to every shader
+    let shader_defines = [
+        (
+            "ENVIRONMENT_REFLECTIONS",
+            (ENVIRONMENT_REFLECTIONS as u32).to_string(),
+        ),
+        ("FRAME_UNIFORMS_BINDING", FRAME_UNIFORMS_BINDING.to_string()),
+        ("LIGHTS_BINDING", LIGHTS_BINDING.to_string()),
+        ("TILE_LIGHTS_BINDING", TILE_LIGHTS_BINDING.to_string()),
+        ("TILE_SIZE", TILE_SIZE.to_string()),
+        ("MAX_LIGHTS_PER_TILE", MAX_LIGHTS_PER_TILE.to_string()),
+        ("MATERIALS_BINDING", MATERIALS_BINDING.to_string()),
+        ("PREFILTERED_MIP_LEVELS", PREFILTERED_MIP_LEVELS.to_string()),
+        ("FOG_FADE_START", FOG_FADE_START.to_string()),
+        ("SECTIONS_BINDING", SECTIONS_BINDING.to_string()),
+        ("DRAW_COMMANDS_BINDING", DRAW_COMMANDS_BINDING.to_string()),
+        ("CULL_WORK_GROUP_SIZE", CULL_WORK_GROUP_SIZE.to_string()),
+        ("HI_Z_WORK_GROUP_SIZE", HI_Z_WORK_GROUP_SIZE.to_string()),
+        ("VOXEL_BLOCKS_BINDING", VOXEL_BLOCKS_BINDING.to_string()),
+        ("FAR_DISTANCE", FAR_DISTANCE.to_string()),
+    ];
+
+    let mut programs = Programs::load(&resource_packs, &shader_defines).unwrap();
+    let mut post_processing = PostProcessing::new(
+        &resource_packs,
+        &shader_defines,
+        INITIAL_WIDTH,
+        INITIAL_HEIGHT,
+    )
+    .unwrap();
+
+    programs.cube.check_attributes(BlockVertex::ATTRIBUTES);
+    programs.skybox.check_attributes(PositionVertex::ATTRIBUTES);
+    programs
+        .atmosphere
+        .check_attributes(PositionVertex::ATTRIBUTES);
+    programs
+        .irradiance
+        .check_attributes(PositionVertex::ATTRIBUTES);
+    programs
+        .prefilter
+        .check_attributes(PositionVertex::ATTRIBUTES);
+    for program in [
+        &programs.cube,
+        &programs.skybox,
+        &programs.atmosphere,
+        &programs.light_culling,
+        &programs.section_culling,
+        &programs.raymarch,
+        &programs.irradiance,
+        &programs.prefilter,
+    ]
+    .iter()
+    {
+        program.check_uniform_block("Frame", std::mem::size_of::<FrameUniforms>());
+    }
 
-    let skybox_program = {
-        let vertex_shader = Shader::from_source(
-            &CString::new(include_str!(concat!(
-                env!("CARGO_MANIFEST_DIR"),
-                "/shaders/skybox/skybox.vert.glsl"
-            )))
-            .unwrap(),
-            gl::VERTEX_SHADER,
-        )
-        .unwrap();
-        let fragment_shader = Shader::from_source(
-            &CString::new(include_str!(concat!(
-                env!("CARGO_MANIFEST_DIR"),
-                "/shaders/skybox/skybox.frag.glsl"
-            )))
-            .unwrap(),
-            gl::FRAGMENT_SHADER,
-        )
-        .unwrap();
-        program::Program::new(vec![
-            (vertex_shader, gl::VERTEX_SHADER),
-            (fragment_shader, gl::FRAGMENT_SHADER),
-        ])
-        .unwrap()
+    // Shared by all the programs, updated every frame
+    let frame_uniforms = UniformBuffer::<FrameUniforms>::new();
+
+    // In development mode, recompile the programs whenever a shader changes
+    let mut shader_watcher = if hot_reload {
+        Some(ShaderWatcher::new(&resource_packs))
+    } else {
+        None
     };
 
     // *************************************************************************
     // Create textures
-    let mossy_cobblestone_texture = Texture2D::new(
-        image::open(concat!(
-            env!("CARGO_MANIFEST_DIR"),
-            "/textures/mossy_cobblestone.png"
-        ))
-        .unwrap()
-        .to_rgb(),
-    );
+    let block_registry = BlockRegistry::new();
+    // With an alpha channel for the transparent blocks
+    let block_images: Vec<RgbaImage> = block_registry
+        .texture_files()
+        .iter()
+        .map(|file| {
+            resource_packs
+                .image(&format!("textures/{}", file))
+                .unwrap()
+                .to_rgba()
+        })
+        .collect();
+    let (block_texture_width, block_texture_height) = block_images[0].dimensions();
+    let block_textures = TextureArray::new(block_images, gl::SRGB8_ALPHA8);
+
+    // The normal and height maps of each surface layer. The faces without a
+    // height map are flat, and those without a normal map get one derived from
+    // their height map
+    let load_block_image = |file: &str| {
+        resource_packs
+            .image(&format!("textures/{}", file))
+            .unwrap()
+            .to_rgb()
+    };
+    let mut normal_map_images = vec![];
+    let mut height_map_images = vec![];
+    for surface in block_registry.surface_files() {
+        let height_map = match surface.height_map {
+            Some(file) => load_block_image(file),
+            None => RgbImage::from_pixel(
+                block_texture_width,
+                block_texture_height,
+                Rgb([255, 255, 255]),
+            ),
+        };
+        let normal_map = match surface.normal_map {
+            Some(file) => load_block_image(file),
+            None => normal_map_from_height(&height_map, NORMAL_MAP_STRENGTH),
+        };
+        normal_map_images.push(normal_map);
+        height_map_images.push(height_map);
+    }
+    let block_normal_maps = TextureArray::new(normal_map_images, gl::RGB8);
+    let block_height_maps = TextureArray::new(height_map_images, gl::RGB8);
+
+    // *************************************************************************
+    // The world never changes, its sections are only meshed again at another
+    // level of detail as the camera moves. Its blocks are uploaded for the ray
+    // marching.
+    let world_mesh = WorldMesh::new(&world, &block_registry);
+    let raycaster = Raycaster::new(raycast_resolution);
+    let occlusion = OcclusionCulling::new(&world_mesh);
+    let voxel_volume = VoxelVolume::new(&world, &block_registry);
+
+    // The material of every block, bound once and for all
+    let materials = Buffer::from_slice(&block_registry.materials());
+    materials.bind_base(gl::SHADER_STORAGE_BUFFER, MATERIALS_BINDING);
 
     // Ignore the rotations and the names, like this is works and that's it
     let sky_cubemap_texture = TextureCubeMap::new([
-        image::open(concat!(
-            env!("CARGO_MANIFEST_DIR"),
-            "/textures/skybox/front.jpg"
-        ))
-        .unwrap()
-        .rotate270()
-        .to_rgb(),
-        image::open(concat!(
-            env!("CARGO_MANIFEST_DIR"),
-            "/textures/skybox/back.jpg"
-        ))
-        .unwrap()
-        .rotate90()
-        .to_rgb(),
-        image::open(concat!(
-            env!("CARGO_MANIFEST_DIR"),
-            "/textures/skybox/right.jpg"
-        ))
-        .unwrap()
-        .rotate180()
-        .to_rgb(),
-        image::open(concat!(
-            env!("CARGO_MANIFEST_DIR"),
-            "/textures/skybox/left.jpg"
-        ))
-        .unwrap()
-        .to_rgb(),
-        image::open(concat!(
-            env!("CARGO_MANIFEST_DIR"),
-            "/textures/skybox/top.jpg"
-        ))
-        .unwrap()
-        .rotate270()
-        .to_rgb(),
-        image::open(concat!(
-            env!("CARGO_MANIFEST_DIR"),
-            "/textures/skybox/bottom.jpg"
-        ))
-        .unwrap()
-        .rotate270()
-        .to_rgb(),
+        resource_packs
+            .image("textures/skybox/front.jpg")
+            .unwrap()
+            .rotate270()
+            .to_rgb(),
+        resource_packs
+            .image("textures/skybox/back.jpg")
+            .unwrap()
+            .rotate90()
+            .to_rgb(),
+        resource_packs
+            .image("textures/skybox/right.jpg")
+            .unwrap()
+            .rotate180()
+            .to_rgb(),
+        resource_packs
+            .image("textures/skybox/left.jpg")
+            .unwrap()
+            .to_rgb(),
+        resource_packs
+            .image("textures/skybox/top.jpg")
+            .unwrap()
+            .rotate270()
+            .to_rgb(),
+        resource_packs
+            .image("textures/skybox/bottom.jpg")
+            .unwrap()
+            .rotate270()
+            .to_rgb(),
     ]);
 
+    // Filled by bake_atmosphere whenever the sun moves
+    let atmosphere_cubemap_texture = TextureCubeMap::empty(ATMOSPHERE_CUBEMAP_SIZE, 1, gl::RGB16F);
+
+    // *************************************************************************
+    // Image based lighting: the skybox never changes so it is only baked once,
+    // the atmosphere every time it is
+    let brdf_lut = bake_brdf_lut(&programs.brdf);
+    let sky_lighting = EnvironmentLighting::new();
+    sky_lighting.bake(
+        &sky_cubemap_texture,
+        &programs.irradiance,
+        &programs.prefilter,
+        &frame_uniforms,
+        &skybox_cube,
+    );
+    let atmosphere_lighting = EnvironmentLighting::new();
+
+    // *************************************************************************
+    // Lights: the blocks emitting light never change, so they are only
+    // collected once
+    let block_lights = block_lights(&world, &block_registry);
+    let tiled_lights = TiledLights::new();
+
+    let mut renderer = Renderer {
+        mesh_bo,
+        mesh_vao,
+        skybox_cube,
+        block_textures,
+        block_normal_maps,
+        block_height_maps,
+        sky_cubemap_texture,
+        sky_lighting,
+        atmosphere_lighting,
+        brdf_lut,
+        frame_uniforms,
+        tiled_lights,
+        block_lights,
+        world_mesh,
+        voxel_volume,
+        raycaster,
+        occlusion,
+    };
+
     // *************************************************************************
     // Camera, event handling, and main loop
 
@@ -430,6 +844,15 @@ fn main() {
 
     let mut time = 0.0;
 
+    let mut sky_mode = SkyMode::CubeMap;
+    let mut surface_mapping = true;
+    let mut post_settings = PostSettings::new();
+    let mut visibility = Visibility::Meshes;
+    let mut frustum_culling = true;
+    let mut occlusion_culling = true;
+    let mut lod = true;
+    let mut last_baked_sun_direction: Option<glm::Vec3> = None;
+
     while !window.should_close() {
         glfw.poll_events();
         for (_, event) in glfw::flush_messages(&events) {
@@ -502,6 +925,18 @@ fn main() {
                         Key::C => {
                             last_camera_pos -= up;
                         }
+                        Key::K if action == Action::Press => sky_mode = sky_mode.toggle(),
+                        Key::N if action == Action::Press => surface_mapping = !surface_mapping,
+                        Key::B if action == Action::Press => post_settings.toggle_bloom(),
+                        Key::R if action == Action::Press => visibility = visibility.toggle(),
+                        Key::F if action == Action::Press => frustum_culling = !frustum_culling,
+                        Key::O if action == Action::Press => occlusion_culling = !occlusion_culling,
+                        Key::L if action == Action::Press => lod = !lod,
+                        Key::T if action == Action::Press => {
+                            post_settings.tone_mapper = post_settings.tone_mapper.toggle()
+                        }
+                        Key::Equal => post_settings.exposure *= EXPOSURE_STEP,
+                        Key::Minus => post_settings.exposure /= EXPOSURE_STEP,
                         Key::Escape => window.set_should_close(true),
                         _ => (),
                     }
@@ -510,24 +945,82 @@ fn main() {
             }
         }
 
+        if let Some(shader_watcher) = &mut shader_watcher {
+            if shader_watcher.poll() {
+                programs.reload(&resource_packs, &shader_defines);
+                post_processing.reload(&resource_packs, &shader_defines);
+                // The atmosphere and the baked lighting might look different
+                // now
+                renderer.brdf_lut = bake_brdf_lut(&programs.brdf);
+                renderer.sky_lighting.bake(
+                    &renderer.sky_cubemap_texture,
+                    &programs.irradiance,
+                    &programs.prefilter,
+                    &renderer.frame_uniforms,
+                    &renderer.skybox_cube,
+                );
+                last_baked_sun_direction = None;
+            }
+        }
+
+        let sun_direction = sun_direction(time);
+
+        // Re-bake the atmosphere once the sun has moved by more than half a
+        // degree since the last time
+        if sky_mode == SkyMode::Atmosphere {
+            let needs_baking = match last_baked_sun_direction {
+                Some(last) => glm::dot(&last, &sun_direction) < 0.5f32.to_radians().cos(),
+                None => true,
+            };
+            if needs_baking {
+                bake_atmosphere(
+                    &programs.atmosphere,
+                    &renderer.frame_uniforms,
+                    &renderer.skybox_cube,
+                    &sun_direction,
+                    &atmosphere_cubemap_texture,
+                    ATMOSPHERE_CUBEMAP_SIZE,
+                );
+                renderer.atmosphere_lighting.bake(
+                    &atmosphere_cubemap_texture,
+                    &programs.irradiance,
+                    &programs.prefilter,
+                    &renderer.frame_uniforms,
+                    &renderer.skybox_cube,
+                );
+                last_baked_sun_direction = Some(sun_direction);
+            }
+        }
+
         measure_elapsed(|| {
-            draw(
-                &last_camera_ray,
-                &last_camera_pos,
-                &up,
-                last_width as f64,
-                last_height as f64,
-                cube_vao,
-                cube_bo,
-                skybox_vao,
-                skybox_bo,
-                &mossy_cobblestone_texture,
-                &sky_cubemap_texture,
-                &textured_phong_cube_program,
-                &skybox_program,
-                &chunk,
+            post_processing.begin_scene(last_width, last_height);
+            let settings = FrameSettings {
+                camera_pos: last_camera_pos,
+                camera_ray: last_camera_ray,
+                up,
+                width: last_width as f64,
+                height: last_height as f64,
+                sky_mode,
+                sun_direction,
+                surface_mapping,
+                visibility,
+                frustum_culling,
+                occlusion_culling,
+                lod,
                 time,
-            );
+            };
+            let view_projection =
+                draw(&mut renderer, &programs, &world, &block_registry, &settings);
+            // The next frame is culled against the depth of this one
+            if visibility == Visibility::Meshes && occlusion_culling {
+                renderer.occlusion.build_hi_z(
+                    &programs.hi_z,
+                    post_processing.scene_depth(),
+                    (last_width, last_height),
+                    &view_projection,
+                );
+            }
+            post_processing.finish(&post_settings);
         });
 
         time += 1.0;
@@ -535,3 +1028,20 @@ fn main() {
         window.swap_buffers()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_resolutions() {
+        assert_eq!(parse_resolution("320x180"), Some((320, 180)));
+        assert_eq!(parse_resolution("x180"), None);
+        assert_eq!(parse_resolution("320x"), None);
+        assert_eq!(parse_resolution("0x0"), None);
+        assert_eq!(parse_resolution("320x180x2"), None);
+        assert_eq!(parse_resolution("-320x180"), None);
+        assert_eq!(parse_resolution("garbage"), None);
+        assert_eq!(parse_resolution(""), None);
+    }
+}
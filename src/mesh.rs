@@ -0,0 +1,671 @@
+use gl::types::*;
+
+use crate::arena::MeshArena;
+use crate::block::{BlockRegistry, Face, Transparency, FACES};
+use crate::buffer::Buffer;
+use crate::chunk::{AIR, CHUNK_X_SIZE, CHUNK_Y_SIZE, CHUNK_Z_SIZE};
+use crate::lod::{lod_level, LodGrid, LOD_LEVELS, SKIRT_DEPTH};
+use crate::vertex::BlockVertex;
+use crate::world::World;
+
+// Corners of each face relative to the center of the block, in the order
+// bottom-left, bottom-right, top-right, top-left as seen from outside the
+// block, which makes them counter clockwise
+fn face_corners(face: Face) -> [[GLfloat; 3]; 4] {
+    match face {
+        Face::PositiveX => [
+            [0.5, -0.5, -0.5],
+            [0.5, 0.5, -0.5],
+            [0.5, 0.5, 0.5],
+            [0.5, -0.5, 0.5],
+        ],
+        Face::NegativeX => [
+            [-0.5, 0.5, -0.5],
+            [-0.5, -0.5, -0.5],
+            [-0.5, -0.5, 0.5],
+            [-0.5, 0.5, 0.5],
+        ],
+        Face::PositiveY => [
+            [0.5, 0.5, -0.5],
+            [-0.5, 0.5, -0.5],
+            [-0.5, 0.5, 0.5],
+            [0.5, 0.5, 0.5],
+        ],
+        Face::NegativeY => [
+            [-0.5, -0.5, -0.5],
+            [0.5, -0.5, -0.5],
+            [0.5, -0.5, 0.5],
+            [-0.5, -0.5, 0.5],
+        ],
+        Face::PositiveZ => [
+            [-0.5, -0.5, 0.5],
+            [0.5, -0.5, 0.5],
+            [0.5, 0.5, 0.5],
+            [-0.5, 0.5, 0.5],
+        ],
+        Face::NegativeZ => [
+            [0.5, -0.5, -0.5],
+            [-0.5, -0.5, -0.5],
+            [-0.5, 0.5, -0.5],
+            [0.5, 0.5, -0.5],
+        ],
+    }
+}
+
+// The first row of the images ends up at v = 0, so v grows downwards to keep
+// the textures upright on the side faces
+const FACE_UVS: [[GLfloat; 2]; 4] = [[0., 1.], [1., 1.], [1., 0.], [0., 0.]];
+
+// A face of the cube of side `size` centered on `position`, the texture is
+// repeated once per block
+pub fn push_face(
+    vertices: &mut Vec<BlockVertex>,
+    position: [GLfloat; 3],
+    size: GLfloat,
+    face: Face,
+    layer: GLuint,
+    surface_layer: GLuint,
+    material: GLuint,
+) {
+    let corners = face_corners(face);
+    let n = face.normal();
+    let normal = [n[0] as GLfloat, n[1] as GLfloat, n[2] as GLfloat];
+    // From the bottom-left corner to the bottom-right one, along which u grows
+    let tangent = [
+        corners[1][0] - corners[0][0],
+        corners[1][1] - corners[0][1],
+        corners[1][2] - corners[0][2],
+    ];
+
+    for &i in [0, 1, 2, 2, 3, 0].iter() {
+        let corner = corners[i];
+        vertices.push(BlockVertex::new(
+            [
+                position[0] + corner[0] * size,
+                position[1] + corner[1] * size,
+                position[2] + corner[2] * size,
+            ],
+            [FACE_UVS[i][0] * size, FACE_UVS[i][1] * size],
+            normal,
+            tangent,
+            layer,
+            surface_layer,
+            material,
+        ));
+    }
+}
+
+pub fn push_cube(
+    vertices: &mut Vec<BlockVertex>,
+    position: [GLfloat; 3],
+    block: GLuint,
+    registry: &BlockRegistry,
+) {
+    let layers = registry.face_layers(block);
+    let surface_layers = registry.face_surface_layers(block);
+    for (i, &face) in FACES.iter().enumerate() {
+        push_face(
+            vertices,
+            position,
+            1.,
+            face,
+            layers[i],
+            surface_layers[i],
+            block,
+        );
+    }
+}
+
+// A face is hidden by an opaque neighbour, and between two transparent blocks
+// of the same kind, like inside a body of water, see hides
+fn face_is_visible(
+    world: &World,
+    registry: &BlockRegistry,
+    block: GLuint,
+    x: GLuint,
+    y: GLuint,
+    z: GLuint,
+    face: Face,
+) -> bool {
+    let n = face.normal();
+    let (nx, ny, nz) = (x as GLint + n[0], y as GLint + n[1], z as GLint + n[2]);
+
+    if !World::contains(nx, ny, nz) {
+        return true;
+    }
+
+    let neighbour = world.get(nx as GLuint, ny as GLuint, nz as GLuint);
+    !hides(registry, block, neighbour)
+}
+
+fn hides(registry: &BlockRegistry, block: GLuint, neighbour: GLuint) -> bool {
+    neighbour != AIR
+        && (registry.transparency(neighbour) == Transparency::Opaque || neighbour == block)
+}
+
+// The faces of a mesh split by how they are drawn
+pub struct BlockMesh {
+    // Opaque and cutout faces, drawn first in any order
+    pub opaque: Vec<BlockVertex>,
+    // Blended over the opaque ones, they have to be sorted back to front
+    pub translucent: Vec<BlockVertex>,
+}
+
+// Builds the faces of the blocks at the given world coordinates which are not
+// hidden by a neighbour, each vertex carrying the texture array layers of its
+// face and its block id
+pub fn mesh_blocks(world: &World, blocks: &[[GLfloat; 3]], registry: &BlockRegistry) -> BlockMesh {
+    let mut opaque = Vec::with_capacity(blocks.len() * 6);
+    let mut translucent = vec![];
+
+    for &position in blocks {
+        let (x, y, z) = (
+            position[0] as GLuint,
+            position[1] as GLuint,
+            position[2] as GLuint,
+        );
+        let block = world.get(x, y, z);
+        let layers = registry.face_layers(block);
+        let surface_layers = registry.face_surface_layers(block);
+        let vertices = match registry.transparency(block) {
+            Transparency::Opaque | Transparency::Cutout => &mut opaque,
+            Transparency::Translucent => &mut translucent,
+        };
+
+        for (i, &face) in FACES.iter().enumerate() {
+            if face_is_visible(world, registry, block, x, y, z, face) {
+                push_face(
+                    vertices,
+                    position,
+                    1.,
+                    face,
+                    layers[i],
+                    surface_layers[i],
+                    block,
+                );
+            }
+        }
+    }
+
+    BlockMesh {
+        opaque,
+        translucent,
+    }
+}
+
+// The chunks are meshed in cubic sections of this many blocks, which are
+// culled one by one
+pub const SECTION_SIZE: GLuint = 16;
+
+// Meshes the opaque and cutout cells of a section of `cells` cells along each
+// axis, starting at the cell `min`. A cell is a cube of `cell_size` blocks,
+// whose block is given by `block`, which must be AIR outside of the world.
+//
+// Past the first level, the faces on the sides of the section which are close
+// to the surface are kept even when they are hidden. Those are the skirts,
+// which cover the gaps between neighbouring sections drawn at different levels
+// of detail, whose surfaces do not quite meet. The first level needs none, the
+// coarser sections next to it have the skirts.
+pub fn mesh_cells(
+    block: impl Fn(i32, i32, i32) -> GLuint,
+    registry: &BlockRegistry,
+    min: [i32; 3],
+    cells: i32,
+    cell_size: u32,
+) -> Vec<BlockVertex> {
+    let mut vertices = vec![];
+    let size = cell_size as GLfloat;
+    // Blocks are centered on integer coordinates, cells on the middle of their
+    // blocks
+    let center_offset = (size - 1.) / 2.;
+    let skirt_cells = if cell_size > 1 {
+        (SKIRT_DEPTH / cell_size).max(1) as i32
+    } else {
+        0
+    };
+
+    for z in min[2]..min[2] + cells {
+        for y in min[1]..min[1] + cells {
+            for x in min[0]..min[0] + cells {
+                let cell = block(x, y, z);
+                // Which includes AIR
+                if registry.transparency(cell) == Transparency::Translucent {
+                    continue;
+                }
+
+                let near_surface = (1..=skirt_cells)
+                    .any(|dz| registry.transparency(block(x, y, z + dz)) != Transparency::Opaque);
+                let position = [
+                    x as GLfloat * size + center_offset,
+                    y as GLfloat * size + center_offset,
+                    z as GLfloat * size + center_offset,
+                ];
+                let layers = registry.face_layers(cell);
+                let surface_layers = registry.face_surface_layers(cell);
+
+                for (i, &face) in FACES.iter().enumerate() {
+                    let n = face.normal();
+                    let neighbour = block(x + n[0], y + n[1], z + n[2]);
+                    // The skirts only go around the sides
+                    let on_side = (0..2).any(|axis| {
+                        let edge = if n[axis] > 0 {
+                            min[axis] + cells - 1
+                        } else {
+                            min[axis]
+                        };
+                        n[axis] != 0 && [x, y][axis] == edge
+                    });
+                    if !hides(registry, cell, neighbour) || (on_side && near_surface) {
+                        push_face(
+                            &mut vertices,
+                            position,
+                            size,
+                            face,
+                            layers[i],
+                            surface_layers[i],
+                            cell,
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    vertices
+}
+
+// The bounding box of a section and where its opaque faces are in the arena.
+// Follows the std430 layout of Section in shaders/occlusion/cull.comp.glsl
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+struct SectionBounds {
+    min: [GLfloat; 3],
+    first_index: GLuint,
+    max: [GLfloat; 3],
+    index_count: GLuint,
+    base_vertex: GLint,
+    _padding: [GLuint; 3],
+}
+
+pub struct Section {
+    pub min: glm::Vec3,
+    pub max: glm::Vec3,
+    // Always at full resolution, sorted along with those of the other sections
+    // every frame
+    pub translucent: Vec<BlockVertex>,
+    // The world coordinates of the first block of the section
+    origin: [i32; 3],
+    // The level of detail its opaque faces are meshed at, None until they are
+    level: Option<usize>,
+}
+
+// Enough for the sections of a few chunks, the arena grows as needed
+const INITIAL_ARENA_FACES: usize = 1 << 16;
+
+// The mesh of the whole world. The opaque faces of every section, at the
+// level of detail it is seen at from the camera, are packed into a single
+// arena, so that they can all be drawn at once by an indirect draw, see
+// occlusion.rs. The sections made only of air are left out.
+pub struct WorldMesh {
+    pub sections: Vec<Section>,
+    section_bounds: Buffer<SectionBounds>,
+    opaque: MeshArena<BlockVertex>,
+    // The levels of detail past the first, which is the world itself, to mesh
+    // the sections again when the camera moves. Each one is an eighth of the
+    // size of the previous one.
+    lod_grids: Vec<LodGrid>,
+}
+
+impl WorldMesh {
+    // Only the translucent faces are meshed here, the opaque ones are meshed
+    // by the first update, once the camera is known
+    pub fn new(world: &World, registry: &BlockRegistry) -> Self {
+        let mut sections = vec![];
+
+        let mut lod_grids = vec![LodGrid::from_world(world, registry)];
+        while lod_grids.len() < LOD_LEVELS - 1 {
+            let coarser = lod_grids.last().unwrap().coarser();
+            lod_grids.push(coarser);
+        }
+
+        for (chunk_x, chunk_y) in World::chunk_positions() {
+            let origin = World::chunk_origin(chunk_x, chunk_y);
+            for section_z in 0..CHUNK_Z_SIZE / SECTION_SIZE {
+                for section_y in 0..CHUNK_Y_SIZE / SECTION_SIZE {
+                    for section_x in 0..CHUNK_X_SIZE / SECTION_SIZE {
+                        let min = [
+                            (origin[0] + section_x * SECTION_SIZE) as i32,
+                            (origin[1] + section_y * SECTION_SIZE) as i32,
+                            (origin[2] + section_z * SECTION_SIZE) as i32,
+                        ];
+
+                        let mut solid = false;
+                        let mut translucent_blocks = vec![];
+                        for z in min[2]..min[2] + SECTION_SIZE as i32 {
+                            for y in min[1]..min[1] + SECTION_SIZE as i32 {
+                                for x in min[0]..min[0] + SECTION_SIZE as i32 {
+                                    let block = world.get(x as GLuint, y as GLuint, z as GLuint);
+                                    if block == AIR {
+                                        continue;
+                                    }
+                                    match registry.transparency(block) {
+                                        Transparency::Opaque | Transparency::Cutout => solid = true,
+                                        Transparency::Translucent => translucent_blocks.push([
+                                            x as GLfloat,
+                                            y as GLfloat,
+                                            z as GLfloat,
+                                        ]),
+                                    }
+                                }
+                            }
+                        }
+                        let translucent =
+                            mesh_blocks(world, &translucent_blocks, registry).translucent;
+
+                        // Without any opaque or cutout block it has no opaque
+                        // face at any level
+                        if !solid && translucent.is_empty() {
+                            continue;
+                        }
+
+                        // The blocks are centered on integer coordinates
+                        let bounds_min =
+                            glm::vec3(min[0] as f32, min[1] as f32, min[2] as f32).add_scalar(-0.5);
+                        sections.push(Section {
+                            min: bounds_min,
+                            max: bounds_min.add_scalar(SECTION_SIZE as f32),
+                            translucent,
+                            origin: min,
+                            level: None,
+                        });
+                    }
+                }
+            }
+        }
+
+        let mut world_mesh = Self {
+            section_bounds: Buffer::new(sections.len()),
+            sections,
+            opaque: MeshArena::new(
+                INITIAL_ARENA_FACES * CORNERS_PER_FACE,
+                INITIAL_ARENA_FACES * VERTICES_PER_FACE,
+            ),
+            lod_grids,
+        };
+        world_mesh.upload_section_bounds();
+        world_mesh
+    }
+
+    // Meshes again the opaque faces of the sections whose level of detail
+    // changed since the last update, replacing their previous mesh in the
+    // arena. Without `lod` every section is at the first level.
+    pub fn update(
+        &mut self,
+        world: &World,
+        registry: &BlockRegistry,
+        camera_position: &glm::Vec3,
+        lod: bool,
+    ) {
+        let mut changed = false;
+        for (i, section) in self.sections.iter_mut().enumerate() {
+            let level = if lod {
+                lod_level(&section.min, &section.max, camera_position)
+            } else {
+                0
+            };
+            if section.level == Some(level) {
+                continue;
+            }
+
+            let faces = if level == 0 {
+                mesh_cells(
+                    |x, y, z| {
+                        if World::contains(x, y, z) {
+                            world.get(x as GLuint, y as GLuint, z as GLuint)
+                        } else {
+                            AIR
+                        }
+                    },
+                    registry,
+                    section.origin,
+                    SECTION_SIZE as i32,
+                    1,
+                )
+            } else {
+                let grid = &self.lod_grids[level - 1];
+                let cell_size = grid.cell_size as i32;
+                mesh_cells(
+                    |x, y, z| grid.get(x, y, z),
+                    registry,
+                    [
+                        section.origin[0] / cell_size,
+                        section.origin[1] / cell_size,
+                        section.origin[2] / cell_size,
+                    ],
+                    SECTION_SIZE as i32 / cell_size,
+                    grid.cell_size,
+                )
+            };
+            let (vertices, indices) = index_faces(&faces);
+            self.opaque.set(i, &vertices, &indices);
+            section.level = Some(level);
+            changed = true;
+        }
+
+        // Setting a mesh can move the others around the arena
+        if changed {
+            self.upload_section_bounds();
+        }
+    }
+
+    fn upload_section_bounds(&mut self) {
+        let section_bounds: Vec<SectionBounds> = self
+            .sections
+            .iter()
+            .enumerate()
+            .map(|(i, section)| {
+                // Nothing is drawn until the section is meshed
+                let (first_index, index_count, base_vertex) = match self.opaque.allocation(i) {
+                    Some(allocation) => (
+                        allocation.first_index as GLuint,
+                        allocation.index_count as GLuint,
+                        allocation.first_vertex as GLint,
+                    ),
+                    None => (0, 0, 0),
+                };
+                SectionBounds {
+                    min: section.min.into(),
+                    first_index,
+                    max: section.max.into(),
+                    index_count,
+                    base_vertex,
+                    _padding: [0; 3],
+                }
+            })
+            .collect();
+        self.section_bounds.upload(&section_bounds);
+    }
+
+    pub fn bind_section_bounds(&self, binding: GLuint) {
+        self.section_bounds
+            .bind_base(gl::SHADER_STORAGE_BUFFER, binding);
+    }
+
+    pub fn bind_opaque(&self) {
+        self.opaque.bind();
+    }
+}
+
+const VERTICES_PER_FACE: usize = 6;
+const CORNERS_PER_FACE: usize = 4;
+
+// Turns faces made of two triangles, as built by push_face, into their four
+// corners and the indices of the triangles, which take less memory
+pub fn index_faces(vertices: &[BlockVertex]) -> (Vec<BlockVertex>, Vec<GLuint>) {
+    let face_count = vertices.len() / VERTICES_PER_FACE;
+    let mut corners = Vec::with_capacity(face_count * CORNERS_PER_FACE);
+    let mut indices = Vec::with_capacity(face_count * VERTICES_PER_FACE);
+    for face in vertices.chunks(VERTICES_PER_FACE) {
+        let first = corners.len() as GLuint;
+        // The triangles are 0, 1, 2 and 2, 3, 0
+        corners.extend_from_slice(&[face[0], face[1], face[2], face[4]]);
+        indices.extend([0, 1, 2, 2, 3, 0].iter().map(|i| first + i));
+    }
+    (corners, indices)
+}
+
+// Orders the faces from the farthest from the camera to the closest, so that
+// each one is blended over those behind it
+pub fn sort_back_to_front(vertices: &mut Vec<BlockVertex>, camera_position: &glm::Vec3) {
+    // The first and third vertices of a face are opposite corners
+    let distance = |face: &[BlockVertex]| {
+        let (a, b) = (face[0].position(), face[2].position());
+        let center = glm::vec3(a[0] + b[0], a[1] + b[1], a[2] + b[2]) / 2.;
+        glm::distance2(&center, camera_position)
+    };
+    let mut faces: Vec<&[BlockVertex]> = vertices.chunks(VERTICES_PER_FACE).collect();
+    faces.sort_by(|a, b| distance(b).total_cmp(&distance(a)));
+    *vertices = faces.concat();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::{COBBLESTONE, GLASS, STAINED_GLASS};
+
+    // One coordinate of the center of each face
+    fn face_centers(vertices: &[BlockVertex], axis: usize) -> Vec<GLfloat> {
+        vertices
+            .chunks(VERTICES_PER_FACE)
+            .map(|face| (face[0].position()[axis] + face[2].position()[axis]) / 2.)
+            .collect()
+    }
+
+    #[test]
+    fn splits_the_faces_by_transparency() {
+        let registry = BlockRegistry::new();
+        let mut world = World::empty();
+        let blocks = [
+            ([1, 1, 1], COBBLESTONE),
+            ([3, 1, 1], GLASS),
+            ([5, 1, 1], STAINED_GLASS),
+            ([6, 1, 1], STAINED_GLASS),
+        ];
+        for &([x, y, z], block) in blocks.iter() {
+            world.set(x, y, z, block);
+        }
+        let positions: Vec<[GLfloat; 3]> = blocks
+            .iter()
+            .map(|&([x, y, z], _)| [x as GLfloat, y as GLfloat, z as GLfloat])
+            .collect();
+
+        let mesh = mesh_blocks(&world, &positions, &registry);
+        // Every face of the opaque and the cutout block
+        assert_eq!(mesh.opaque.len(), 2 * 6 * VERTICES_PER_FACE);
+        assert!(face_centers(&mesh.opaque, 0)
+            .iter()
+            .all(|&x| (0.5..=1.5).contains(&x) || (2.5..=3.5).contains(&x)));
+        // The two translucent blocks hide the face between them
+        assert_eq!(mesh.translucent.len(), 10 * VERTICES_PER_FACE);
+        assert!(face_centers(&mesh.translucent, 0)
+            .iter()
+            .all(|&x| (4.5..=6.5).contains(&x)));
+    }
+
+    #[test]
+    fn sorts_the_faces_back_to_front() {
+        let camera_position = glm::vec3(10., 0., 0.);
+        let mut vertices = vec![];
+        // Two faces at the same distance from the camera, on either side of
+        // it, and two at different distances
+        for &position in [[10., 4., 0.], [4., 0., 0.], [10., -4., 0.], [0., 0., 0.]].iter() {
+            push_face(
+                &mut vertices,
+                position,
+                1.,
+                Face::PositiveX,
+                0,
+                0,
+                STAINED_GLASS,
+            );
+        }
+
+        sort_back_to_front(&mut vertices, &camera_position);
+        assert_eq!(face_centers(&vertices, 0), [0.5, 4.5, 10.5, 10.5]);
+        // The faces at the same distance keep their order
+        assert_eq!(face_centers(&vertices, 1)[2..], [4., -4.]);
+        // Each face is still made of its own vertices
+        for face in vertices.chunks(VERTICES_PER_FACE) {
+            assert!(face
+                .iter()
+                .all(|vertex| vertex.position()[0] == face[0].position()[0]));
+        }
+    }
+
+    // The lowest and highest corner of each face
+    fn face_bounds(vertices: &[BlockVertex]) -> Vec<([GLfloat; 3], [GLfloat; 3])> {
+        vertices
+            .chunks(VERTICES_PER_FACE)
+            .map(|face| {
+                let mut min = face[0].position();
+                let mut max = min;
+                for vertex in face {
+                    for axis in 0..3 {
+                        min[axis] = min[axis].min(vertex.position()[axis]);
+                        max[axis] = max[axis].max(vertex.position()[axis]);
+                    }
+                }
+                (min, max)
+            })
+            .collect()
+    }
+
+    // Meshes the section at the origin of flat ground, whose surface is at the
+    // top of the section, at the level with cells of `cell_size` blocks
+    fn mesh_ground(cell_size: u32) -> Vec<BlockVertex> {
+        let registry = BlockRegistry::new();
+        let top = (SECTION_SIZE / cell_size) as i32;
+        mesh_cells(
+            |_, _, z| if z < top { COBBLESTONE } else { AIR },
+            &registry,
+            [0, 0, 0],
+            top,
+            cell_size,
+        )
+    }
+
+    #[test]
+    fn the_first_level_has_no_skirts() {
+        let faces = face_bounds(&mesh_ground(1));
+        // Only the top of the ground
+        assert_eq!(faces.len(), (SECTION_SIZE * SECTION_SIZE) as usize);
+        let top = SECTION_SIZE as GLfloat - 0.5;
+        assert!(faces
+            .iter()
+            .all(|(min, max)| min[2] == top && max[2] == top));
+    }
+
+    #[test]
+    fn coarser_levels_have_skirts_on_their_sides() {
+        let edges = [-0.5, SECTION_SIZE as GLfloat - 0.5];
+        let top = SECTION_SIZE as GLfloat - 0.5;
+        for level in 1..LOD_LEVELS {
+            let cell_size = 1 << level;
+            let cells = SECTION_SIZE / cell_size;
+            let faces = face_bounds(&mesh_ground(cell_size));
+
+            let (tops, sides): (Vec<_>, Vec<_>) =
+                faces.iter().partition(|(min, max)| min[2] == max[2]);
+            assert_eq!(tops.len(), (cells * cells) as usize);
+            // SKIRT_DEPTH blocks of faces down each of the four sides
+            assert_eq!(sides.len(), (4 * cells * SKIRT_DEPTH / cell_size) as usize);
+            for (min, max) in sides {
+                let x_side = min[0] == max[0] && edges.contains(&min[0]);
+                let y_side = min[1] == max[1] && edges.contains(&min[1]);
+                assert!(x_side || y_side, "{:?}", (min, max));
+                assert!(min[2] >= top - SKIRT_DEPTH as GLfloat);
+                assert!(max[2] <= top);
+            }
+        }
+    }
+}
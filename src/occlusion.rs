@@ -0,0 +1,185 @@
+use gl::types::*;
+
+use crate::buffer::Buffer;
+use crate::mesh::WorldMesh;
+use crate::program::Program;
+use crate::texture::Texture2D;
+
+// Shader storage buffer bindings, passed to the shaders as defines
+pub const SECTIONS_BINDING: GLuint = 4;
+pub const DRAW_COMMANDS_BINDING: GLuint = 5;
+
+// Sections tested by each work group of shaders/occlusion/cull.comp.glsl
+pub const CULL_WORK_GROUP_SIZE: u32 = 64;
+// Side in texels of the square tiles of a Hi-Z level written by each work
+// group of shaders/occlusion/hi_z.comp.glsl
+pub const HI_Z_WORK_GROUP_SIZE: u32 = 8;
+
+// The layout glMultiDrawElementsIndirect expects, and the std430 layout of
+// DrawCommand in shaders/occlusion/cull.comp.glsl
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+struct DrawCommand {
+    count: GLuint,
+    instance_count: GLuint,
+    first_index: GLuint,
+    base_vertex: GLint,
+    base_instance: GLuint,
+}
+
+// Decides on the GPU which sections of the world mesh to draw, without reading
+// anything back. A compute shader tests the box of every section against the
+// frustum, and against a hierarchical Z-buffer (Hi-Z) built from the depth of
+// the previous frame, then writes one indirect draw command per section, with
+// no instance when it is hidden.
+pub struct OcclusionCulling {
+    draw_commands: Buffer<DrawCommand>,
+    // Each level holds the farthest depth of the texels of the previous one it
+    // covers, the first being a copy of the depth buffer
+    hi_z: Texture2D,
+    hi_z_size: (u32, u32),
+    hi_z_levels: u32,
+    // What the depth in the Hi-Z was drawn with, None until it has been built
+    hi_z_view_projection: Option<glm::Mat4>,
+}
+
+impl OcclusionCulling {
+    pub fn new(world_mesh: &WorldMesh) -> Self {
+        // One per section, filled in by the compute shader
+        let draw_command = DrawCommand {
+            count: 0,
+            instance_count: 0,
+            first_index: 0,
+            base_vertex: 0,
+            base_instance: 0,
+        };
+        Self {
+            draw_commands: Buffer::from_slice(&vec![draw_command; world_mesh.sections.len()]),
+            hi_z: Texture2D::empty(1, 1, gl::R32F),
+            hi_z_size: (0, 0),
+            hi_z_levels: 1,
+            hi_z_view_projection: None,
+        }
+    }
+
+    // Writes the draw commands of the sections visible in the current frame,
+    // the Frame uniforms must be up to date. Until there is a Hi-Z from a
+    // previous frame only the frustum is used.
+    pub fn cull(
+        &self,
+        cull_program: &Program,
+        world_mesh: &WorldMesh,
+        frustum_culling: bool,
+        occlusion_culling: bool,
+    ) {
+        cull_program.use_();
+        let hi_z_unit = 0;
+        self.hi_z.bind(hi_z_unit);
+        cull_program.set_uniform_sampler("hi_z", hi_z_unit);
+        cull_program.set_uniform_mat4(
+            "previous_view_projection",
+            &self.hi_z_view_projection.unwrap_or_else(glm::identity),
+        );
+        cull_program.set_uniform_bool("frustum_culling", frustum_culling);
+        cull_program.set_uniform_bool(
+            "occlusion_culling",
+            occlusion_culling && self.hi_z_view_projection.is_some(),
+        );
+
+        world_mesh.bind_section_bounds(SECTIONS_BINDING);
+        self.draw_commands
+            .bind_base(gl::SHADER_STORAGE_BUFFER, DRAW_COMMANDS_BINDING);
+        unsafe {
+            gl::DispatchCompute(
+                (self.draw_commands.len() as u32).div_ceil(CULL_WORK_GROUP_SIZE),
+                1,
+                1,
+            );
+            // The draw commands are read by the indirect draw
+            gl::MemoryBarrier(gl::COMMAND_BARRIER_BIT);
+        }
+    }
+
+    // Draws the opaque faces of the sections which passed the last cull in a
+    // single call, the program drawing them must be in use
+    pub fn draw(&self, world_mesh: &WorldMesh) {
+        world_mesh.bind_opaque();
+        unsafe {
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, self.draw_commands.id());
+            gl::MultiDrawElementsIndirect(
+                gl::TRIANGLES,
+                gl::UNSIGNED_INT,
+                std::ptr::null(),
+                self.draw_commands.len() as GLsizei,
+                0,
+            );
+            gl::BindBuffer(gl::DRAW_INDIRECT_BUFFER, 0);
+        }
+    }
+
+    // Builds the Hi-Z from the depth buffer of the frame which was just drawn
+    // with `view_projection`, for the next frame to be culled against
+    pub fn build_hi_z(
+        &mut self,
+        hi_z_program: &Program,
+        depth: &Texture2D,
+        (width, height): (u32, u32),
+        view_projection: &glm::Mat4,
+    ) {
+        if self.hi_z_size != (width, height) {
+            self.hi_z_size = (width, height);
+            self.hi_z_levels = 32 - width.max(height).leading_zeros();
+            self.hi_z = Texture2D::empty_with_levels(width, height, self.hi_z_levels, gl::R32F);
+            unsafe {
+                gl::TextureParameteri(
+                    self.hi_z.name(),
+                    gl::TEXTURE_MIN_FILTER,
+                    gl::NEAREST_MIPMAP_NEAREST as GLint,
+                );
+                gl::TextureParameteri(
+                    self.hi_z.name(),
+                    gl::TEXTURE_MAG_FILTER,
+                    gl::NEAREST as GLint,
+                );
+            }
+        }
+
+        hi_z_program.use_();
+        let (source_unit, destination_unit) = (0, 0);
+        hi_z_program.set_uniform_sampler("source", source_unit);
+        hi_z_program.set_uniform_image("destination", destination_unit);
+        for level in 0..self.hi_z_levels {
+            // The first level reads the depth buffer, the others the level
+            // before them
+            if level == 0 {
+                depth.bind(source_unit);
+                hi_z_program.set_uniform_int("source_level", 0);
+            } else {
+                self.hi_z.bind(source_unit);
+                hi_z_program.set_uniform_int("source_level", level as GLint - 1);
+            }
+
+            let (level_width, level_height) = ((width >> level).max(1), (height >> level).max(1));
+            unsafe {
+                gl::BindImageTexture(
+                    destination_unit,
+                    self.hi_z.name(),
+                    level as GLint,
+                    gl::FALSE,
+                    0,
+                    gl::WRITE_ONLY,
+                    gl::R32F,
+                );
+                gl::DispatchCompute(
+                    level_width.div_ceil(HI_Z_WORK_GROUP_SIZE),
+                    level_height.div_ceil(HI_Z_WORK_GROUP_SIZE),
+                    1,
+                );
+                // The next level, and then the culling, read this one
+                gl::MemoryBarrier(gl::TEXTURE_FETCH_BARRIER_BIT);
+            }
+        }
+
+        self.hi_z_view_projection = Some(*view_projection);
+    }
+}
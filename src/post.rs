@@ -0,0 +1,277 @@
+use gl::types::*;
+
+use crate::buffer::VertexArray;
+use crate::framebuffer::Framebuffer;
+use crate::hot_reload::reload;
+use crate::program::Program;
+use crate::resource_pack::ResourcePacks;
+use crate::texture::Texture2D;
+
+const BRIGHT_PASS_SHADERS: [(&str, GLenum); 2] = [
+    ("shaders/fullscreen.vert.glsl", gl::VERTEX_SHADER),
+    ("shaders/post/bright_pass.frag.glsl", gl::FRAGMENT_SHADER),
+];
+const BLUR_SHADERS: [(&str, GLenum); 2] = [
+    ("shaders/fullscreen.vert.glsl", gl::VERTEX_SHADER),
+    ("shaders/post/blur.frag.glsl", gl::FRAGMENT_SHADER),
+];
+const COMPOSITE_SHADERS: [(&str, GLenum); 2] = [
+    ("shaders/fullscreen.vert.glsl", gl::VERTEX_SHADER),
+    ("shaders/post/composite.frag.glsl", gl::FRAGMENT_SHADER),
+];
+const TONE_MAPPING_SHADERS: [(&str, GLenum); 2] = [
+    ("shaders/fullscreen.vert.glsl", gl::VERTEX_SHADER),
+    ("shaders/post/tone_mapping.frag.glsl", gl::FRAGMENT_SHADER),
+];
+
+// The scene is rendered with floating point colors, which can go past 1
+const HDR_FORMAT: GLenum = gl::RGBA16F;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum ToneMapper {
+    Reinhard,
+    // The filmic curve fitted by Krzysztof Narkowicz, see
+    // https://knarkowicz.wordpress.com/2016/01/06/aces-filmic-tone-mapping-curve/
+    Aces,
+}
+
+impl ToneMapper {
+    pub fn toggle(self) -> Self {
+        match self {
+            ToneMapper::Reinhard => ToneMapper::Aces,
+            ToneMapper::Aces => ToneMapper::Reinhard,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum PostEffect {
+    // Blurs the parts of the image brighter than `threshold` at half
+    // resolution, then adds them back scaled by `intensity`
+    Bloom {
+        threshold: f32,
+        intensity: f32,
+        // Each pass is a horizontal and a vertical gaussian blur
+        blur_passes: u32,
+    },
+}
+
+pub const DEFAULT_BLOOM: PostEffect = PostEffect::Bloom {
+    threshold: 1.,
+    intensity: 0.3,
+    blur_passes: 4,
+};
+
+pub struct PostSettings {
+    // Applied in order to the HDR image of the scene
+    pub effects: Vec<PostEffect>,
+    // Then the result is scaled by the exposure, tone mapped to [0, 1] and
+    // gamma corrected
+    pub exposure: f32,
+    pub tone_mapper: ToneMapper,
+}
+
+impl PostSettings {
+    pub fn new() -> Self {
+        Self {
+            effects: vec![DEFAULT_BLOOM],
+            exposure: 1.,
+            tone_mapper: ToneMapper::Aces,
+        }
+    }
+
+    pub fn toggle_bloom(&mut self) {
+        let had_bloom = !self.effects.is_empty();
+        self.effects.retain(|effect| match effect {
+            PostEffect::Bloom { .. } => false,
+        });
+        if !had_bloom {
+            self.effects.push(DEFAULT_BLOOM);
+        }
+    }
+}
+
+// Owns the HDR render targets and the programs of the post-processing stack
+pub struct PostProcessing {
+    bright_pass_program: Program,
+    blur_program: Program,
+    composite_program: Program,
+    tone_mapping_program: Program,
+
+    // The vertices of the full screen triangle come from gl_VertexID
+    vertex_array: VertexArray,
+
+    // The scene is drawn to the first, then each effect reads from one and
+    // writes to the other
+    targets: [Framebuffer; 2],
+    // Half resolution, for the bloom
+    bloom_targets: [Framebuffer; 2],
+}
+
+impl PostProcessing {
+    pub fn new(
+        resource_packs: &ResourcePacks,
+        shader_defines: &[(&str, String)],
+        width: u32,
+        height: u32,
+    ) -> Result<Self, String> {
+        let (targets, bloom_targets) = Self::targets(width, height);
+        Ok(Self {
+            bright_pass_program: Program::load(
+                resource_packs,
+                &BRIGHT_PASS_SHADERS,
+                shader_defines,
+            )?,
+            blur_program: Program::load(resource_packs, &BLUR_SHADERS, shader_defines)?,
+            composite_program: Program::load(resource_packs, &COMPOSITE_SHADERS, shader_defines)?,
+            tone_mapping_program: Program::load(
+                resource_packs,
+                &TONE_MAPPING_SHADERS,
+                shader_defines,
+            )?,
+            vertex_array: VertexArray::new(),
+            targets,
+            bloom_targets,
+        })
+    }
+
+    fn targets(width: u32, height: u32) -> ([Framebuffer; 2], [Framebuffer; 2]) {
+        let (bloom_width, bloom_height) = ((width / 2).max(1), (height / 2).max(1));
+        (
+            [
+                Framebuffer::new(width, height, HDR_FORMAT, true),
+                Framebuffer::new(width, height, HDR_FORMAT, false),
+            ],
+            [
+                Framebuffer::new(bloom_width, bloom_height, HDR_FORMAT, false),
+                Framebuffer::new(bloom_width, bloom_height, HDR_FORMAT, false),
+            ],
+        )
+    }
+
+    pub fn reload(&mut self, resource_packs: &ResourcePacks, shader_defines: &[(&str, String)]) {
+        reload(
+            &mut self.bright_pass_program,
+            resource_packs,
+            &BRIGHT_PASS_SHADERS,
+            shader_defines,
+        );
+        reload(
+            &mut self.blur_program,
+            resource_packs,
+            &BLUR_SHADERS,
+            shader_defines,
+        );
+        reload(
+            &mut self.composite_program,
+            resource_packs,
+            &COMPOSITE_SHADERS,
+            shader_defines,
+        );
+        reload(
+            &mut self.tone_mapping_program,
+            resource_packs,
+            &TONE_MAPPING_SHADERS,
+            shader_defines,
+        );
+    }
+
+    // Makes the scene be drawn to the HDR target, which follows the size of
+    // the window
+    pub fn begin_scene(&mut self, width: u32, height: u32) {
+        if self.targets[0].size() != (width, height) {
+            let (targets, bloom_targets) = Self::targets(width, height);
+            self.targets = targets;
+            self.bloom_targets = bloom_targets;
+        }
+        self.targets[0].bind();
+    }
+
+    // The depth buffer the scene was drawn with
+    pub fn scene_depth(&self) -> &Texture2D {
+        self.targets[0].depth().unwrap()
+    }
+
+    // Runs the effects on the scene, then tone maps it to the window
+    pub fn finish(&self, settings: &PostSettings) {
+        unsafe { gl::Disable(gl::DEPTH_TEST) };
+        self.vertex_array.bind();
+
+        let mut source = 0;
+        for effect in &settings.effects {
+            match *effect {
+                PostEffect::Bloom {
+                    threshold,
+                    intensity,
+                    blur_passes,
+                } => self.bloom(
+                    &self.targets[source],
+                    &self.targets[1 - source],
+                    threshold,
+                    intensity,
+                    blur_passes,
+                ),
+            }
+            source = 1 - source;
+        }
+
+        let (width, height) = self.targets[source].size();
+        Framebuffer::bind_default(width, height);
+        self.tone_mapping_program.use_();
+        self.targets[source].color().bind(0);
+        self.tone_mapping_program.set_uniform_sampler("source", 0);
+        self.tone_mapping_program
+            .set_uniform_float("exposure", settings.exposure);
+        self.tone_mapping_program
+            .set_uniform_bool("aces", settings.tone_mapper == ToneMapper::Aces);
+        draw_fullscreen_triangle();
+
+        unsafe { gl::Enable(gl::DEPTH_TEST) };
+    }
+
+    fn bloom(
+        &self,
+        source: &Framebuffer,
+        destination: &Framebuffer,
+        threshold: f32,
+        intensity: f32,
+        blur_passes: u32,
+    ) {
+        // Keep only what is bright enough, downsampling it
+        self.bloom_targets[0].bind();
+        self.bright_pass_program.use_();
+        source.color().bind(0);
+        self.bright_pass_program.set_uniform_sampler("source", 0);
+        self.bright_pass_program
+            .set_uniform_float("threshold", threshold);
+        draw_fullscreen_triangle();
+
+        // Blur it back and forth between the two half resolution targets
+        self.blur_program.use_();
+        self.blur_program.set_uniform_sampler("source", 0);
+        for _ in 0..blur_passes {
+            for &horizontal in [true, false].iter() {
+                let (from, to) = if horizontal { (0, 1) } else { (1, 0) };
+                self.bloom_targets[to].bind();
+                self.bloom_targets[from].color().bind(0);
+                self.blur_program.set_uniform_bool("horizontal", horizontal);
+                draw_fullscreen_triangle();
+            }
+        }
+
+        // Add it to the image
+        destination.bind();
+        self.composite_program.use_();
+        source.color().bind(0);
+        self.bloom_targets[0].color().bind(1);
+        self.composite_program.set_uniform_sampler("source", 0);
+        self.composite_program.set_uniform_sampler("bloom", 1);
+        self.composite_program
+            .set_uniform_float("intensity", intensity);
+        draw_fullscreen_triangle();
+    }
+}
+
+fn draw_fullscreen_triangle() {
+    unsafe { gl::DrawArrays(gl::TRIANGLES, 0, 3) };
+}
@@ -0,0 +1,213 @@
+use crate::resource_pack::ResourcePacks;
+
+// A shader after preprocessing, `files[i]` being the file which the #line
+// directives refer to as source string number i
+pub struct Preprocessed {
+    pub source: String,
+    pub files: Vec<String>,
+}
+
+// Looks for `name` next to the including file first, then in shaders/
+fn resolve_include(
+    packs: &ResourcePacks,
+    including: &str,
+    name: &str,
+) -> Result<Option<String>, String> {
+    // Files at the root of the pack have no directory to prepend
+    let sibling = match including.rfind('/') {
+        Some(end) => format!("{}/{}", &including[..end], name),
+        None => name.to_string(),
+    };
+    for path in [sibling, format!("shaders/{}", name)].iter() {
+        if packs.contains(path)? {
+            return Ok(Some(path.clone()));
+        }
+    }
+    Ok(None)
+}
+
+fn include(
+    packs: &ResourcePacks,
+    path: &str,
+    preprocessed: &mut Preprocessed,
+) -> Result<(), String> {
+    let source = packs.read_to_string(path)?;
+
+    let file = preprocessed.files.len();
+    preprocessed.files.push(path.to_string());
+
+    for (i, line) in source.lines().enumerate() {
+        let directive = line.trim_start();
+
+        if directive.starts_with("#version") {
+            // Only allowed at the top of the main file, which takes care of it
+            preprocessed.source.push('\n');
+        } else if let Some(name) = directive.strip_prefix("#include") {
+            let name = name.trim();
+            if !(name.len() >= 2 && name.starts_with('"') && name.ends_with('"')) {
+                return Err(format!("{}:{}: expected #include \"file\"", path, i + 1));
+            }
+            let name = &name[1..name.len() - 1];
+
+            let included = resolve_include(packs, path, name)?
+                .ok_or_else(|| format!("{}:{}: cannot find {}", path, i + 1, name))?;
+
+            // Every file is included at most once
+            if !preprocessed.files.contains(&included) {
+                preprocessed
+                    .source
+                    .push_str(&format!("#line 1 {}\n", preprocessed.files.len()));
+                include(packs, &included, preprocessed)?;
+            }
+            preprocessed
+                .source
+                .push_str(&format!("#line {} {}\n", i + 2, file));
+        } else {
+            preprocessed.source.push_str(line);
+            preprocessed.source.push('\n');
+        }
+    }
+
+    Ok(())
+}
+
+// Resolves the #include "file" directives of the shader at `path`, and adds a
+// #define for each one of `defines` right after its #version.
+// #line directives keep track of the original file and line, so that the
+// GetShaderInfoLog can be rewritten with remap_info_log.
+pub fn preprocess(
+    packs: &ResourcePacks,
+    path: &str,
+    defines: &[(&str, String)],
+) -> Result<Preprocessed, String> {
+    let source = packs.read_to_string(path)?;
+    let version = source
+        .lines()
+        .next()
+        .filter(|line| line.trim_start().starts_with("#version"))
+        .ok_or_else(|| format!("{}:1: expected #version", path))?;
+
+    let mut preprocessed = Preprocessed {
+        source: format!("{}\n", version),
+        files: vec![],
+    };
+    for (name, value) in defines {
+        preprocessed
+            .source
+            .push_str(&format!("#define {} {}\n", name, value));
+    }
+    preprocessed.source.push_str("#line 1 0\n");
+
+    include(packs, path, &mut preprocessed)?;
+
+    Ok(preprocessed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::resource_pack::TempPack;
+
+    #[test]
+    fn includes_nested_files_once() {
+        let pack = TempPack::directory(
+            "preprocessor-nested",
+            &[
+                (
+                    "shaders/main.frag.glsl",
+                    "#version 450\n#include \"lib/light.glsl\"\n#include \"lib/math.glsl\"\nvoid main() {}\n",
+                ),
+                ("shaders/lib/light.glsl", "#include \"math.glsl\"\nfloat light;\n"),
+                ("shaders/lib/math.glsl", "const float PI = 3.14;\n"),
+            ],
+        );
+        let packs = ResourcePacks::new(pack.open());
+        let preprocessed = preprocess(
+            &packs,
+            "shaders/main.frag.glsl",
+            &[("SIZE", "4".to_string())],
+        )
+        .unwrap();
+        assert_eq!(
+            preprocessed.files,
+            [
+                "shaders/main.frag.glsl",
+                "shaders/lib/light.glsl",
+                "shaders/lib/math.glsl"
+            ]
+        );
+        assert_eq!(
+            preprocessed.source,
+            [
+                "#version 450",
+                "#define SIZE 4",
+                "#line 1 0",
+                "",
+                "#line 1 1",
+                "#line 1 2",
+                "const float PI = 3.14;",
+                "#line 2 1",
+                "float light;",
+                "#line 3 0",
+                "#line 4 0",
+                "void main() {}",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn resolves_includes() {
+        let pack = TempPack::directory(
+            "preprocessor-resolve",
+            &[
+                ("root.glsl", ""),
+                ("shaders/common.glsl", ""),
+                ("shaders/sky/sky.glsl", ""),
+            ],
+        );
+        let packs = ResourcePacks::new(pack.open());
+        let resolve = |including, name| resolve_include(&packs, including, name).unwrap();
+        assert_eq!(
+            resolve("top.glsl", "root.glsl"),
+            Some("root.glsl".to_string())
+        );
+        assert_eq!(
+            resolve("shaders/sky/sky.frag.glsl", "sky.glsl"),
+            Some("shaders/sky/sky.glsl".to_string())
+        );
+        assert_eq!(
+            resolve("shaders/sky/sky.frag.glsl", "common.glsl"),
+            Some("shaders/common.glsl".to_string())
+        );
+        assert_eq!(resolve("shaders/sky/sky.frag.glsl", "missing.glsl"), None);
+    }
+
+    #[test]
+    fn rejects_broken_includes() {
+        let pack = TempPack::directory(
+            "preprocessor-broken",
+            &[
+                ("shaders/no_version.glsl", "void main() {}\n"),
+                (
+                    "shaders/missing.glsl",
+                    "#version 450\n#include \"missing.glsl2\"\n",
+                ),
+                (
+                    "shaders/unquoted.glsl",
+                    "#version 450\n#include common.glsl\n",
+                ),
+            ],
+        );
+        let packs = ResourcePacks::new(pack.open());
+        assert!(preprocess(&packs, "shaders/no_version.glsl", &[]).is_err());
+        assert_eq!(
+            preprocess(&packs, "shaders/missing.glsl", &[])
+                .err()
+                .unwrap(),
+            "shaders/missing.glsl:2: cannot find missing.glsl2"
+        );
+        assert!(preprocess(&packs, "shaders/unquoted.glsl", &[]).is_err());
+    }
+}
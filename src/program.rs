@@ -1,10 +1,126 @@
 use gl::types::*;
 
-use crate::shader::Shader;
+use crate::buffer::Buffer;
+use crate::fog::Fog;
+use crate::preprocessor::preprocess;
+use crate::resource_pack::ResourcePacks;
+use crate::shader::{remap_info_log, Shader};
+use crate::vertex::Attribute;
+use std::cell::{Cell, RefCell};
+use std::collections::{HashMap, HashSet};
 use std::ffi::CString;
 
+// An active uniform or attribute, as reported by the driver after linking
+#[derive(Debug, Copy, Clone)]
+pub struct Variable {
+    pub location: GLint,
+    pub type_: GLenum,
+}
+
+const SAMPLER_TYPES: [GLenum; 5] = [
+    gl::SAMPLER_2D,
+    gl::SAMPLER_2D_ARRAY,
+    gl::SAMPLER_3D,
+    gl::SAMPLER_CUBE,
+    gl::UNSIGNED_INT_SAMPLER_3D,
+];
+
+pub fn type_name(type_: GLenum) -> &'static str {
+    match type_ {
+        gl::FLOAT => "float",
+        gl::FLOAT_VEC2 => "vec2",
+        gl::FLOAT_VEC3 => "vec3",
+        gl::FLOAT_VEC4 => "vec4",
+        gl::INT => "int",
+        gl::UNSIGNED_INT => "uint",
+        gl::BOOL => "bool",
+        gl::FLOAT_MAT3 => "mat3",
+        gl::FLOAT_MAT4 => "mat4",
+        gl::SAMPLER_2D => "sampler2D",
+        gl::SAMPLER_2D_ARRAY => "sampler2DArray",
+        gl::SAMPLER_3D => "sampler3D",
+        gl::SAMPLER_CUBE => "samplerCube",
+        gl::IMAGE_2D => "image2D",
+        gl::UNSIGNED_INT_SAMPLER_3D => "usampler3D",
+        _ => "unknown type",
+    }
+}
+
+// Queries either the active uniforms or the active attributes of a program
+unsafe fn active_variables(program: GLuint, attributes: bool) -> HashMap<String, Variable> {
+    let (count, max_length) = if attributes {
+        (gl::ACTIVE_ATTRIBUTES, gl::ACTIVE_ATTRIBUTE_MAX_LENGTH)
+    } else {
+        (gl::ACTIVE_UNIFORMS, gl::ACTIVE_UNIFORM_MAX_LENGTH)
+    };
+    let mut count_value = 0;
+    let mut max_length_value = 0;
+    gl::GetProgramiv(program, count, &mut count_value);
+    gl::GetProgramiv(program, max_length, &mut max_length_value);
+
+    let mut variables = HashMap::new();
+    for index in 0..count_value as GLuint {
+        let mut name = vec![0u8; max_length_value.max(1) as usize];
+        let mut length = 0;
+        let mut size = 0;
+        let mut type_ = 0;
+        if attributes {
+            gl::GetActiveAttrib(
+                program,
+                index,
+                name.len() as GLsizei,
+                &mut length,
+                &mut size,
+                &mut type_,
+                name.as_mut_ptr() as *mut GLchar,
+            );
+        } else {
+            gl::GetActiveUniform(
+                program,
+                index,
+                name.len() as GLsizei,
+                &mut length,
+                &mut size,
+                &mut type_,
+                name.as_mut_ptr() as *mut GLchar,
+            );
+        }
+        name.truncate(length as usize);
+
+        let c_name = CString::new(name.clone()).unwrap();
+        let location = if attributes {
+            gl::GetAttribLocation(program, c_name.as_ptr())
+        } else {
+            gl::GetUniformLocation(program, c_name.as_ptr())
+        };
+
+        // Members of uniform blocks have no location
+        if location < 0 && !attributes {
+            continue;
+        }
+
+        // Arrays are reported as `name[0]`
+        let mut name = String::from_utf8_lossy(&name).to_string();
+        if name.ends_with("[0]") {
+            name.truncate(name.len() - 3);
+        }
+
+        variables.insert(name, Variable { location, type_ });
+    }
+    variables
+}
+
 pub struct Program {
     id: GLuint,
+    // The shaders it was loaded from, to make error messages readable
+    name: String,
+    uniforms: HashMap<String, Variable>,
+    attributes: HashMap<String, Variable>,
+
+    // Used in debug builds to report uniforms which are never set
+    set_uniforms: RefCell<HashSet<String>>,
+    uses: Cell<u32>,
+    reported: RefCell<HashSet<String>>,
 }
 
 impl Program {
@@ -44,14 +160,184 @@ impl Program {
             return Err(error.to_string_lossy().to_string());
         }
 
-        Ok(Program { id: program })
+        let (uniforms, attributes) = unsafe {
+            (
+                active_variables(program, false),
+                active_variables(program, true),
+            )
+        };
+
+        Ok(Program {
+            id: program,
+            name: String::new(),
+            uniforms,
+            attributes,
+            set_uniforms: RefCell::new(HashSet::new()),
+            uses: Cell::new(0),
+            reported: RefCell::new(HashSet::new()),
+        })
+    }
+
+    // Preprocesses, compiles and links the shaders found at the given paths of
+    // the resource packs
+    pub fn load(
+        packs: &ResourcePacks,
+        shaders: &[(&str, GLenum)],
+        defines: &[(&str, String)],
+    ) -> Result<Self, String> {
+        let mut compiled = Vec::with_capacity(shaders.len());
+        for &(path, kind) in shaders {
+            let preprocessed = preprocess(packs, path, defines)?;
+            let source =
+                CString::new(preprocessed.source).map_err(|e| format!("{}: {}", path, e))?;
+            let files: Vec<&str> = preprocessed.files.iter().map(String::as_str).collect();
+            let shader =
+                Shader::from_source(&source, kind).map_err(|log| remap_info_log(&log, &files))?;
+            compiled.push((shader, kind));
+        }
+        let mut program = Program::new(compiled)?;
+        program.name = shaders
+            .iter()
+            .map(|&(path, _)| path)
+            .collect::<Vec<_>>()
+            .join(", ");
+        Ok(program)
+    }
+
+    // Errors about the way the program is used are only checked in debug
+    // builds, and only reported once
+    fn report(&self, error: String) {
+        if self.reported.borrow_mut().insert(error.clone()) {
+            eprintln!("error: program {}: {}", self.name, error);
+        }
     }
 
     pub fn use_(&self) {
         unsafe { gl::UseProgram(self.id) };
+
+        // Uniforms keep their value, so by the second time the program is
+        // used all of them must have been set at least once
+        if cfg!(debug_assertions) {
+            self.uses.set(self.uses.get() + 1);
+            if self.uses.get() == 2 {
+                let set_uniforms = self.set_uniforms.borrow();
+                let mut unset: Vec<&String> = self
+                    .uniforms
+                    .keys()
+                    .filter(|&name| !set_uniforms.contains(name))
+                    .collect();
+                unset.sort();
+                for name in unset {
+                    self.report(format!("uniform `{}` is never set", name));
+                }
+            }
+        }
+    }
+
+    // Checks in debug builds that the active attributes match the vertex
+    // format
+    pub fn check_attributes(&self, vertex_attributes: &[Attribute]) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+
+        let mut names: Vec<&String> = self.attributes.keys().collect();
+        names.sort();
+        for name in names {
+            let attribute = &self.attributes[name];
+            // Built-ins like gl_VertexID are reported with location -1
+            if attribute.location < 0 {
+                continue;
+            }
+            match vertex_attributes.iter().find(|a| a.name == name) {
+                Some(vertex_attribute) => {
+                    if attribute.location as GLuint != vertex_attribute.location {
+                        self.report(format!(
+                            "attribute `{}` is at location {}, but the vertex format has it at {}",
+                            name, attribute.location, vertex_attribute.location
+                        ));
+                    }
+                    if attribute.type_ != vertex_attribute.glsl_type {
+                        self.report(format!(
+                            "attribute `{}` is a {}, but the vertex format has a {}",
+                            name,
+                            type_name(attribute.type_),
+                            type_name(vertex_attribute.glsl_type)
+                        ));
+                    }
+                }
+                None => self.report(format!(
+                    "attribute `{}` is missing from the vertex format",
+                    name
+                )),
+            }
+        }
     }
 
-    pub fn set_uniform_mat4(&self, location: GLint, matrix: &glm::Mat4) {
+    // Looks up the location of an active uniform, checking in debug builds
+    // that it exists and that its type is one of `types`
+    fn uniform_location(&self, name: &str, types: &[GLenum]) -> Option<GLint> {
+        let uniform = match self.uniforms.get(name) {
+            Some(uniform) => uniform,
+            None => {
+                if cfg!(debug_assertions) {
+                    self.report(format!("`{}` is not an active uniform", name));
+                }
+                return None;
+            }
+        };
+
+        if cfg!(debug_assertions) {
+            if !types.contains(&uniform.type_) {
+                self.report(format!(
+                    "uniform `{}` is a {}, but it was set as a {}",
+                    name,
+                    type_name(uniform.type_),
+                    type_name(types[0])
+                ));
+                return None;
+            }
+            self.set_uniforms.borrow_mut().insert(name.to_string());
+        }
+
+        Some(uniform.location)
+    }
+
+    // Checks in debug builds that the size of a uniform block matches the one
+    // of the Rust struct which is uploaded to it
+    pub fn check_uniform_block(&self, name: &str, size: usize) {
+        if !cfg!(debug_assertions) {
+            return;
+        }
+
+        let c_name = CString::new(name).unwrap();
+        let index = unsafe { gl::GetUniformBlockIndex(self.id, c_name.as_ptr()) };
+        if index == gl::INVALID_INDEX {
+            return;
+        }
+
+        let mut block_size = 0;
+        unsafe {
+            gl::GetActiveUniformBlockiv(
+                self.id,
+                index,
+                gl::UNIFORM_BLOCK_DATA_SIZE,
+                &mut block_size,
+            )
+        };
+        if block_size as usize != size {
+            self.report(format!(
+                "uniform block `{}` is {} bytes, but the struct uploaded to it is {} bytes",
+                name, block_size, size
+            ));
+        }
+    }
+
+    pub fn set_uniform_mat4(&self, name: &str, matrix: &glm::Mat4) {
+        let location = match self.uniform_location(name, &[gl::FLOAT_MAT4]) {
+            Some(location) => location,
+            None => return,
+        };
         unsafe {
             gl::ProgramUniformMatrix4fv(
                 self.id,
@@ -63,14 +349,46 @@ impl Program {
         };
     }
 
-    pub fn set_uniform_vec3(&self, location: GLint, vector: &glm::Vec3) {
-        unsafe { gl::ProgramUniform3fv(self.id, location, 1, glm::value_ptr(&vector).as_ptr()) };
+    pub fn set_uniform_float(&self, name: &str, value: GLfloat) {
+        let location = match self.uniform_location(name, &[gl::FLOAT]) {
+            Some(location) => location,
+            None => return,
+        };
+        unsafe { gl::ProgramUniform1f(self.id, location, value) };
+    }
+
+    pub fn set_uniform_int(&self, name: &str, value: GLint) {
+        let location = match self.uniform_location(name, &[gl::INT]) {
+            Some(location) => location,
+            None => return,
+        };
+        unsafe { gl::ProgramUniform1i(self.id, location, value) };
+    }
+
+    pub fn set_uniform_bool(&self, name: &str, value: bool) {
+        let location = match self.uniform_location(name, &[gl::BOOL]) {
+            Some(location) => location,
+            None => return,
+        };
+        unsafe { gl::ProgramUniform1i(self.id, location, value as GLint) };
     }
 
     #[allow(non_snake_case)]
-    pub fn set_uniform_sampler(&self, location: GLint, texture_unit: GLuint) {
+    pub fn set_uniform_sampler(&self, name: &str, texture_unit: GLuint) {
+        let location = match self.uniform_location(name, &SAMPLER_TYPES) {
+            Some(location) => location,
+            None => return,
+        };
         unsafe { gl::ProgramUniform1i(self.id, location, texture_unit as GLint) };
     }
+
+    pub fn set_uniform_image(&self, name: &str, image_unit: GLuint) {
+        let location = match self.uniform_location(name, &[gl::IMAGE_2D]) {
+            Some(location) => location,
+            None => return,
+        };
+        unsafe { gl::ProgramUniform1i(self.id, location, image_unit as GLint) };
+    }
 }
 
 impl Drop for Program {
@@ -80,3 +398,111 @@ impl Drop for Program {
         }
     }
 }
+
+// The binding point of the Frame uniform block, see shaders/common.glsl
+pub const FRAME_UNIFORMS_BINDING: GLuint = 0;
+
+// Everything the shaders need which is the same for the whole frame. Follows
+// the std140 layout of the Frame uniform block, which is why the vec3s are
+// stored as vec4s.
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+pub struct FrameUniforms {
+    pub view: [[GLfloat; 4]; 4],
+    pub projection: [[GLfloat; 4]; 4],
+    pub camera_position: [GLfloat; 4],
+    pub sun_direction: [GLfloat; 4],
+    pub viewport_size: [GLfloat; 2],
+    pub time: GLfloat,
+    pub light_count: GLuint,
+    pub fog: Fog,
+}
+
+impl FrameUniforms {
+    pub fn new(
+        view: &glm::Mat4,
+        projection: &glm::Mat4,
+        camera_position: &glm::Vec3,
+        sun_direction: &glm::Vec3,
+        viewport_size: (u32, u32),
+        time: f64,
+        light_count: usize,
+    ) -> Self {
+        Self {
+            view: (*view).into(),
+            projection: (*projection).into(),
+            camera_position: glm::vec3_to_vec4(camera_position).into(),
+            sun_direction: glm::vec3_to_vec4(sun_direction).into(),
+            viewport_size: [viewport_size.0 as GLfloat, viewport_size.1 as GLfloat],
+            time: time as GLfloat,
+            light_count: light_count as GLuint,
+            fog: Fog::none(),
+        }
+    }
+
+    pub fn with_fog(self, fog: Fog) -> Self {
+        Self { fog, ..self }
+    }
+}
+
+// A buffer holding a single T, which must follow the std140 layout of the
+// uniform block it is bound to
+pub struct UniformBuffer<T: Copy> {
+    buffer: Buffer<T>,
+}
+
+impl<T: Copy> UniformBuffer<T> {
+    pub fn new() -> Self {
+        Self {
+            buffer: Buffer::new(1),
+        }
+    }
+
+    pub fn update(&self, data: &T) {
+        self.buffer.write(0, std::slice::from_ref(data));
+    }
+
+    pub fn bind(&self, binding: GLuint) {
+        self.buffer.bind_base(gl::UNIFORM_BUFFER, binding);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    fn shader_files(directory: &Path, files: &mut Vec<std::path::PathBuf>) {
+        for entry in std::fs::read_dir(directory).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                shader_files(&path, files);
+            } else if path.extension() == Some("glsl".as_ref()) {
+                files.push(path);
+            }
+        }
+    }
+
+    // The uniforms are looked up by name through reflection, an explicit
+    // location would only be a second source of truth
+    #[test]
+    fn shaders_leave_the_uniform_locations_to_the_linker() {
+        let mut files = vec![];
+        shader_files(
+            &Path::new(env!("CARGO_MANIFEST_DIR")).join("shaders"),
+            &mut files,
+        );
+        assert!(!files.is_empty());
+        for file in files.iter() {
+            let source = std::fs::read_to_string(file).unwrap();
+            for (i, line) in source.lines().enumerate() {
+                let declaration: String = line.split_whitespace().collect();
+                assert!(
+                    !(declaration.starts_with("layout(location") && line.contains(" uniform ")),
+                    "{}:{}: uniform with an explicit location",
+                    file.display(),
+                    i + 1
+                );
+            }
+        }
+    }
+}
@@ -1,106 +1,231 @@
 use gl::types::*;
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use crate::chunk::{Chunk, CHUNK_BLOCKS, CHUNK_X_SIZE, CHUNK_Y_SIZE, CHUNK_Z_SIZE, COBBLESTONE};
+use crate::block::{BlockRegistry, Transparency};
+use crate::chunk::AIR;
 use crate::constants::*;
+use crate::voxel_ray::VoxelRay;
+use crate::world::{World, WORLD_BLOCKS, WORLD_X_SIZE, WORLD_Y_SIZE, WORLD_Z_SIZE};
 
-pub fn raycast(aspect_ratio: f32, fov: f32, camera_pos: &glm::Vec3, camera_ray: &glm::Vec3, up: &glm::Vec3, chunk: &Chunk) -> Vec<[GLfloat; 3]> {
-    let mut offsets: Vec<[GLfloat; 3]> = Vec::with_capacity(CHUNK_BLOCKS);
-
-    let mut block_used: [bool; CHUNK_BLOCKS] = [false; CHUNK_BLOCKS];
-
-    let far_height = 2. * ((1.1 * fov) / 2.).tan() * FAR_DISTANCE;
-    let far_width = aspect_ratio * far_height;
-
-    let right = glm::cross(&camera_ray, &up).normalize();
-    let camera_up = glm::cross(&right, &camera_ray).normalize();
-    let fc = camera_pos + camera_ray * FAR_DISTANCE;
-    let fbl = fc - (camera_up * far_height / 2.) - (right * far_width / 2.);
-
-    let max_u = 320;
-    let max_v = 180;
-    for v in 0..max_v {
-        for u in 0..max_u {
-            let du = u as GLfloat / max_u as GLfloat;
-            let dv = v as GLfloat / max_v as GLfloat;
-            // initialization step
-
-            // ray starting position
-            let ray_start = camera_pos;
-            // ray direction
-            let ray_direction =
-                (fbl + camera_up * far_height * dv + right * far_width * du) - ray_start;
-            let ray_direction = ray_direction.normalize();
-            // voxel on which the ray origin is found
-            let mut ray_voxel = glm::floor(&camera_pos);
-            // how much to increment as we cross voxel boundaries
-            let step = glm::sign(&ray_direction);
-            // the value of t at which the ray crosses the fist vertical voxel boundary
-            let mut t_max = ((ray_voxel + step) - ray_start)
-                .component_div(&ray_direction.add_scalar(0.0000001));
-            // how far along the ray we must move for each component of such movement to equal the width of a voxel
-            let t_delta = (glm::vec3(1., 1., 1.)
-                .component_div(&ray_direction.add_scalar(0.0000001)))
-            .component_mul(&step);
-
-            // traversal step
-            for _ in 0..(FAR_DISTANCE * (3.0f32).sqrt() + 1.) as u32 {
-                if t_max.x < t_max.y {
-                    if t_max.x < t_max.z {
-                        ray_voxel.x += step.x;
-                        t_max.x += t_delta.x;
-                    } else {
-                        ray_voxel.z += step.z;
-                        t_max.z += t_delta.z;
-                    }
-                } else {
-                    if t_max.y < t_max.z {
-                        ray_voxel.y += step.y;
-                        t_max.y += t_delta.y;
-                    } else {
-                        ray_voxel.z += step.z;
-                        t_max.z += t_delta.z;
-                    }
-                }
+// One bit per block of the world, which the threads casting rays can set at
+// the same time
+struct BlockSet {
+    words: Vec<AtomicU64>,
+}
 
-                if ray_voxel.x >= (CHUNK_X_SIZE as f32) {
-                    break;
-                }
-                if ray_voxel.y >= (CHUNK_Y_SIZE as f32) {
-                    break;
-                }
-                if ray_voxel.z >= (CHUNK_Z_SIZE as f32) {
-                    break;
-                }
-                if ray_voxel.x < 0. {
-                    break;
-                }
-                if ray_voxel.y < 0. {
-                    break;
-                }
-                if ray_voxel.z < 0. {
-                    break;
-                }
+impl BlockSet {
+    fn new(len: usize) -> Self {
+        Self {
+            words: (0..len.div_ceil(64)).map(|_| AtomicU64::new(0)).collect(),
+        }
+    }
+
+    // Whether the bit was not set before
+    fn insert(&self, index: usize) -> bool {
+        let bit = 1 << (index % 64);
+        self.words[index / 64].fetch_or(bit, Ordering::Relaxed) & bit == 0
+    }
+
+    fn remove(&mut self, index: usize) {
+        *self.words[index / 64].get_mut() &= !(1 << (index % 64));
+    }
+}
+
+fn block_index(x: GLuint, y: GLuint, z: GLuint) -> usize {
+    (z * WORLD_Y_SIZE * WORLD_X_SIZE + y * WORLD_X_SIZE + x) as usize
+}
+
+// Finds the blocks seen from the camera by casting `resolution` rays through
+// the view, spread over a few threads by rows
+pub struct Raycaster {
+    // The blocks already found by a ray during the current cast. Cleared at the
+    // end of each cast by removing the blocks found, which is cheaper than
+    // clearing the whole set
+    found: BlockSet,
+    pub resolution: (u32, u32),
+    threads: usize,
+}
+
+impl Raycaster {
+    pub fn new(resolution: (u32, u32)) -> Self {
+        Self {
+            found: BlockSet::new(WORLD_BLOCKS),
+            resolution,
+            threads: std::thread::available_parallelism()
+                .map(|threads| threads.get())
+                .unwrap_or(1),
+        }
+    }
 
-                let x_ = ray_voxel.x as GLuint;
-                let y_ = ray_voxel.y as GLuint;
-                let z_ = ray_voxel.z as GLuint;
-                let x = ray_voxel.x;
-                let y = ray_voxel.y;
-                let z = ray_voxel.z;
-
-                if chunk.get(x_, y_, z_) == COBBLESTONE
-                    && !block_used
-                        [(z_ * CHUNK_Y_SIZE * CHUNK_X_SIZE + y_ * CHUNK_X_SIZE + x_) as usize]
-                {
-                    block_used
-                        [(z_ * CHUNK_Y_SIZE * CHUNK_X_SIZE + y_ * CHUNK_X_SIZE + x_) as usize] =
-                        true;
-                    offsets.push([x, y, z]);
-                    break;
+    #[allow(clippy::too_many_arguments)]
+    pub fn raycast(
+        &mut self,
+        aspect_ratio: f32,
+        fov: f32,
+        camera_pos: &glm::Vec3,
+        camera_ray: &glm::Vec3,
+        up: &glm::Vec3,
+        world: &World,
+        block_registry: &BlockRegistry,
+    ) -> Vec<[GLfloat; 3]> {
+        let far_height = 2. * ((1.1 * fov) / 2.).tan() * FAR_DISTANCE;
+        let far_width = aspect_ratio * far_height;
+
+        let right = glm::cross(camera_ray, up).normalize();
+        let camera_up = glm::cross(&right, camera_ray).normalize();
+        let fc = camera_pos + camera_ray * FAR_DISTANCE;
+        let fbl = fc - (camera_up * far_height / 2.) - (right * far_width / 2.);
+
+        let (max_u, max_v) = self.resolution;
+        let rows_per_thread = max_v.div_ceil(self.threads as u32).max(1);
+        let found = &self.found;
+        let cast_rows = |rows: std::ops::Range<u32>| {
+            let mut offsets = vec![];
+            for v in rows {
+                for u in 0..max_u {
+                    let du = u as GLfloat / max_u as GLfloat;
+                    let dv = v as GLfloat / max_v as GLfloat;
+                    // Up to the far plane
+                    let ray =
+                        (fbl + camera_up * far_height * dv + right * far_width * du) - camera_pos;
+                    cast_ray(
+                        camera_pos,
+                        &ray.normalize(),
+                        ray.norm(),
+                        world,
+                        block_registry,
+                        found,
+                        &mut offsets,
+                    );
                 }
             }
+            offsets
+        };
+
+        let offsets: Vec<[GLfloat; 3]> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..max_v)
+                .step_by(rows_per_thread as usize)
+                .map(|first_row| {
+                    let rows = first_row..(first_row + rows_per_thread).min(max_v);
+                    scope.spawn(move || cast_rows(rows))
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|worker| worker.join().unwrap())
+                .collect()
+        });
+
+        for offset in &offsets {
+            self.found.remove(block_index(
+                offset[0] as GLuint,
+                offset[1] as GLuint,
+                offset[2] as GLuint,
+            ));
         }
+
+        offsets
     }
+}
 
-    return offsets;
+// Walks the blocks along a ray, adding those not found yet to `offsets`, until
+// it reaches an opaque block, leaves the world or goes past `max_distance`.
+// The empty boxes known to the world are skipped at once.
+fn cast_ray(
+    ray_start: &glm::Vec3,
+    ray_direction: &glm::Vec3,
+    max_distance: f32,
+    world: &World,
+    block_registry: &BlockRegistry,
+    found: &BlockSet,
+    offsets: &mut Vec<[GLfloat; 3]>,
+) {
+    let world_max = [
+        WORLD_X_SIZE as i32 - 1,
+        WORLD_Y_SIZE as i32 - 1,
+        WORLD_Z_SIZE as i32 - 1,
+    ];
+    let mut ray = VoxelRay::new(ray_start, ray_direction, [0, 0, 0], world_max, max_distance);
+    while let Some(hit) = ray.next() {
+        let [x, y, z] = hit.voxel;
+        let (x, y, z) = (x as GLuint, y as GLuint, z as GLuint);
+
+        let block = world.get(x, y, z);
+        if block == AIR {
+            if let Some((min, max)) = world.empty_box(x, y, z) {
+                ray.skip_box(min, max);
+            }
+        } else {
+            if found.insert(block_index(x, y, z)) {
+                offsets.push([x as GLfloat, y as GLfloat, z as GLfloat]);
+            }
+            // What is behind a transparent block can be seen too
+            if block_registry.transparency(block) == Transparency::Opaque {
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::{COBBLESTONE, GLASS};
+
+    #[test]
+    fn block_sets_report_each_block_once() {
+        let mut set = BlockSet::new(130);
+        assert!(set.insert(3));
+        assert!(!set.insert(3));
+        // In the last word, which is only partly used
+        assert!(set.insert(129));
+        assert!(set.insert(128));
+        set.remove(3);
+        assert!(set.insert(3));
+        assert!(!set.insert(129));
+    }
+
+    #[test]
+    fn block_sets_are_shared_between_threads() {
+        let set = BlockSet::new(1000);
+        let inserted: usize = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..4)
+                .map(|_| scope.spawn(|| (0..1000).filter(|&i| set.insert(i)).count()))
+                .collect();
+            workers
+                .into_iter()
+                .map(|worker| worker.join().unwrap())
+                .sum()
+        });
+        assert_eq!(inserted, 1000);
+    }
+
+    #[test]
+    fn casts_find_the_same_blocks_every_time() {
+        let registry = BlockRegistry::new();
+        let mut world = World::empty();
+        // Looking down the x axis, through the glass at the cobblestone
+        world.set(10, 10, 10, GLASS);
+        world.set(12, 10, 10, COBBLESTONE);
+        world.set(14, 10, 10, COBBLESTONE);
+        let mut raycaster = Raycaster::new((8, 8));
+        let mut cast = || {
+            let mut offsets = raycaster.raycast(
+                1.,
+                1.,
+                &glm::vec3(5.5, 10.5, 10.5),
+                &glm::vec3(1., 0., 0.),
+                &glm::vec3(0., 0., 1.),
+                &world,
+                &registry,
+            );
+            offsets.sort_by(|a, b| a.partial_cmp(b).unwrap());
+            offsets
+        };
+
+        let offsets = cast();
+        assert_eq!(offsets, [[10., 10., 10.], [12., 10., 10.]]);
+        // The blocks found by the first cast were removed from the set
+        assert_eq!(cast(), offsets);
+    }
 }
@@ -0,0 +1,93 @@
+use gl::types::*;
+
+use crate::block::{BlockRegistry, Transparency, BLOCKS};
+use crate::buffer::{Buffer, VertexArray};
+use crate::ibl::EnvironmentLighting;
+use crate::program::Program;
+use crate::texture::{Texture2D, Texture3D, TextureArray};
+use crate::world::{World, WORLD_BLOCKS, WORLD_X_SIZE, WORLD_Y_SIZE, WORLD_Z_SIZE};
+
+// The shader storage buffer binding of the blocks, passed to the shaders as a
+// define
+pub const VOXEL_BLOCKS_BINDING: GLuint = 6;
+
+// The block ids are stored in a byte each
+const _: () = assert!(BLOCKS.len() <= 256, "block ids fit in a byte");
+
+// What the ray marching shader needs to know about a block. Follows the std430
+// layout of VoxelBlock in shaders/raymarch/raymarch.frag.glsl
+#[derive(Debug, Copy, Clone)]
+#[repr(C)]
+struct VoxelBlock {
+    face_layers: [GLuint; 6],
+    transparency: GLuint,
+}
+
+// The whole world uploaded as a 3D texture of block ids, which is drawn
+// without any mesh by marching a ray through it for every pixel. This is an
+// alternative to rasterizing the meshes, to compare them.
+pub struct VoxelVolume {
+    blocks: Texture3D,
+    voxel_blocks: Buffer<VoxelBlock>,
+    // The vertices of the full screen triangle come from gl_VertexID
+    vertex_array: VertexArray,
+}
+
+impl VoxelVolume {
+    pub fn new(world: &World, registry: &BlockRegistry) -> Self {
+        let mut blocks = Vec::with_capacity(WORLD_BLOCKS);
+        for z in 0..WORLD_Z_SIZE {
+            for y in 0..WORLD_Y_SIZE {
+                for x in 0..WORLD_X_SIZE {
+                    blocks.push(world.get(x, y, z) as u8);
+                }
+            }
+        }
+
+        let voxel_blocks: Vec<VoxelBlock> = (0..BLOCKS.len() as GLuint)
+            .map(|block| VoxelBlock {
+                face_layers: registry.face_layers(block),
+                transparency: match registry.transparency(block) {
+                    Transparency::Opaque => 0,
+                    Transparency::Cutout => 1,
+                    Transparency::Translucent => 2,
+                },
+            })
+            .collect();
+
+        Self {
+            blocks: Texture3D::from_bytes([WORLD_X_SIZE, WORLD_Y_SIZE, WORLD_Z_SIZE], &blocks),
+            voxel_blocks: Buffer::from_slice(&voxel_blocks),
+            vertex_array: VertexArray::new(),
+        }
+    }
+
+    // Draws the blocks over the whole screen, writing their depth. The Frame
+    // uniforms, the lights of the tiles and the materials must be bound.
+    pub fn draw(
+        &self,
+        raymarch_program: &Program,
+        block_textures: &TextureArray,
+        environment_lighting: &EnvironmentLighting,
+        brdf_lut: &Texture2D,
+    ) {
+        raymarch_program.use_();
+
+        let (blocks_unit, textures_unit, irradiance_unit, prefiltered_unit, brdf_lut_unit) =
+            (0, 1, 2, 3, 4);
+        self.blocks.bind(blocks_unit);
+        raymarch_program.set_uniform_sampler("blocks", blocks_unit);
+        block_textures.bind(textures_unit);
+        raymarch_program.set_uniform_sampler("tex", textures_unit);
+        environment_lighting.bind(irradiance_unit, prefiltered_unit);
+        raymarch_program.set_uniform_sampler("irradiance_map", irradiance_unit);
+        raymarch_program.set_uniform_sampler("prefiltered_map", prefiltered_unit);
+        brdf_lut.bind(brdf_lut_unit);
+        raymarch_program.set_uniform_sampler("brdf_lut", brdf_lut_unit);
+
+        self.voxel_blocks
+            .bind_base(gl::SHADER_STORAGE_BUFFER, VOXEL_BLOCKS_BINDING);
+        self.vertex_array.bind();
+        unsafe { gl::DrawArrays(gl::TRIANGLES, 0, 3) };
+    }
+}
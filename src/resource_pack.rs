@@ -0,0 +1,380 @@
+use std::cell::RefCell;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+// Every pack has one at its root, see the one of the base pack
+pub const MANIFEST_FILE: &str = "pack.manifest";
+pub const PACK_FORMAT: u32 = 1;
+
+pub struct Manifest {
+    pub name: String,
+    pub description: String,
+}
+
+impl Manifest {
+    // One `key = value` per line, lines starting with # are comments
+    pub fn parse(source: &str) -> Result<Self, String> {
+        let mut name = None;
+        let mut description = String::new();
+        let mut format: Option<u32> = None;
+
+        for (i, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let mut key_value = line.splitn(2, '=');
+            let key = key_value.next().unwrap().trim();
+            let value = match key_value.next() {
+                Some(value) => value.trim(),
+                None => return Err(format!("line {}: expected `key = value`", i + 1)),
+            };
+
+            match key {
+                "name" => name = Some(value.to_string()),
+                "description" => description = value.to_string(),
+                "format" => {
+                    format = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("line {}: invalid format `{}`", i + 1, value))?,
+                    )
+                }
+                _ => return Err(format!("line {}: unknown key `{}`", i + 1, key)),
+            }
+        }
+
+        let name = name.ok_or_else(|| "missing `name`".to_string())?;
+        let format = format.ok_or_else(|| "missing `format`".to_string())?;
+        if format != PACK_FORMAT {
+            return Err(format!(
+                "unsupported format {}, expected {}",
+                format, PACK_FORMAT
+            ));
+        }
+
+        Ok(Self { name, description })
+    }
+}
+
+enum Source {
+    Directory(PathBuf),
+    // ZipArchive needs to be mutable to read from it
+    Zip(RefCell<ZipArchive<File>>),
+}
+
+impl Source {
+    fn read(&self, path: &str) -> Result<Option<Vec<u8>>, String> {
+        let mut bytes = vec![];
+        match self {
+            Source::Directory(directory) => {
+                let file_path = directory.join(path);
+                if !file_path.is_file() {
+                    return Ok(None);
+                }
+                File::open(&file_path)
+                    .and_then(|mut file| file.read_to_end(&mut bytes))
+                    .map_err(|e| format!("{}: {}", file_path.display(), e))?;
+            }
+            Source::Zip(archive) => {
+                let mut archive = archive.borrow_mut();
+                let mut file = match archive.by_name(path) {
+                    Ok(file) => file,
+                    Err(ZipError::FileNotFound) => return Ok(None),
+                    Err(e) => return Err(format!("{}: {}", path, e)),
+                };
+                file.read_to_end(&mut bytes)
+                    .map_err(|e| format!("{}: {}", path, e))?;
+            }
+        }
+        Ok(Some(bytes))
+    }
+
+    fn contains(&self, path: &str) -> Result<bool, String> {
+        match self {
+            Source::Directory(directory) => Ok(directory.join(path).is_file()),
+            Source::Zip(archive) => match archive.borrow_mut().by_name(path) {
+                Ok(_) => Ok(true),
+                Err(ZipError::FileNotFound) => Ok(false),
+                Err(e) => Err(format!("{}: {}", path, e)),
+            },
+        }
+    }
+}
+
+pub struct ResourcePack {
+    pub manifest: Manifest,
+    source: Source,
+}
+
+impl ResourcePack {
+    // Opens either a directory or a .zip archive
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let source = if path.is_dir() {
+            Source::Directory(path.to_path_buf())
+        } else {
+            let file = File::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+            let archive =
+                ZipArchive::new(file).map_err(|e| format!("{}: {}", path.display(), e))?;
+            Source::Zip(RefCell::new(archive))
+        };
+
+        let manifest = source
+            .read(MANIFEST_FILE)?
+            .ok_or_else(|| format!("{}: missing {}", path.display(), MANIFEST_FILE))?;
+        let manifest = Manifest::parse(&String::from_utf8_lossy(&manifest))
+            .map_err(|e| format!("{}/{}: {}", path.display(), MANIFEST_FILE, e))?;
+
+        Ok(Self { manifest, source })
+    }
+
+    // The directory the pack was loaded from, if it is not an archive
+    pub fn directory(&self) -> Option<&Path> {
+        match &self.source {
+            Source::Directory(directory) => Some(directory),
+            Source::Zip(_) => None,
+        }
+    }
+
+    // `path` is relative to the root of the pack and uses / as separator.
+    // Returns None when the pack does not contain it.
+    pub fn read(&self, path: &str) -> Result<Option<Vec<u8>>, String> {
+        self.source.read(path)
+    }
+
+    pub fn contains(&self, path: &str) -> Result<bool, String> {
+        self.source.contains(path)
+    }
+}
+
+// A stack of packs, where a file in a pack overrides the same file in all the
+// packs below it. The base pack is at the bottom.
+pub struct ResourcePacks {
+    packs: Vec<ResourcePack>,
+}
+
+impl ResourcePacks {
+    pub fn new(base: ResourcePack) -> Self {
+        Self { packs: vec![base] }
+    }
+
+    pub fn push(&mut self, pack: ResourcePack) {
+        self.packs.push(pack);
+    }
+
+    pub fn packs(&self) -> &[ResourcePack] {
+        &self.packs
+    }
+
+    pub fn read(&self, path: &str) -> Result<Vec<u8>, String> {
+        for pack in self.packs.iter().rev() {
+            if let Some(bytes) = pack.read(path)? {
+                return Ok(bytes);
+            }
+        }
+        Err(format!("{}: not found in any resource pack", path))
+    }
+
+    // Whether any of the packs contains `path`, without reading it
+    pub fn contains(&self, path: &str) -> Result<bool, String> {
+        for pack in self.packs.iter() {
+            if pack.contains(path)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    pub fn read_to_string(&self, path: &str) -> Result<String, String> {
+        String::from_utf8(self.read(path)?).map_err(|e| format!("{}: {}", path, e))
+    }
+
+    pub fn image(&self, path: &str) -> Result<image::DynamicImage, String> {
+        image::load_from_memory(&self.read(path)?).map_err(|e| format!("{}: {}", path, e))
+    }
+}
+
+// The base pack is the directory containing the executable when it was
+// shipped along with the assets, or the source tree when running with cargo.
+// VOXEL_BASE_PACK takes precedence over both.
+pub fn base_pack_path() -> PathBuf {
+    if let Some(path) = std::env::var_os("VOXEL_BASE_PACK") {
+        return PathBuf::from(path);
+    }
+
+    if let Some(directory) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    {
+        if directory.join(MANIFEST_FILE).is_file() {
+            return directory;
+        }
+    }
+
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+}
+
+// A pack in the temporary directory holding the given files, removed when
+// this is dropped
+#[cfg(test)]
+pub struct TempPack {
+    pub path: PathBuf,
+}
+
+#[cfg(test)]
+impl TempPack {
+    fn path(name: &str, extension: &str) -> PathBuf {
+        std::env::temp_dir().join(format!(
+            "voxel-pack-{}-{}{}",
+            std::process::id(),
+            name,
+            extension
+        ))
+    }
+
+    fn manifest(name: &str) -> String {
+        format!("name = {}\nformat = {}\n", name, PACK_FORMAT)
+    }
+
+    pub fn directory(name: &str, files: &[(&str, &str)]) -> Self {
+        let pack = Self {
+            path: Self::path(name, ""),
+        };
+        let _ = std::fs::remove_dir_all(&pack.path);
+        std::fs::create_dir_all(&pack.path).unwrap();
+        std::fs::write(pack.path.join(MANIFEST_FILE), Self::manifest(name)).unwrap();
+        for (path, content) in files.iter() {
+            let file_path = pack.path.join(path);
+            std::fs::create_dir_all(file_path.parent().unwrap()).unwrap();
+            std::fs::write(file_path, content).unwrap();
+        }
+        pack
+    }
+
+    // The archive is built in memory, then written out for
+    // ResourcePack::open
+    pub fn zip(name: &str, files: &[(&str, &str)]) -> Self {
+        use std::io::Write;
+
+        let mut writer = zip::ZipWriter::new(std::io::Cursor::new(vec![]));
+        let options =
+            zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Stored);
+        let manifest = Self::manifest(name);
+        for (path, content) in [(MANIFEST_FILE, manifest.as_str())].iter().chain(files) {
+            writer.start_file(*path, options).unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        let bytes = writer.finish().unwrap().into_inner();
+
+        let pack = Self {
+            path: Self::path(name, ".zip"),
+        };
+        std::fs::write(&pack.path, bytes).unwrap();
+        pack
+    }
+
+    pub fn open(&self) -> ResourcePack {
+        ResourcePack::open(&self.path).unwrap()
+    }
+}
+
+#[cfg(test)]
+impl Drop for TempPack {
+    fn drop(&mut self) {
+        let _ = if self.path.is_dir() {
+            std::fs::remove_dir_all(&self.path)
+        } else {
+            std::fs::remove_file(&self.path)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_manifests() {
+        let manifest = Manifest::parse(
+            "# A comment\n\nname = Test pack\n  # Indented comment\ndescription = a = b\nformat = 1\n",
+        )
+        .unwrap();
+        assert_eq!(manifest.name, "Test pack");
+        assert_eq!(manifest.description, "a = b");
+
+        let manifest = Manifest::parse("format = 1\nname = No description").unwrap();
+        assert_eq!(manifest.description, "");
+    }
+
+    #[test]
+    fn rejects_broken_manifests() {
+        assert!(Manifest::parse("format = 1").is_err());
+        assert!(Manifest::parse("name = Test pack").is_err());
+        assert!(Manifest::parse("name = Test pack\nformat = 2").is_err());
+        assert!(Manifest::parse("name = Test pack\nformat = one").is_err());
+        assert!(Manifest::parse("name = Test pack\nformat = 1\nversion = 3").is_err());
+        assert!(Manifest::parse("name = Test pack\nformat = 1\nno value").is_err());
+        // Comments must take the whole line
+        assert!(Manifest::parse("name = Test pack\nformat = 1 # current").is_err());
+    }
+
+    #[test]
+    fn later_packs_override_earlier_ones() {
+        let base = TempPack::directory("base", &[("a.txt", "base a"), ("dir/b.txt", "base b")]);
+        let middle = TempPack::directory(
+            "middle",
+            &[("dir/b.txt", "middle b"), ("c.txt", "middle c")],
+        );
+        let top = TempPack::directory("top", &[("c.txt", "top c")]);
+        let mut packs = ResourcePacks::new(base.open());
+        packs.push(middle.open());
+        packs.push(top.open());
+
+        let names: Vec<_> = packs
+            .packs()
+            .iter()
+            .map(|pack| &pack.manifest.name)
+            .collect();
+        assert_eq!(names, ["base", "middle", "top"]);
+        assert_eq!(packs.read_to_string("a.txt").unwrap(), "base a");
+        assert_eq!(packs.read_to_string("dir/b.txt").unwrap(), "middle b");
+        assert_eq!(packs.read_to_string("c.txt").unwrap(), "top c");
+        assert_eq!(
+            packs.read_to_string(MANIFEST_FILE).unwrap(),
+            format!("name = top\nformat = {}\n", PACK_FORMAT)
+        );
+        assert!(packs.read("missing.txt").is_err());
+        assert!(packs.contains("dir/b.txt").unwrap());
+        assert!(!packs.contains("dir/a.txt").unwrap());
+    }
+
+    #[test]
+    fn reads_zip_packs() {
+        let zip = TempPack::zip("zip", &[("dir/a.txt", "zip a")]);
+        let pack = zip.open();
+        assert_eq!(pack.manifest.name, "zip");
+        assert_eq!(pack.read("dir/a.txt").unwrap().unwrap(), b"zip a");
+        assert_eq!(pack.read("missing.txt").unwrap(), None);
+
+        let mut packs = ResourcePacks::new(pack);
+        let top = TempPack::directory("zip-top", &[]);
+        packs.push(top.open());
+        assert_eq!(packs.read_to_string("dir/a.txt").unwrap(), "zip a");
+    }
+
+    #[test]
+    fn removes_temporary_packs() {
+        let directory = TempPack::directory("removed", &[("a.txt", "a")]);
+        let zip = TempPack::zip("removed", &[("a.txt", "a")]);
+        let paths = [directory.path.clone(), zip.path.clone()];
+        assert!(paths.iter().all(|path| path.exists()));
+        drop(directory);
+        drop(zip);
+        assert!(paths.iter().all(|path| !path.exists()));
+    }
+}
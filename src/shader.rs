@@ -47,3 +47,113 @@ impl Drop for Shader {
         unsafe { gl::DeleteShader(self.id) };
     }
 }
+
+// Splits `12rest` into (12, "rest")
+fn leading_number(s: &str) -> Option<(usize, &str)> {
+    let end = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    Some((s[..end].parse().ok()?, &s[end..]))
+}
+
+// Parses the location of a line of GetShaderInfoLog in the formats of Mesa
+// `0:12(5): error: ...`, NVIDIA `0(12) : error C0000: ...` and AMD/Intel
+// `0:12: ...`, returning the source string number, the line and the message
+fn parse_info_log_line(line: &str) -> Option<(usize, usize, &str)> {
+    let (source, rest) = leading_number(line)?;
+    let (line, rest) = if let Some(rest) = rest.strip_prefix('(') {
+        let (line, rest) = leading_number(rest)?;
+        (line, rest.strip_prefix(')')?)
+    } else {
+        let (line, rest) = leading_number(rest.strip_prefix(':')?)?;
+        // Skip Mesa's column
+        match rest.strip_prefix('(') {
+            Some(rest) => (line, rest.split_once(')')?.1),
+            None => (line, rest),
+        }
+    };
+    let message = rest.trim_start().strip_prefix(':')?.trim_start();
+    Some((source, line, message))
+}
+
+// Rewrites the locations in a GetShaderInfoLog as `file:line: message`, where
+// `files[i]` is the name of the i-th source string. Lines in a format we do not
+// know about are kept as they are.
+pub fn remap_info_log(log: &str, files: &[&str]) -> String {
+    let mut remapped = String::with_capacity(log.len());
+    for line in log.lines() {
+        let (severity, rest) = match line.find(": ") {
+            Some(split) if line.starts_with("ERROR: ") || line.starts_with("WARNING: ") => {
+                (&line[..split + 1], &line[split + 2..])
+            }
+            _ => ("", line),
+        };
+
+        match parse_info_log_line(rest) {
+            Some((source, source_line, message)) => {
+                let file = files.get(source).copied().unwrap_or("<unknown>");
+                if severity.is_empty() {
+                    remapped.push_str(&format!("{}:{}: {}\n", file, source_line, message));
+                } else {
+                    remapped.push_str(&format!(
+                        "{}:{}: {} {}\n",
+                        file, source_line, severity, message
+                    ));
+                }
+            }
+            None => {
+                remapped.push_str(line);
+                remapped.push('\n');
+            }
+        }
+    }
+    remapped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_the_info_log_formats() {
+        assert_eq!(
+            parse_info_log_line("0:12(5): error: `foo' undeclared"),
+            Some((0, 12, "error: `foo' undeclared"))
+        );
+        assert_eq!(
+            parse_info_log_line("1(7) : error C0000: syntax error"),
+            Some((1, 7, "error C0000: syntax error"))
+        );
+        assert_eq!(
+            parse_info_log_line("2:3: 'x' : undeclared identifier"),
+            Some((2, 3, "'x' : undeclared identifier"))
+        );
+        assert_eq!(parse_info_log_line("1 compilation errors."), None);
+        assert_eq!(parse_info_log_line("error: no location"), None);
+    }
+
+    #[test]
+    fn remaps_the_info_log() {
+        let files = ["shaders/cube.frag.glsl", "shaders/common.glsl"];
+        let log = [
+            "0:12(5): error: `foo' undeclared",
+            "1(7) : warning C7555: unused variable",
+            "ERROR: 1:3: 'x' : undeclared identifier",
+            "WARNING: 0:4: extension not supported",
+            "ERROR: 1 compilation errors.  No code generated.",
+            "5:1(1): error: from an unknown file",
+        ]
+        .join("\n");
+        assert_eq!(
+            remap_info_log(&log, &files),
+            [
+                "shaders/cube.frag.glsl:12: error: `foo' undeclared",
+                "shaders/common.glsl:7: warning C7555: unused variable",
+                "shaders/common.glsl:3: ERROR: 'x' : undeclared identifier",
+                "shaders/cube.frag.glsl:4: WARNING: extension not supported",
+                "ERROR: 1 compilation errors.  No code generated.",
+                "<unknown>:1: error: from an unknown file",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+}
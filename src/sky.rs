@@ -0,0 +1,158 @@
+use gl::types::*;
+
+use crate::buffer::{Buffer, VertexArray};
+use crate::program::{FrameUniforms, Program, UniformBuffer, FRAME_UNIFORMS_BINDING};
+use crate::texture::TextureCubeMap;
+use crate::vertex::{skybox_cube, PositionVertex};
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SkyMode {
+    // The six images of textures/skybox
+    CubeMap,
+    // Rayleigh/Mie single scattering computed by shaders/sky/sky.frag.glsl
+    Atmosphere,
+}
+
+impl SkyMode {
+    pub fn toggle(self) -> Self {
+        match self {
+            SkyMode::CubeMap => SkyMode::Atmosphere,
+            SkyMode::Atmosphere => SkyMode::CubeMap,
+        }
+    }
+}
+
+pub const ATMOSPHERE_CUBEMAP_SIZE: u32 = 128;
+
+// How many frames it takes the sun to go around once
+const DAY_LENGTH: f64 = 20_000.;
+
+pub fn sun_direction(time: f64) -> glm::Vec3 {
+    // Start in the morning, with the sun slightly above the horizon
+    let angle = 0.1 + 2. * std::f64::consts::PI * time / DAY_LENGTH;
+    glm::normalize(&glm::vec3(0.2, angle.cos() as f32, angle.sin() as f32))
+}
+
+// The cube drawn around the camera by the skybox programs, also used to render
+// into cubemaps
+pub struct SkyboxCube {
+    vertex_buffer: Buffer<PositionVertex>,
+    vertex_array: VertexArray,
+}
+
+impl SkyboxCube {
+    pub fn new() -> Self {
+        let vertex_buffer = Buffer::from_slice(&skybox_cube());
+        let vertex_array = VertexArray::with_vertex_buffer(&vertex_buffer);
+        Self {
+            vertex_buffer,
+            vertex_array,
+        }
+    }
+
+    pub fn draw(&self) {
+        self.vertex_array.bind();
+        unsafe {
+            gl::DrawArrays(gl::TRIANGLES, 0, self.vertex_buffer.len() as GLsizei);
+        }
+    }
+}
+
+// Draws the skybox cube with `program`, which must be in use with all of its
+// other uniforms set, into `mip_level` of every face of `cubemap`, whose
+// faces are `size` pixels wide at that level
+pub fn render_cubemap(
+    program: &Program,
+    frame_uniforms: &UniformBuffer<FrameUniforms>,
+    skybox_cube: &SkyboxCube,
+    sun_direction: &glm::Vec3,
+    cubemap: &TextureCubeMap,
+    size: u32,
+    mip_level: u32,
+) {
+    // Camera direction and up vector for each face, in the order given by
+    // GL_TEXTURE_CUBE_MAP_POSITIVE_X + face
+    let faces = [
+        (glm::vec3(1., 0., 0.), glm::vec3(0., -1., 0.)),
+        (glm::vec3(-1., 0., 0.), glm::vec3(0., -1., 0.)),
+        (glm::vec3(0., 1., 0.), glm::vec3(0., 0., 1.)),
+        (glm::vec3(0., -1., 0.), glm::vec3(0., 0., -1.)),
+        (glm::vec3(0., 0., 1.), glm::vec3(0., -1., 0.)),
+        (glm::vec3(0., 0., -1.), glm::vec3(0., -1., 0.)),
+    ];
+
+    let model: glm::Mat4 = glm::identity();
+    let projection: glm::Mat4 = glm::perspective(1., glm::half_pi(), 0.1, 10.);
+
+    let mut previous_viewport: [GLint; 4] = [0; 4];
+    let mut fbo = 0;
+    unsafe {
+        gl::GetIntegerv(gl::VIEWPORT, previous_viewport.as_mut_ptr());
+        gl::CreateFramebuffers(1, &mut fbo);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, fbo);
+        gl::Viewport(0, 0, size as GLsizei, size as GLsizei);
+        gl::Disable(gl::DEPTH_TEST);
+    }
+
+    program.set_uniform_mat4("model", &model);
+    frame_uniforms.bind(FRAME_UNIFORMS_BINDING);
+
+    let origin = glm::vec3(0., 0., 0.);
+    for (face, (direction, up)) in faces.iter().enumerate() {
+        let view = glm::look_at(&origin, direction, up);
+        frame_uniforms.update(&FrameUniforms::new(
+            &view,
+            &projection,
+            &origin,
+            sun_direction,
+            (size, size),
+            0.,
+            0,
+        ));
+
+        unsafe {
+            gl::NamedFramebufferTextureLayer(
+                fbo,
+                gl::COLOR_ATTACHMENT0,
+                cubemap.name(),
+                mip_level as GLint,
+                face as GLint,
+            );
+        }
+        skybox_cube.draw();
+    }
+
+    unsafe {
+        gl::Enable(gl::DEPTH_TEST);
+        gl::BindFramebuffer(gl::FRAMEBUFFER, 0);
+        gl::DeleteFramebuffers(1, &fbo);
+        gl::Viewport(
+            previous_viewport[0],
+            previous_viewport[1],
+            previous_viewport[2],
+            previous_viewport[3],
+        );
+    }
+}
+
+// Renders the procedural sky into the faces of `cubemap`, so that it can be
+// sampled for the environment reflections like the cubemap skybox
+pub fn bake_atmosphere(
+    atmosphere_program: &Program,
+    frame_uniforms: &UniformBuffer<FrameUniforms>,
+    skybox_cube: &SkyboxCube,
+    sun_direction: &glm::Vec3,
+    cubemap: &TextureCubeMap,
+    size: u32,
+) {
+    atmosphere_program.use_();
+    render_cubemap(
+        atmosphere_program,
+        frame_uniforms,
+        skybox_cube,
+        sun_direction,
+        cubemap,
+        size,
+        0,
+    );
+}
@@ -0,0 +1,188 @@
+use gl::types::*;
+use std::collections::HashMap;
+
+use crate::chunk::{Chunk, CHUNK_X_SIZE, CHUNK_Y_SIZE, CHUNK_Z_SIZE};
+
+// A node covers a cube of blocks, which are either all the same or split into
+// the 8 cubes of half its size
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum Node {
+    Uniform(GLuint),
+    // Index in Octree::children
+    Split(u32),
+}
+
+// The children of a split node, the bits of the index of a child telling on
+// which half along x, y and z it is
+type Children = [Node; 8];
+
+const _: () = assert!(
+    CHUNK_X_SIZE == CHUNK_Z_SIZE && CHUNK_Y_SIZE == CHUNK_Z_SIZE,
+    "chunks are cubes"
+);
+
+fn child_index(x: u32, y: u32, z: u32, half: u32) -> usize {
+    ((x & half != 0) as usize) | ((y & half != 0) as usize) << 1 | ((z & half != 0) as usize) << 2
+}
+
+// The blocks of a cube of `size` blocks, a power of two, as a sparse voxel
+// octree, which takes much less memory than a dense array for mostly empty or
+// mostly uniform volumes. Identical subtrees are only stored once, which makes
+// it a directed acyclic graph rather than a tree: all the stone below the
+// surface ends up in a handful of nodes.
+pub struct Octree {
+    size: u32,
+    root: Node,
+    children: Vec<Children>,
+}
+
+impl Octree {
+    // `block` is called once for every block of the cube
+    pub fn new(size: u32, block: impl Fn(u32, u32, u32) -> GLuint) -> Self {
+        assert!(size.is_power_of_two(), "octrees cover a power of two");
+        let mut children = vec![];
+        let mut shared = HashMap::new();
+        let root = Self::build(&block, [0, 0, 0], size, &mut children, &mut shared);
+        Self {
+            size,
+            root,
+            children,
+        }
+    }
+
+    pub fn from_chunk(chunk: &Chunk) -> Self {
+        Self::new(CHUNK_Z_SIZE, |x, y, z| chunk.get(x, y, z))
+    }
+
+    fn build(
+        block: &impl Fn(u32, u32, u32) -> GLuint,
+        min: [u32; 3],
+        size: u32,
+        children: &mut Vec<Children>,
+        shared: &mut HashMap<Children, u32>,
+    ) -> Node {
+        if size == 1 {
+            return Node::Uniform(block(min[0], min[1], min[2]));
+        }
+
+        let half = size / 2;
+        let mut nodes = [Node::Uniform(0); 8];
+        for (i, node) in nodes.iter_mut().enumerate() {
+            let child_min = [
+                min[0] + half * (i as u32 & 1),
+                min[1] + half * (i as u32 >> 1 & 1),
+                min[2] + half * (i as u32 >> 2 & 1),
+            ];
+            *node = Self::build(block, child_min, half, children, shared);
+        }
+
+        if nodes.iter().all(|&node| node == nodes[0]) {
+            if let Node::Uniform(_) = nodes[0] {
+                return nodes[0];
+            }
+        }
+        let index = *shared.entry(nodes).or_insert_with(|| {
+            children.push(nodes);
+            (children.len() - 1) as u32
+        });
+        Node::Split(index)
+    }
+
+    // Same as Chunk::get
+    pub fn get(&self, x: u32, y: u32, z: u32) -> GLuint {
+        self.uniform_node(x, y, z).0
+    }
+
+    // The block, first block and size of the largest uniform node containing
+    // the given block
+    pub fn uniform_node(&self, x: u32, y: u32, z: u32) -> (GLuint, [u32; 3], u32) {
+        let mut node = self.root;
+        let mut size = self.size;
+        loop {
+            match node {
+                Node::Uniform(block) => {
+                    let mask = !(size - 1);
+                    return (block, [x & mask, y & mask, z & mask], size);
+                }
+                Node::Split(index) => {
+                    size /= 2;
+                    node = self.children[index as usize][child_index(x, y, z, size)];
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    const SIZE: u32 = 16;
+
+    fn terrain(x: u32, y: u32, z: u32) -> GLuint {
+        if z < (x + y) / 4 {
+            1
+        } else if z == (x + y) / 4 && x.is_multiple_of(5) {
+            2
+        } else {
+            0
+        }
+    }
+
+    #[test]
+    fn matches_the_blocks_it_was_built_from() {
+        let octree = Octree::new(SIZE, terrain);
+        for z in 0..SIZE {
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    assert_eq!(octree.get(x, y, z), terrain(x, y, z), "{} {} {}", x, y, z);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn matches_random_blocks() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let blocks: Vec<GLuint> = (0..SIZE * SIZE * SIZE)
+            .map(|_| {
+                if rng.gen_bool(0.1) {
+                    rng.gen_range(1, 4)
+                } else {
+                    0
+                }
+            })
+            .collect();
+        let block = |x, y, z| blocks[((z * SIZE + y) * SIZE + x) as usize];
+        let octree = Octree::new(SIZE, block);
+        for z in 0..SIZE {
+            for y in 0..SIZE {
+                for x in 0..SIZE {
+                    assert_eq!(octree.get(x, y, z), block(x, y, z));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn merges_uniform_cubes() {
+        let octree = Octree::new(SIZE, |_, _, z| if z < SIZE / 2 { 1 } else { 0 });
+        // One split root, with two uniform halves
+        assert_eq!(octree.children.len(), 1);
+        assert_eq!(octree.uniform_node(3, 5, 9), (0, [0, 0, 8], 8));
+        assert_eq!(octree.uniform_node(3, 5, 7), (1, [0, 0, 0], 8));
+    }
+
+    #[test]
+    fn shares_identical_subtrees() {
+        // The same pattern repeated in every cube of 4 blocks
+        let octree = Octree::new(SIZE, |x, y, z| {
+            (x & 3 == 0 && y & 3 == 0 && z & 3 == 0) as GLuint
+        });
+        // One node per level
+        assert_eq!(octree.children.len(), 4);
+        assert_eq!(octree.get(4, 8, 12), 1);
+        assert_eq!(octree.get(5, 8, 12), 0);
+    }
+}
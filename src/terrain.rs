@@ -0,0 +1,195 @@
+use gl::types::*;
+use noise::{Fbm, MultiFractal, NoiseFn, Perlin, Seedable};
+
+use crate::biome::{Biome, BiomeMap, Column};
+use crate::chunk::{AIR, CHUNK_X_SIZE, CHUNK_Y_SIZE, CHUNK_Z_SIZE, WATER};
+
+// The air lower than this is filled with water up to it
+pub const WATER_LEVEL: GLuint = 12;
+
+const OVERHANG_SEED: u32 = 3;
+const CHEESE_SEED: u32 = 4;
+const SPAGHETTI_SEEDS: [u32; 2] = [5, 6];
+
+// The noises are sampled at the world coordinates divided by the size of a
+// chunk, like the biomes. The caves are squashed vertically, so that they
+// spread sideways more than up and down.
+const OVERHANG_FREQUENCY: f64 = 6.;
+const CHEESE_FREQUENCY: f64 = 6.;
+const SPAGHETTI_FREQUENCY: f64 = 4.;
+const CAVE_SQUASH: f64 = 2.;
+
+// Where the cheese noise is above this there are large caverns
+const CHEESE_THRESHOLD: f64 = 0.4;
+// Where both spaghetti noises are closer than this to 0 there are tunnels,
+// along the lines where their zero surfaces cross
+const SPAGHETTI_WIDTH: f64 = 0.06;
+// How many blocks are left above the caverns, and above the tunnels under
+// water so that the sea does not hang over them
+const CAVE_ROOF: GLuint = 6;
+
+// Generates the blocks of the world from a density at every block: the ground
+// is where it is positive. It falls with the height above the heightmap of the
+// biomes and is pushed around by a 3D noise, which makes overhangs and arches,
+// then caves are carved out of it.
+pub struct Terrain {
+    biome_map: BiomeMap,
+    overhangs: Fbm,
+    cheese: Fbm,
+    spaghetti: [Perlin; 2],
+}
+
+impl Terrain {
+    pub fn new() -> Self {
+        Self {
+            biome_map: BiomeMap::new(),
+            overhangs: Fbm::new()
+                .set_seed(OVERHANG_SEED)
+                .set_octaves(3)
+                .set_frequency(OVERHANG_FREQUENCY),
+            cheese: Fbm::new()
+                .set_seed(CHEESE_SEED)
+                .set_octaves(2)
+                .set_frequency(CHEESE_FREQUENCY),
+            spaghetti: [
+                Perlin::new().set_seed(SPAGHETTI_SEEDS[0]),
+                Perlin::new().set_seed(SPAGHETTI_SEEDS[1]),
+            ],
+        }
+    }
+
+    fn point(x: GLuint, y: GLuint, z: GLuint, frequency: f64, squash: f64) -> [f64; 3] {
+        [
+            x as f64 / CHUNK_X_SIZE as f64 * frequency,
+            y as f64 / CHUNK_Y_SIZE as f64 * frequency,
+            z as f64 / CHUNK_Z_SIZE as f64 * frequency * squash,
+        ]
+    }
+
+    fn density(&self, x: GLuint, y: GLuint, z: GLuint, column: &Column) -> f64 {
+        // The noise of a Fbm goes a little past -1 and 1
+        let overhang = self
+            .overhangs
+            .get(Self::point(x, y, z, 1., 1.))
+            .clamp(-1., 1.);
+        column.height as f64 - z as f64 - 0.5 + column.overhang * overhang
+    }
+
+    // Whether the block is carved out by the caverns, only when `covered` by
+    // enough ground, or by the tunnels
+    fn is_cave(&self, x: GLuint, y: GLuint, z: GLuint, covered: bool) -> bool {
+        if covered && self.cheese.get(Self::point(x, y, z, 1., CAVE_SQUASH)) > CHEESE_THRESHOLD {
+            return true;
+        }
+        let point = Self::point(x, y, z, SPAGHETTI_FREQUENCY, CAVE_SQUASH);
+        self.spaghetti
+            .iter()
+            .all(|noise| noise.get(point).abs() < SPAGHETTI_WIDTH)
+    }
+
+    // The biome of the column at the given world coordinates, cheaper than
+    // the whole column
+    pub fn biome(&self, x: GLuint, y: GLuint) -> &'static Biome {
+        self.biome_map.column(x, y).biome
+    }
+
+    // Fills `blocks` with the column at the given world coordinates, from the
+    // bottom up. The returned height is one past its highest block which is
+    // neither air nor water. The blocks only depend on the world coordinates,
+    // so that the chunks continue each other.
+    pub fn column(&self, x: GLuint, y: GLuint, blocks: &mut [GLuint]) -> Column {
+        let mut column = self.biome_map.column(x, y);
+
+        // Only the blocks where the 3D noise can change the sign of the
+        // density need to sample it
+        let band = column.overhang.ceil() as GLuint + 1;
+        let (band_bottom, band_top) = (
+            column.height.saturating_sub(band),
+            (column.height + band).min(CHUNK_Z_SIZE),
+        );
+
+        // From the top down, to know how deep under the surface each block is
+        let mut depth = 0;
+        let mut top = 0;
+        for z in (0..CHUNK_Z_SIZE).rev() {
+            let solid = if z < band_bottom {
+                true
+            } else if z >= band_top {
+                false
+            } else {
+                self.density(x, y, z, &column) > 0.
+            };
+            blocks[z as usize] = if solid {
+                let underwater = depth == 0 && z + 1 < WATER_LEVEL;
+                let block = column.block(z, depth, underwater);
+                depth += 1;
+                top = top.max(z + 1);
+                block
+            } else {
+                depth = 0;
+                if z < WATER_LEVEL {
+                    WATER
+                } else {
+                    AIR
+                }
+            };
+        }
+
+        // Under water the caves stay under the sea floor, on land the tunnels
+        // can open on the surface. The lowest block is never carved, for the
+        // world to have a floor.
+        let underwater = top < WATER_LEVEL;
+        for z in 1..top {
+            let covered = z + CAVE_ROOF < top;
+            if (covered || !underwater)
+                && blocks[z as usize] != WATER
+                && self.is_cave(x, y, z, covered)
+            {
+                blocks[z as usize] = AIR;
+            }
+        }
+
+        column.height = (0..top)
+            .rev()
+            .find(|&z| blocks[z as usize] != AIR && blocks[z as usize] != WATER)
+            .map_or(0, |z| z + 1);
+        column
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn world_column(terrain: &Terrain, x: GLuint, y: GLuint) -> (Vec<GLuint>, GLuint) {
+        let mut blocks = vec![AIR; CHUNK_Z_SIZE as usize];
+        let height = terrain.column(x, y, &mut blocks).height;
+        (blocks, height)
+    }
+
+    #[test]
+    fn columns_are_the_same_every_time() {
+        let (a, b) = (Terrain::new(), Terrain::new());
+        for i in 0..32 {
+            let (x, y) = (i * 37 % 500, i * 91 % 500);
+            assert_eq!(world_column(&a, x, y), world_column(&b, x, y));
+        }
+    }
+
+    #[test]
+    fn carves_caves_under_the_surface() {
+        let terrain = Terrain::new();
+        let mut caves = 0;
+        for y in 0..64 {
+            for x in 0..64 {
+                let (blocks, height) = world_column(&terrain, x * 2, y * 2);
+                assert_ne!(blocks[0], AIR);
+                caves += blocks[..height as usize]
+                    .iter()
+                    .filter(|&&block| block == AIR)
+                    .count();
+            }
+        }
+        assert!(caves > 0);
+    }
+}
@@ -1,5 +1,5 @@
 use gl::types::*;
-use image::RgbImage;
+use image::{ImageBuffer, Pixel, Rgb, RgbImage};
 pub struct Texture2D {
     name: GLuint,
 }
@@ -7,44 +7,114 @@ impl Texture2D {
     pub fn bind(&self, texture_unit: GLuint) {
         unsafe { gl::BindTextureUnit(texture_unit, self.name) };
     }
-    pub fn new(texture_image: RgbImage) -> Self {
-        let texture_width = texture_image.width();
-        let texture_height = texture_image.height();
+
+    // Allocates a single level texture without uploading any data, it is
+    // meant to be rendered to
+    pub fn empty(width: u32, height: u32, internal_format: GLenum) -> Self {
+        Self::empty_with_levels(width, height, 1, internal_format)
+    }
+
+    // Like empty, with mip levels which are filled by hand
+    pub fn empty_with_levels(
+        width: u32,
+        height: u32,
+        mip_levels: u32,
+        internal_format: GLenum,
+    ) -> Self {
         let mut texture_name = 0;
         unsafe {
             gl::CreateTextures(gl::TEXTURE_2D, 1, &mut texture_name);
-            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
 
             gl::TextureStorage2D(
                 texture_name,
-                4,
-                gl::RGB8,
-                texture_width as GLsizei,
-                texture_height as GLsizei,
+                mip_levels as GLsizei,
+                internal_format,
+                width as GLsizei,
+                height as GLsizei,
             );
-            gl::TextureSubImage2D(
+
+            gl::TextureParameteri(texture_name, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as GLint);
+            gl::TextureParameteri(texture_name, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as GLint);
+            gl::TextureParameteri(texture_name, gl::TEXTURE_MIN_FILTER, gl::LINEAR as GLint);
+            gl::TextureParameteri(texture_name, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+        };
+
+        Self { name: texture_name }
+    }
+
+    pub fn name(&self) -> GLuint {
+        self.name
+    }
+}
+
+impl Drop for Texture2D {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteTextures(1, &self.name) };
+    }
+}
+
+// All the layers share the same size and are sampled in the shaders with a
+// sampler2DArray, so that many block textures can be used in one draw call
+pub struct TextureArray {
+    name: GLuint,
+}
+impl TextureArray {
+    pub fn bind(&self, texture_unit: GLuint) {
+        unsafe { gl::BindTextureUnit(texture_unit, self.name) };
+    }
+    // Color images should use an sRGB internal format, so that they are
+    // converted to linear when sampled, data like normal maps a linear one.
+    // The images can be RGB or RGBA.
+    pub fn new<P: Pixel<Subpixel = u8> + 'static>(
+        texture_images: Vec<ImageBuffer<P, Vec<u8>>>,
+        internal_format: GLenum,
+    ) -> Self {
+        let format = match P::CHANNEL_COUNT {
+            3 => gl::RGB,
+            4 => gl::RGBA,
+            channels => unreachable!("unsupported channel count {}", channels),
+        };
+        let texture_width = texture_images[0].width();
+        let texture_height = texture_images[0].height();
+        let mip_levels = 32 - texture_width.max(texture_height).leading_zeros();
+        let mut texture_name = 0;
+        unsafe {
+            gl::CreateTextures(gl::TEXTURE_2D_ARRAY, 1, &mut texture_name);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+
+            gl::TextureStorage3D(
                 texture_name,
-                0,
-                0,
-                0,
+                mip_levels as GLsizei,
+                internal_format,
                 texture_width as GLsizei,
                 texture_height as GLsizei,
-                gl::RGB,
-                gl::UNSIGNED_BYTE,
-                texture_image.into_raw().as_ptr() as *const GLvoid,
+                texture_images.len() as GLsizei,
             );
 
+            for (layer, img) in texture_images.into_iter().enumerate() {
+                assert_eq!(
+                    (img.width(), img.height()),
+                    (texture_width, texture_height),
+                    "all the layers of a texture array must have the same size"
+                );
+                gl::TextureSubImage3D(
+                    texture_name,
+                    0,
+                    0,
+                    0,
+                    layer as GLsizei,
+                    texture_width as GLsizei,
+                    texture_height as GLsizei,
+                    1,
+                    format,
+                    gl::UNSIGNED_BYTE,
+                    img.into_raw().as_ptr() as *const GLvoid,
+                );
+            }
+
             gl::GenerateTextureMipmap(texture_name);
-            gl::TextureParameteri(
-                texture_name,
-                gl::TEXTURE_WRAP_S,
-                gl::CLAMP_TO_BORDER as GLint,
-            );
-            gl::TextureParameteri(
-                texture_name,
-                gl::TEXTURE_WRAP_T,
-                gl::CLAMP_TO_BORDER as GLint,
-            );
+            gl::TextureParameteri(texture_name, gl::TEXTURE_WRAP_S, gl::REPEAT as GLint);
+            gl::TextureParameteri(texture_name, gl::TEXTURE_WRAP_T, gl::REPEAT as GLint);
             gl::TextureParameteri(
                 texture_name,
                 gl::TEXTURE_MIN_FILTER,
@@ -57,6 +127,12 @@ impl Texture2D {
     }
 }
 
+impl Drop for TextureArray {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteTextures(1, &self.name) };
+    }
+}
+
 pub struct TextureCubeMap {
     name: GLuint,
 }
@@ -73,7 +149,7 @@ impl TextureCubeMap {
             gl::TextureStorage2D(
                 texture_name,
                 1,
-                gl::RGB8,
+                gl::SRGB8,
                 texture_images[0].width() as GLsizei,
                 texture_images[0].height() as GLsizei,
             );
@@ -107,4 +183,163 @@ impl TextureCubeMap {
 
         Self { name: texture_name }
     }
+
+    // Allocates a cubemap without uploading any data, its faces are meant to
+    // be rendered to (e.g. by baking the procedural sky). With more than one
+    // mip level each of them is rendered separately, they are not generated.
+    pub fn empty(size: u32, mip_levels: u32, internal_format: GLenum) -> Self {
+        let mut texture_name = 0;
+        unsafe {
+            gl::CreateTextures(gl::TEXTURE_CUBE_MAP, 1, &mut texture_name);
+
+            gl::TextureStorage2D(
+                texture_name,
+                mip_levels as GLsizei,
+                internal_format,
+                size as GLsizei,
+                size as GLsizei,
+            );
+
+            gl::TextureParameteri(texture_name, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as GLint);
+            gl::TextureParameteri(texture_name, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as GLint);
+            gl::TextureParameteri(texture_name, gl::TEXTURE_WRAP_R, gl::CLAMP_TO_EDGE as GLint);
+
+            let min_filter = if mip_levels > 1 {
+                gl::LINEAR_MIPMAP_LINEAR
+            } else {
+                gl::LINEAR
+            };
+            gl::TextureParameteri(texture_name, gl::TEXTURE_MIN_FILTER, min_filter as GLint);
+            gl::TextureParameteri(texture_name, gl::TEXTURE_MAG_FILTER, gl::LINEAR as GLint);
+        };
+
+        Self { name: texture_name }
+    }
+
+    pub fn name(&self) -> GLuint {
+        self.name
+    }
+}
+
+impl Drop for TextureCubeMap {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteTextures(1, &self.name) };
+    }
+}
+
+// A volume of unsigned bytes, read exactly with texelFetch from a usampler3D
+pub struct Texture3D {
+    name: GLuint,
+}
+
+impl Texture3D {
+    pub fn bind(&self, texture_unit: GLuint) {
+        unsafe { gl::BindTextureUnit(texture_unit, self.name) };
+    }
+
+    // `texels` are in rows along x, then along y, then along z
+    pub fn from_bytes(size: [u32; 3], texels: &[u8]) -> Self {
+        assert_eq!(texels.len(), (size[0] * size[1] * size[2]) as usize);
+        let mut texture_name = 0;
+        unsafe {
+            gl::CreateTextures(gl::TEXTURE_3D, 1, &mut texture_name);
+            gl::PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+
+            gl::TextureStorage3D(
+                texture_name,
+                1,
+                gl::R8UI,
+                size[0] as GLsizei,
+                size[1] as GLsizei,
+                size[2] as GLsizei,
+            );
+            gl::TextureSubImage3D(
+                texture_name,
+                0,
+                0,
+                0,
+                0,
+                size[0] as GLsizei,
+                size[1] as GLsizei,
+                size[2] as GLsizei,
+                gl::RED_INTEGER,
+                gl::UNSIGNED_BYTE,
+                texels.as_ptr() as *const GLvoid,
+            );
+
+            gl::TextureParameteri(texture_name, gl::TEXTURE_MIN_FILTER, gl::NEAREST as GLint);
+            gl::TextureParameteri(texture_name, gl::TEXTURE_MAG_FILTER, gl::NEAREST as GLint);
+        };
+
+        Self { name: texture_name }
+    }
+}
+
+impl Drop for Texture3D {
+    fn drop(&mut self) {
+        unsafe { gl::DeleteTextures(1, &self.name) };
+    }
+}
+
+// Builds a tangent space normal map, with green pointing up in the image, from
+// a height map whose red channel is the height. `strength` scales the slopes.
+pub fn normal_map_from_height(height_map: &RgbImage, strength: f32) -> RgbImage {
+    let (width, height) = height_map.dimensions();
+    // The textures repeat, so do the neighbours of the pixels on the border
+    let height_at = |x: i64, y: i64| -> f32 {
+        let x = x.rem_euclid(width as i64) as u32;
+        let y = y.rem_euclid(height as i64) as u32;
+        height_map.get_pixel(x, y)[0] as f32 / 255.
+    };
+
+    RgbImage::from_fn(width, height, |x, y| {
+        let (x, y) = (x as i64, y as i64);
+        let dx = (height_at(x + 1, y) - height_at(x - 1, y)) / 2.;
+        // The rows go downwards
+        let dy = (height_at(x, y + 1) - height_at(x, y - 1)) / 2.;
+
+        let normal = glm::normalize(&glm::vec3(-dx * strength, dy * strength, 1.));
+        let encode = |c: f32| ((c * 0.5 + 0.5) * 255.).round() as u8;
+        Rgb([encode(normal.x), encode(normal.y), encode(normal.z)])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flat_height_maps_face_out_of_the_surface() {
+        let height_map = RgbImage::from_pixel(4, 4, Rgb([100, 0, 0]));
+        let normal_map = normal_map_from_height(&height_map, 2.);
+        assert!(normal_map
+            .pixels()
+            .all(|pixel| *pixel == Rgb([128, 128, 255])));
+    }
+
+    #[test]
+    fn normals_lean_down_the_slopes() {
+        // Rising to the right, the border pixels see the other side of the
+        // texture so only the inner ones are checked
+        let rising_right = RgbImage::from_fn(4, 4, |x, _| Rgb([x as u8 * 50, 0, 0]));
+        let normal_map = normal_map_from_height(&rising_right, 2.);
+        for &(x, y) in &[(1, 1), (2, 2)] {
+            let Rgb([red, green, blue]) = *normal_map.get_pixel(x, y);
+            assert!(red < 128, "{:?}", (red, green, blue));
+            assert_eq!(green, 128);
+            assert!(blue < 255);
+        }
+
+        // Rising towards the bottom of the image, the normal leans towards
+        // its top, which green points to
+        let rising_down = RgbImage::from_fn(4, 4, |_, y| Rgb([y as u8 * 50, 0, 0]));
+        let normal_map = normal_map_from_height(&rising_down, 2.);
+        let Rgb([red, green, _]) = *normal_map.get_pixel(1, 1);
+        assert_eq!(red, 128);
+        assert!(green > 128);
+
+        // A steeper slope leans more
+        let steeper = normal_map_from_height(&rising_down, 4.);
+        assert!(steeper.get_pixel(1, 1)[1] > green);
+    }
 }
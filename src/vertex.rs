@@ -1,170 +1,228 @@
 use gl::types::*;
 
+// One attribute of a vertex format, the attributes must be listed in the same
+// order as the fields of the struct
+pub struct Attribute {
+    // As declared in the shaders: layout(location = location) in glsl_type name;
+    pub name: &'static str,
+    pub location: GLuint,
+    pub glsl_type: GLenum,
+    // How it is stored in the vertex
+    pub components: GLint,
+    pub component_type: GLenum,
+}
+
+fn component_size(component_type: GLenum) -> usize {
+    match component_type {
+        gl::FLOAT => std::mem::size_of::<GLfloat>(),
+        gl::UNSIGNED_INT | gl::INT => std::mem::size_of::<GLuint>(),
+        _ => unreachable!("unsupported component type {}", component_type),
+    }
+}
+
+pub trait Vertex: Copy {
+    const ATTRIBUTES: &'static [Attribute];
+
+    fn vertex_specification(vao: GLuint, vbo: GLuint) {
+        // See
+        // https://docs.google.com/presentation/d/13t-x_HWZOip8GWLAdlZu6_jV-VnIb0-FQBTVnLIsRSw/edit#slide=id.g75eed9a1c_0_67
+        unsafe {
+            // Bind vao and vbo together
+            gl::VertexArrayVertexBuffer(vao, 0, vbo, 0, std::mem::size_of::<Self>() as GLint);
+
+            let mut offset = 0;
+            for attribute in Self::ATTRIBUTES {
+                gl::EnableVertexArrayAttrib(vao, attribute.location);
+                match attribute.component_type {
+                    // Integers are passed as they are rather than converted
+                    // to floats
+                    gl::UNSIGNED_INT | gl::INT => gl::VertexArrayAttribIFormat(
+                        vao,
+                        attribute.location,
+                        attribute.components,
+                        attribute.component_type,
+                        offset as GLuint,
+                    ),
+                    _ => gl::VertexArrayAttribFormat(
+                        vao,
+                        attribute.location,
+                        attribute.components,
+                        attribute.component_type,
+                        gl::FALSE,
+                        offset as GLuint,
+                    ),
+                }
+                gl::VertexArrayAttribBinding(vao, attribute.location, 0);
+
+                offset += attribute.components as usize * component_size(attribute.component_type);
+            }
+            debug_assert_eq!(
+                offset,
+                std::mem::size_of::<Self>(),
+                "the attributes do not cover the whole vertex"
+            );
+        }
+    }
+}
+
 #[derive(Debug, Copy, Clone)]
 #[repr(C, packed)]
-pub struct VertexUVNormal {
+pub struct BlockVertex {
     position: [GLfloat; 3],
     texture_uv: [GLfloat; 2],
     normal: [GLfloat; 3],
+    // Points towards increasing u, the bitangent is cross(tangent, normal)
+    tangent: [GLfloat; 3],
+    layer: GLuint,
+    surface_layer: GLuint,
+    // The block id, which indexes the materials
+    material: GLuint,
 }
-impl VertexUVNormal {
-    pub fn new(position: [GLfloat; 3], texture_uv: [GLfloat; 2], normal: [GLfloat; 3]) -> Self {
+impl BlockVertex {
+    pub fn new(
+        position: [GLfloat; 3],
+        texture_uv: [GLfloat; 2],
+        normal: [GLfloat; 3],
+        tangent: [GLfloat; 3],
+        layer: GLuint,
+        surface_layer: GLuint,
+        material: GLuint,
+    ) -> Self {
         Self {
             position,
             texture_uv,
             normal,
+            tangent,
+            layer,
+            surface_layer,
+            material,
         }
     }
 
-    pub fn vertex_specification(vao: GLuint, vbo: GLuint) {
-        // See
-        // https://docs.google.com/presentation/d/13t-x_HWZOip8GWLAdlZu6_jV-VnIb0-FQBTVnLIsRSw/edit#slide=id.g75eed9a1c_0_67
-        unsafe {
-            // Bind vao and vbo together
-            gl::VertexArrayVertexBuffer(vao, 0, vbo, 0, std::mem::size_of::<Self>() as GLint);
-
-            // layout (location = 0) in vec3 in_position;
-            let offset = 0;
-            let location = 0;
-            gl::EnableVertexArrayAttrib(vao, location);
-            gl::VertexArrayAttribFormat(vao, location, 3, gl::FLOAT, gl::FALSE, offset);
-            gl::VertexArrayAttribBinding(vao, location, 0);
-
-            // layout (location = 1) in vec2 in_texture_uv;
-            let offset = (3 * std::mem::size_of::<GLfloat>()) as GLuint;
-            let location = 1;
-            gl::EnableVertexArrayAttrib(vao, location);
-            gl::VertexArrayAttribFormat(vao, location, 2, gl::FLOAT, gl::FALSE, offset);
-            gl::VertexArrayAttribBinding(vao, location, 0);
-
-            // layout (location = 2) in vec3 in_normal;
-            let offset = (5 * std::mem::size_of::<GLfloat>()) as GLuint;
-            let location = 2;
-            gl::EnableVertexArrayAttrib(vao, location);
-            gl::VertexArrayAttribFormat(vao, location, 3, gl::FLOAT, gl::FALSE, offset);
-            gl::VertexArrayAttribBinding(vao, location, 0);
-        }
+    pub fn position(&self) -> [GLfloat; 3] {
+        self.position
     }
 }
-
-pub fn cube() -> Vec<VertexUVNormal> {
-    return vec![
-        // Back face
-        VertexUVNormal::new([-0.5, -0.5, -0.5], [0.0, 0.0], [0.0, 0.0, -1.0]), // Bottom-left
-        VertexUVNormal::new([0.5, 0.5, -0.5], [1.0, 1.0], [0.0, 0.0, -1.0]),   // top-right
-        VertexUVNormal::new([0.5, -0.5, -0.5], [1.0, 0.0], [0.0, 0.0, -1.0]),  // bottom-right
-        VertexUVNormal::new([0.5, 0.5, -0.5], [1.0, 1.0], [0.0, 0.0, -1.0]),   // top-right
-        VertexUVNormal::new([-0.5, -0.5, -0.5], [0.0, 0.0], [0.0, 0.0, -1.0]), // bottom-left
-        VertexUVNormal::new([-0.5, 0.5, -0.5], [0.0, 1.0], [0.0, 0.0, -1.0]),  // top-left
-        // Front face
-        VertexUVNormal::new([-0.5, -0.5, 0.5], [0.0, 0.0], [0.0, 0.0, 1.0]), // bottom-left
-        VertexUVNormal::new([0.5, -0.5, 0.5], [1.0, 0.0], [0.0, 0.0, 1.0]),  // bottom-right
-        VertexUVNormal::new([0.5, 0.5, 0.5], [1.0, 1.0], [0.0, 0.0, 1.0]),   // top-right
-        VertexUVNormal::new([0.5, 0.5, 0.5], [1.0, 1.0], [0.0, 0.0, 1.0]),   // top-right
-        VertexUVNormal::new([-0.5, 0.5, 0.5], [0.0, 1.0], [0.0, 0.0, 1.0]),  // top-left
-        VertexUVNormal::new([-0.5, -0.5, 0.5], [0.0, 0.0], [0.0, 0.0, 1.0]), // bottom-left
-        // Left face
-        VertexUVNormal::new([-0.5, 0.5, 0.5], [1.0, 0.0], [-1.0, 0.0, 0.0]), // top-right
-        VertexUVNormal::new([-0.5, 0.5, -0.5], [1.0, 1.0], [-1.0, 0.0, 0.0]), // top-left
-        VertexUVNormal::new([-0.5, -0.5, -0.5], [0.0, 1.0], [-1.0, 0.0, 0.0]), // bottom-left
-        VertexUVNormal::new([-0.5, -0.5, -0.5], [0.0, 1.0], [-1.0, 0.0, 0.0]), // bottom-left
-        VertexUVNormal::new([-0.5, -0.5, 0.5], [0.0, 0.0], [-1.0, 0.0, 0.0]), // bottom-right
-        VertexUVNormal::new([-0.5, 0.5, 0.5], [1.0, 0.0], [-1.0, 0.0, 0.0]), // top-right
-        // Right face
-        VertexUVNormal::new([0.5, 0.5, 0.5], [1.0, 0.0], [1.0, 0.0, 0.0]), // top-left
-        VertexUVNormal::new([0.5, -0.5, -0.5], [0.0, 1.0], [1.0, 0.0, 0.0]), // bottom-right
-        VertexUVNormal::new([0.5, 0.5, -0.5], [1.0, 1.0], [1.0, 0.0, 0.0]), // top-right
-        VertexUVNormal::new([0.5, -0.5, -0.5], [0.0, 1.0], [1.0, 0.0, 0.0]), // bottom-right
-        VertexUVNormal::new([0.5, 0.5, 0.5], [1.0, 0.0], [1.0, 0.0, 0.0]), // top-left
-        VertexUVNormal::new([0.5, -0.5, 0.5], [0.0, 0.0], [1.0, 0.0, 0.0]), // bottom-left
-        // Bottom face
-        VertexUVNormal::new([-0.5, -0.5, -0.5], [0.0, 1.0], [0.0, -1.0, 0.0]), // top-right
-        VertexUVNormal::new([0.5, -0.5, -0.5], [1.0, 1.0], [0.0, -1.0, 0.0]),  // top-left
-        VertexUVNormal::new([0.5, -0.5, 0.5], [1.0, 0.0], [0.0, -1.0, 0.0]),   // bottom-left
-        VertexUVNormal::new([0.5, -0.5, 0.5], [1.0, 0.0], [0.0, -1.0, 0.0]),   // bottom-left
-        VertexUVNormal::new([-0.5, -0.5, 0.5], [0.0, 0.0], [0.0, -1.0, 0.0]),  // bottom-right
-        VertexUVNormal::new([-0.5, -0.5, -0.5], [0.0, 1.0], [0.0, -1.0, 0.0]), // top-right
-        // Top face
-        VertexUVNormal::new([-0.5, 0.5, -0.5], [0.0, 1.0], [0.0, 1.0, 0.0]), // top-left
-        VertexUVNormal::new([0.5, 0.5, 0.5], [1.0, 0.0], [0.0, 1.0, 0.0]),   // bottom-right
-        VertexUVNormal::new([0.5, 0.5, -0.5], [1.0, 1.0], [0.0, 1.0, 0.0]),  // top-right
-        VertexUVNormal::new([0.5, 0.5, 0.5], [1.0, 0.0], [0.0, 1.0, 0.0]),   // bottom-right
-        VertexUVNormal::new([-0.5, 0.5, -0.5], [0.0, 1.0], [0.0, 1.0, 0.0]), // top-left
-        VertexUVNormal::new([-0.5, 0.5, 0.5], [0.0, 0.0], [0.0, 1.0, 0.0]),  // bottom-left
+impl Vertex for BlockVertex {
+    const ATTRIBUTES: &'static [Attribute] = &[
+        Attribute {
+            name: "in_position",
+            location: 0,
+            glsl_type: gl::FLOAT_VEC3,
+            components: 3,
+            component_type: gl::FLOAT,
+        },
+        Attribute {
+            name: "in_texture_uv",
+            location: 1,
+            glsl_type: gl::FLOAT_VEC2,
+            components: 2,
+            component_type: gl::FLOAT,
+        },
+        Attribute {
+            name: "in_normal",
+            location: 2,
+            glsl_type: gl::FLOAT_VEC3,
+            components: 3,
+            component_type: gl::FLOAT,
+        },
+        Attribute {
+            name: "in_tangent",
+            location: 6,
+            glsl_type: gl::FLOAT_VEC3,
+            components: 3,
+            component_type: gl::FLOAT,
+        },
+        Attribute {
+            name: "in_layer",
+            location: 3,
+            glsl_type: gl::UNSIGNED_INT,
+            components: 1,
+            component_type: gl::UNSIGNED_INT,
+        },
+        Attribute {
+            name: "in_surface_layer",
+            location: 4,
+            glsl_type: gl::UNSIGNED_INT,
+            components: 1,
+            component_type: gl::UNSIGNED_INT,
+        },
+        Attribute {
+            name: "in_material",
+            location: 5,
+            glsl_type: gl::UNSIGNED_INT,
+            components: 1,
+            component_type: gl::UNSIGNED_INT,
+        },
     ];
 }
 
 #[derive(Debug, Copy, Clone)]
 #[repr(C, packed)]
-pub struct Vertex {
+pub struct PositionVertex {
     position: [GLfloat; 3],
 }
-impl Vertex {
+impl PositionVertex {
     pub fn new(position: [GLfloat; 3]) -> Self {
         Self { position }
     }
-
-    pub fn vertex_specification(vao: GLuint, vbo: GLuint) {
-        unsafe {
-            // Bind vao and vbo together
-            gl::VertexArrayVertexBuffer(vao, 0, vbo, 0, std::mem::size_of::<Self>() as GLint);
-
-            // layout (location = 0) in vec3 in_position;
-            let offset = 0;
-            let location = 0;
-            gl::EnableVertexArrayAttrib(vao, location);
-            gl::VertexArrayAttribFormat(vao, location, 3, gl::FLOAT, gl::FALSE, offset);
-            gl::VertexArrayAttribBinding(vao, location, 0);
-        }
-    }
+}
+impl Vertex for PositionVertex {
+    const ATTRIBUTES: &'static [Attribute] = &[Attribute {
+        name: "in_position",
+        location: 0,
+        glsl_type: gl::FLOAT_VEC3,
+        components: 3,
+        component_type: gl::FLOAT,
+    }];
 }
 
-pub fn skybox_cube() -> Vec<Vertex> {
+pub fn skybox_cube() -> Vec<PositionVertex> {
     // The vertices in 1 -> 3 -> 2 rather than 1 -> 2 -> 3, and the normals are
     // inverted. We always are inside the skybox cube so the backface culling
     // would delete everything if we kept the same order as a regular cube
     return vec![
         // Front face (+X)
-        Vertex::new([0.5, 0.5, 0.5]),
-        Vertex::new([0.5, 0.5, -0.5]),
-        Vertex::new([0.5, -0.5, -0.5]),
-        Vertex::new([0.5, -0.5, -0.5]),
-        Vertex::new([0.5, -0.5, 0.5]),
-        Vertex::new([0.5, 0.5, 0.5]),
+        PositionVertex::new([0.5, 0.5, 0.5]),
+        PositionVertex::new([0.5, 0.5, -0.5]),
+        PositionVertex::new([0.5, -0.5, -0.5]),
+        PositionVertex::new([0.5, -0.5, -0.5]),
+        PositionVertex::new([0.5, -0.5, 0.5]),
+        PositionVertex::new([0.5, 0.5, 0.5]),
         // Back face (-X)
-        Vertex::new([-0.5, 0.5, 0.5]),
-        Vertex::new([-0.5, -0.5, -0.5]),
-        Vertex::new([-0.5, 0.5, -0.5]),
-        Vertex::new([-0.5, -0.5, -0.5]),
-        Vertex::new([-0.5, 0.5, 0.5]),
-        Vertex::new([-0.5, -0.5, 0.5]),
+        PositionVertex::new([-0.5, 0.5, 0.5]),
+        PositionVertex::new([-0.5, -0.5, -0.5]),
+        PositionVertex::new([-0.5, 0.5, -0.5]),
+        PositionVertex::new([-0.5, -0.5, -0.5]),
+        PositionVertex::new([-0.5, 0.5, 0.5]),
+        PositionVertex::new([-0.5, -0.5, 0.5]),
         // Left (+Y)
-        Vertex::new([-0.5, 0.5, -0.5]),
-        Vertex::new([0.5, 0.5, -0.5]),
-        Vertex::new([0.5, 0.5, 0.5]),
-        Vertex::new([0.5, 0.5, 0.5]),
-        Vertex::new([-0.5, 0.5, 0.5]),
-        Vertex::new([-0.5, 0.5, -0.5]),
+        PositionVertex::new([-0.5, 0.5, -0.5]),
+        PositionVertex::new([0.5, 0.5, -0.5]),
+        PositionVertex::new([0.5, 0.5, 0.5]),
+        PositionVertex::new([0.5, 0.5, 0.5]),
+        PositionVertex::new([-0.5, 0.5, 0.5]),
+        PositionVertex::new([-0.5, 0.5, -0.5]),
         // Right (-Y)
-        Vertex::new([-0.5, -0.5, -0.5]),
-        Vertex::new([0.5, -0.5, 0.5]),
-        Vertex::new([0.5, -0.5, -0.5]),
-        Vertex::new([0.5, -0.5, 0.5]),
-        Vertex::new([-0.5, -0.5, -0.5]),
-        Vertex::new([-0.5, -0.5, 0.5]),
+        PositionVertex::new([-0.5, -0.5, -0.5]),
+        PositionVertex::new([0.5, -0.5, 0.5]),
+        PositionVertex::new([0.5, -0.5, -0.5]),
+        PositionVertex::new([0.5, -0.5, 0.5]),
+        PositionVertex::new([-0.5, -0.5, -0.5]),
+        PositionVertex::new([-0.5, -0.5, 0.5]),
         // Top (+Z)
-        Vertex::new([-0.5, -0.5, 0.5]),
-        Vertex::new([0.5, 0.5, 0.5]),
-        Vertex::new([0.5, -0.5, 0.5]),
-        Vertex::new([0.5, 0.5, 0.5]),
-        Vertex::new([-0.5, -0.5, 0.5]),
-        Vertex::new([-0.5, 0.5, 0.5]),
+        PositionVertex::new([-0.5, -0.5, 0.5]),
+        PositionVertex::new([0.5, 0.5, 0.5]),
+        PositionVertex::new([0.5, -0.5, 0.5]),
+        PositionVertex::new([0.5, 0.5, 0.5]),
+        PositionVertex::new([-0.5, -0.5, 0.5]),
+        PositionVertex::new([-0.5, 0.5, 0.5]),
         // Bottom (-Z)
-        Vertex::new([-0.5, -0.5, -0.5]),
-        Vertex::new([0.5, -0.5, -0.5]),
-        Vertex::new([0.5, 0.5, -0.5]),
-        Vertex::new([0.5, 0.5, -0.5]),
-        Vertex::new([-0.5, 0.5, -0.5]),
-        Vertex::new([-0.5, -0.5, -0.5]),
+        PositionVertex::new([-0.5, -0.5, -0.5]),
+        PositionVertex::new([0.5, -0.5, -0.5]),
+        PositionVertex::new([0.5, 0.5, -0.5]),
+        PositionVertex::new([0.5, 0.5, -0.5]),
+        PositionVertex::new([-0.5, 0.5, -0.5]),
+        PositionVertex::new([-0.5, -0.5, -0.5]),
     ];
 }
@@ -0,0 +1,184 @@
+// The voxels of the first model of a MagicaVoxel .vox file, see
+// https://github.com/ephtracy/voxel-model/blob/master/MagicaVoxel-file-format-vox.txt
+// Like the world, the z axis is up.
+pub struct VoxModel {
+    pub size: [u32; 3],
+    pub voxels: Vec<Voxel>,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Voxel {
+    pub position: [u8; 3],
+    // From 1 to 255, 0 being empty
+    pub color_index: u8,
+}
+
+// The positions of the voxels are bytes, which is also the largest size
+// MagicaVoxel can edit
+const MAX_MODEL_SIZE: u32 = 256;
+
+// Reads the little endian numbers and the chunks of the file
+struct Reader<'a> {
+    bytes: &'a [u8],
+    offset: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, count: usize) -> Result<&'a [u8], String> {
+        if self.bytes.len() - self.offset < count {
+            return Err(format!("unexpected end of file at byte {}", self.offset));
+        }
+        let bytes = &self.bytes[self.offset..self.offset + count];
+        self.offset += count;
+        Ok(bytes)
+    }
+
+    fn u32(&mut self) -> Result<u32, String> {
+        let bytes = self.take(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    // The id and the content of the next chunk, its children being read as
+    // the chunks following it
+    fn chunk(&mut self) -> Result<(&'a [u8], &'a [u8]), String> {
+        let id = self.take(4)?;
+        let content_size = self.u32()? as usize;
+        let _children_size = self.u32()?;
+        Ok((id, self.take(content_size)?))
+    }
+}
+
+impl VoxModel {
+    pub fn parse(bytes: &[u8]) -> Result<Self, String> {
+        let mut reader = Reader { bytes, offset: 0 };
+        if reader.take(4)? != b"VOX " {
+            return Err("not a .vox file".to_string());
+        }
+        let _version = reader.u32()?;
+        let (id, _) = reader.chunk()?;
+        if id != b"MAIN" {
+            return Err("missing the MAIN chunk".to_string());
+        }
+
+        let mut size = None;
+        let mut voxels = None;
+        while reader.offset < bytes.len() && voxels.is_none() {
+            let (id, content) = reader.chunk()?;
+            let mut content = Reader {
+                bytes: content,
+                offset: 0,
+            };
+            match id {
+                b"SIZE" => size = Some([content.u32()?, content.u32()?, content.u32()?]),
+                b"XYZI" => {
+                    let count = content.u32()? as usize;
+                    // Four bytes per voxel, checked before making room for them
+                    if count > content.bytes.len() / 4 {
+                        return Err(format!("{} voxels do not fit in their chunk", count));
+                    }
+                    let mut model_voxels = Vec::with_capacity(count);
+                    for _ in 0..count {
+                        let voxel = content.take(4)?;
+                        model_voxels.push(Voxel {
+                            position: [voxel[0], voxel[1], voxel[2]],
+                            color_index: voxel[3],
+                        });
+                    }
+                    voxels = Some(model_voxels);
+                }
+                // The palette, the materials and the scene graph are not used
+                _ => {}
+            }
+        }
+
+        let size = size.ok_or_else(|| "missing the SIZE chunk".to_string())?;
+        if size.iter().any(|&axis| axis > MAX_MODEL_SIZE) {
+            return Err(format!(
+                "model of size {:?} larger than {} voxels",
+                size, MAX_MODEL_SIZE
+            ));
+        }
+        let voxels = voxels.ok_or_else(|| "missing the XYZI chunk".to_string())?;
+        for voxel in voxels.iter() {
+            if (0..3).any(|axis| u32::from(voxel.position[axis]) >= size[axis]) {
+                return Err(format!("voxel {:?} outside of the model", voxel.position));
+            }
+        }
+        Ok(Self { size, voxels })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk(id: &[u8], content: &[u8]) -> Vec<u8> {
+        let mut bytes = id.to_vec();
+        bytes.extend_from_slice(&(content.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(content);
+        bytes
+    }
+
+    fn file(size: [u32; 3], voxels: &[[u8; 4]]) -> Vec<u8> {
+        let mut size_content = vec![];
+        for &axis in size.iter() {
+            size_content.extend_from_slice(&axis.to_le_bytes());
+        }
+        let mut voxels_content = (voxels.len() as u32).to_le_bytes().to_vec();
+        for voxel in voxels.iter() {
+            voxels_content.extend_from_slice(voxel);
+        }
+        let children = [
+            chunk(b"SIZE", &size_content),
+            chunk(b"XYZI", &voxels_content),
+            chunk(b"RGBA", &[0; 1024]),
+        ]
+        .concat();
+
+        let mut bytes = b"VOX ".to_vec();
+        bytes.extend_from_slice(&150u32.to_le_bytes());
+        bytes.extend_from_slice(b"MAIN");
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+        bytes.extend_from_slice(&(children.len() as u32).to_le_bytes());
+        bytes.extend(children);
+        bytes
+    }
+
+    #[test]
+    fn parses_the_voxels() {
+        let model = VoxModel::parse(&file([2, 3, 4], &[[0, 0, 0, 1], [1, 2, 3, 7]])).unwrap();
+        assert_eq!(model.size, [2, 3, 4]);
+        assert_eq!(
+            model.voxels,
+            vec![
+                Voxel {
+                    position: [0, 0, 0],
+                    color_index: 1
+                },
+                Voxel {
+                    position: [1, 2, 3],
+                    color_index: 7
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn rejects_broken_files() {
+        assert!(VoxModel::parse(b"PNG stuff").is_err());
+        let bytes = file([2, 2, 2], &[[0, 0, 0, 1]]);
+        // Cut in the middle of the voxels, before the palette
+        let palette_size = 12 + 1024;
+        assert!(VoxModel::parse(&bytes[..bytes.len() - palette_size - 2]).is_err());
+        // More voxels than the chunk holds, after the header, the MAIN chunk
+        // and the SIZE chunk
+        let mut bytes = bytes;
+        let count_offset = 8 + 12 + (12 + 12) + 12;
+        bytes[count_offset..count_offset + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(VoxModel::parse(&bytes).is_err());
+        assert!(VoxModel::parse(&file([2, 2, 2], &[[2, 0, 0, 1]])).is_err());
+        assert!(VoxModel::parse(&file([2, MAX_MODEL_SIZE + 1, 2], &[])).is_err());
+        assert!(VoxModel::parse(&file([MAX_MODEL_SIZE, 1, 1], &[])).is_ok());
+    }
+}
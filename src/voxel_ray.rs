@@ -0,0 +1,496 @@
+use crate::block::Face;
+
+// The blocks crossed by a ray, in order, see "A Fast Voxel Traversal Algorithm
+// for Ray Tracing" by Amanatides and Woo. Blocks are centered on integer
+// coordinates, so the voxel [x, y, z] spans from x - 0.5 to x + 0.5 along x,
+// and so on.
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct VoxelHit {
+    pub voxel: [i32; 3],
+    // Along the ray, in units of the length of its direction
+    pub distance: f32,
+    // The face of the voxel the ray went in through, None for the voxel the
+    // ray starts in
+    pub face: Option<Face>,
+}
+
+// The face through which a ray moving by `step` along `axis` enters a voxel
+fn entry_face(axis: usize, step: i32) -> Face {
+    match (axis, step > 0) {
+        (0, true) => Face::NegativeX,
+        (0, false) => Face::PositiveX,
+        (1, true) => Face::NegativeY,
+        (1, false) => Face::PositiveY,
+        (2, true) => Face::NegativeZ,
+        _ => Face::PositiveZ,
+    }
+}
+
+// Iterates over the voxels between `min` and `max`, inclusive, crossed by the
+// ray up to `max_distance`. A ray starting outside of them is clipped to
+// them first. The components of the direction which are zero are handled
+// exactly, the ray then never crosses a boundary along them.
+pub struct VoxelRay {
+    voxel: [i32; 3],
+    step: [i32; 3],
+    // The distance at which the ray crosses the next boundary along each axis.
+    // Computed from the origin every time rather than accumulated, so that
+    // rounding errors do not add up along long rays.
+    t_max: [f32; 3],
+    origin: [f32; 3],
+    direction: [f32; 3],
+    min: [i32; 3],
+    max: [i32; 3],
+    max_distance: f32,
+    // What the next call to next returns
+    next: Option<VoxelHit>,
+}
+
+impl VoxelRay {
+    pub fn new(
+        origin: &glm::Vec3,
+        direction: &glm::Vec3,
+        min: [i32; 3],
+        max: [i32; 3],
+        max_distance: f32,
+    ) -> Self {
+        // Shifted so that voxels span from n to n + 1
+        let origin = [origin.x + 0.5, origin.y + 0.5, origin.z + 0.5];
+        let direction = [direction.x, direction.y, direction.z];
+
+        let mut ray = Self {
+            voxel: [0; 3],
+            step: [0; 3],
+            t_max: [f32::INFINITY; 3],
+            origin,
+            direction,
+            min,
+            max,
+            max_distance,
+            next: None,
+        };
+
+        // Clip the ray against the box around the voxels, one slab per axis
+        let mut t_enter = 0f32;
+        let mut t_exit = max_distance;
+        let mut enter_axis = None;
+        for axis in 0..3 {
+            let (low, high) = (min[axis] as f32, (max[axis] + 1) as f32);
+            if direction[axis] == 0. {
+                if origin[axis] < low || origin[axis] >= high {
+                    return ray;
+                }
+                continue;
+            }
+            let (mut t_low, mut t_high) = (
+                (low - origin[axis]) / direction[axis],
+                (high - origin[axis]) / direction[axis],
+            );
+            if t_low > t_high {
+                std::mem::swap(&mut t_low, &mut t_high);
+            }
+            if t_low > t_enter {
+                t_enter = t_low;
+                enter_axis = Some(axis);
+            }
+            t_exit = t_exit.min(t_high);
+        }
+        if t_enter >= t_exit {
+            return ray;
+        }
+
+        for axis in 0..3 {
+            let position = origin[axis] + direction[axis] * t_enter;
+            ray.voxel[axis] = (position.floor() as i32).max(min[axis]).min(max[axis]);
+            if direction[axis] > 0. {
+                ray.step[axis] = 1;
+            } else if direction[axis] < 0. {
+                ray.step[axis] = -1;
+            }
+        }
+        // Rounding can put the entry point on the wrong side of the boundary
+        // it crosses
+        if let Some(axis) = enter_axis {
+            ray.voxel[axis] = if ray.step[axis] > 0 {
+                min[axis]
+            } else {
+                max[axis]
+            };
+        }
+
+        for axis in 0..3 {
+            ray.update_t_max(axis);
+        }
+
+        ray.next = Some(VoxelHit {
+            voxel: ray.voxel,
+            distance: t_enter,
+            face: enter_axis.map(|axis| entry_face(axis, ray.step[axis])),
+        });
+        ray
+    }
+
+    // Skips the voxels between `min` and `max`, inclusive, if the next voxel is
+    // one of them, going straight to the first voxel out of them. This is how
+    // large empty volumes, like the nodes of an octree, are crossed at once.
+    pub fn skip_box(&mut self, min: [i32; 3], max: [i32; 3]) {
+        match self.next {
+            Some(hit) if (0..3).all(|a| hit.voxel[a] >= min[a] && hit.voxel[a] <= max[a]) => {}
+            _ => return,
+        }
+
+        // The side of the box crossed first
+        let mut exit = None;
+        let mut distance = f32::INFINITY;
+        for axis in 0..3 {
+            let boundary = match self.step[axis] {
+                0 => continue,
+                1 => max[axis] + 1,
+                _ => min[axis],
+            };
+            let t = (boundary as f32 - self.origin[axis]) / self.direction[axis];
+            if t < distance {
+                distance = t;
+                exit = Some(axis);
+            }
+        }
+        let exit = match exit {
+            Some(exit) if distance < self.max_distance => exit,
+            _ => {
+                self.next = None;
+                return;
+            }
+        };
+
+        for axis in 0..3 {
+            self.voxel[axis] = if axis == exit {
+                if self.step[axis] > 0 {
+                    max[axis] + 1
+                } else {
+                    min[axis] - 1
+                }
+            } else {
+                // Still inside the box along the other axes, up to rounding
+                let position = self.origin[axis] + self.direction[axis] * distance;
+                (position.floor() as i32).max(min[axis]).min(max[axis])
+            };
+            self.update_t_max(axis);
+        }
+
+        let inside = self.voxel[exit] >= self.min[exit] && self.voxel[exit] <= self.max[exit];
+        self.next = if inside {
+            Some(VoxelHit {
+                voxel: self.voxel,
+                distance,
+                face: Some(entry_face(exit, self.step[exit])),
+            })
+        } else {
+            None
+        };
+    }
+
+    fn update_t_max(&mut self, axis: usize) {
+        let boundary = match self.step[axis] {
+            0 => return,
+            1 => self.voxel[axis] + 1,
+            _ => self.voxel[axis],
+        };
+        self.t_max[axis] = (boundary as f32 - self.origin[axis]) / self.direction[axis];
+    }
+}
+
+impl Iterator for VoxelRay {
+    type Item = VoxelHit;
+
+    fn next(&mut self) -> Option<VoxelHit> {
+        let hit = self.next.take()?;
+
+        // The axis along which the next boundary is the closest
+        let mut axis = 0;
+        for i in 1..3 {
+            if self.t_max[i] < self.t_max[axis] {
+                axis = i;
+            }
+        }
+
+        let distance = self.t_max[axis];
+        self.voxel[axis] += self.step[axis];
+        let inside = self.voxel[axis] >= self.min[axis] && self.voxel[axis] <= self.max[axis];
+        if distance < self.max_distance && inside {
+            self.update_t_max(axis);
+            self.next = Some(VoxelHit {
+                voxel: self.voxel,
+                distance,
+                face: Some(entry_face(axis, self.step[axis])),
+            });
+        }
+
+        Some(hit)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    const MIN: [i32; 3] = [-2, 0, 1];
+    const MAX: [i32; 3] = [3, 4, 5];
+
+    // A voxel, the distance at which the ray goes in, and the axis it goes in
+    // along
+    type ExpectedHit = ([i32; 3], f32, Option<usize>);
+
+    // Every voxel of the box whose cube the ray goes through for some length,
+    // sorted by the distance at which it goes in, with the axis it goes in
+    // along. Also returns the shortest length of ray inside a voxel, the
+    // order is ambiguous when it is close to 0.
+    fn brute_force(
+        origin: &glm::Vec3,
+        direction: &glm::Vec3,
+        max_distance: f32,
+    ) -> (Vec<ExpectedHit>, f32) {
+        let mut hits = vec![];
+        let mut shortest = f32::INFINITY;
+        for x in MIN[0]..=MAX[0] {
+            for y in MIN[1]..=MAX[1] {
+                for z in MIN[2]..=MAX[2] {
+                    let voxel = [x, y, z];
+                    let (mut t_enter, mut t_exit) = (0f32, max_distance);
+                    let mut enter_axis = None;
+                    let mut missed = false;
+                    for axis in 0..3 {
+                        let (low, high) = (voxel[axis] as f32 - 0.5, voxel[axis] as f32 + 0.5);
+                        if direction[axis] == 0. {
+                            missed |= origin[axis] < low || origin[axis] >= high;
+                            continue;
+                        }
+                        let t_low = (low - origin[axis]) / direction[axis];
+                        let t_high = (high - origin[axis]) / direction[axis];
+                        let (t_low, t_high) = (t_low.min(t_high), t_low.max(t_high));
+                        if t_low > t_enter {
+                            t_enter = t_low;
+                            enter_axis = Some(axis);
+                        }
+                        t_exit = t_exit.min(t_high);
+                    }
+                    if !missed && t_enter < t_exit {
+                        hits.push((voxel, t_enter, enter_axis));
+                        shortest = shortest.min(t_exit - t_enter);
+                    }
+                }
+            }
+        }
+        hits.sort_by(|a, b| a.1.partial_cmp(&b.1).unwrap());
+        (hits, shortest)
+    }
+
+    fn random_vec3(rng: &mut StdRng, range: f32) -> glm::Vec3 {
+        glm::vec3(
+            rng.gen_range(-range, range),
+            rng.gen_range(-range, range),
+            rng.gen_range(-range, range),
+        )
+    }
+
+    fn check(origin: &glm::Vec3, direction: &glm::Vec3, max_distance: f32) -> bool {
+        let (expected, shortest) = brute_force(origin, direction, max_distance);
+        // Grazing an edge or a corner, either order is right
+        if shortest < 1e-3 {
+            return false;
+        }
+
+        let hits: Vec<VoxelHit> =
+            VoxelRay::new(origin, direction, MIN, MAX, max_distance).collect();
+        let voxels: Vec<[i32; 3]> = hits.iter().map(|hit| hit.voxel).collect();
+        let expected_voxels: Vec<[i32; 3]> = expected.iter().map(|hit| hit.0).collect();
+        assert_eq!(
+            voxels, expected_voxels,
+            "origin {:?} direction {:?}",
+            origin, direction
+        );
+        for (hit, &(voxel, distance, axis)) in hits.iter().zip(expected.iter()) {
+            assert!(
+                (hit.distance - distance).abs() < 1e-4,
+                "{:?} is entered at {}, not {}",
+                voxel,
+                distance,
+                hit.distance
+            );
+            let expected_face =
+                axis.map(|axis| entry_face(axis, if direction[axis] > 0. { 1 } else { -1 }));
+            assert_eq!(hit.face, expected_face, "entry face of {:?}", voxel);
+        }
+        true
+    }
+
+    #[test]
+    fn matches_brute_force_from_inside() {
+        let mut rng = StdRng::seed_from_u64(0);
+        let mut checked = 0;
+        for _ in 0..2000 {
+            let origin = glm::vec3(
+                rng.gen_range(MIN[0] as f32 - 0.5, MAX[0] as f32 + 0.5),
+                rng.gen_range(MIN[1] as f32 - 0.5, MAX[1] as f32 + 0.5),
+                rng.gen_range(MIN[2] as f32 - 0.5, MAX[2] as f32 + 0.5),
+            );
+            let direction = random_vec3(&mut rng, 1.).normalize();
+            if check(&origin, &direction, rng.gen_range(0., 12.)) {
+                checked += 1;
+            }
+        }
+        assert!(checked > 1500);
+    }
+
+    #[test]
+    fn matches_brute_force_from_outside() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let mut checked = 0;
+        for _ in 0..2000 {
+            // Aim roughly at the box, some rays still miss it
+            let origin = random_vec3(&mut rng, 15.);
+            let target = random_vec3(&mut rng, 3.) + glm::vec3(0.5, 2., 3.);
+            let direction = (target - origin).normalize();
+            if check(&origin, &direction, 40.) {
+                checked += 1;
+            }
+        }
+        assert!(checked > 1500);
+    }
+
+    #[test]
+    fn matches_brute_force_along_axes_and_planes() {
+        let mut rng = StdRng::seed_from_u64(2);
+        let mut checked = 0;
+        for _ in 0..2000 {
+            let origin = random_vec3(&mut rng, 8.);
+            // Zero one or two of the components, the others can be negative
+            let mut direction = random_vec3(&mut rng, 1.);
+            let zeroed = rng.gen_range(1, 3);
+            for _ in 0..zeroed {
+                direction[rng.gen_range(0, 3)] = 0.;
+            }
+            if direction == glm::vec3(0., 0., 0.) {
+                continue;
+            }
+            if check(&origin, &direction.normalize(), 30.) {
+                checked += 1;
+            }
+        }
+        assert!(checked > 1000);
+    }
+
+    #[test]
+    fn walks_axis_aligned_rays_exactly() {
+        let hits: Vec<VoxelHit> = VoxelRay::new(
+            &glm::vec3(0., 2., 3.),
+            &glm::vec3(-1., 0., 0.),
+            MIN,
+            MAX,
+            100.,
+        )
+        .collect();
+        assert_eq!(hits.len(), 3);
+        assert_eq!(hits[0].voxel, [0, 2, 3]);
+        assert_eq!(hits[0].face, None);
+        assert_eq!(hits[2].voxel, [-2, 2, 3]);
+        assert_eq!(hits[2].distance, 1.5);
+        assert_eq!(hits[2].face, Some(Face::PositiveX));
+    }
+
+    #[test]
+    fn clips_rays_starting_outside() {
+        let mut ray = VoxelRay::new(
+            &glm::vec3(1., 2., 20.),
+            &glm::vec3(0., 0., -1.),
+            MIN,
+            MAX,
+            100.,
+        );
+        let first = ray.next().unwrap();
+        assert_eq!(first.voxel, [1, 2, 5]);
+        assert_eq!(first.distance, 14.5);
+        assert_eq!(first.face, Some(Face::PositiveZ));
+        assert_eq!(ray.count(), 4);
+
+        // Parallel to the box and outside of it, and pointing away from it
+        let outside = glm::vec3(1., 10., 3.);
+        assert_eq!(
+            VoxelRay::new(&outside, &glm::vec3(1., 0., 0.), MIN, MAX, 100.).count(),
+            0
+        );
+        assert_eq!(
+            VoxelRay::new(&outside, &glm::vec3(0., 1., 0.), MIN, MAX, 100.).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn stops_at_the_maximum_distance() {
+        let hits: Vec<VoxelHit> = VoxelRay::new(
+            &glm::vec3(-2., 0., 1.),
+            &glm::vec3(0., 1., 0.),
+            MIN,
+            MAX,
+            2.,
+        )
+        .collect();
+        let voxels: Vec<[i32; 3]> = hits.iter().map(|hit| hit.voxel).collect();
+        assert_eq!(voxels, vec![[-2, 0, 1], [-2, 1, 1], [-2, 2, 1]]);
+    }
+
+    #[test]
+    fn skips_boxes_like_walking_through_them() {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut checked = 0;
+        for _ in 0..2000 {
+            let origin = random_vec3(&mut rng, 8.);
+            let direction = random_vec3(&mut rng, 1.).normalize();
+            let max_distance = rng.gen_range(0., 30.);
+            if brute_force(&origin, &direction, max_distance).1 < 1e-3 {
+                continue;
+            }
+            let (mut low, mut high) = ([0; 3], [0; 3]);
+            for axis in 0..3 {
+                let (a, b) = (
+                    rng.gen_range(MIN[axis], MAX[axis] + 1),
+                    rng.gen_range(MIN[axis], MAX[axis] + 1),
+                );
+                low[axis] = a.min(b);
+                high[axis] = a.max(b);
+            }
+            let in_box =
+                |hit: &VoxelHit| (0..3).all(|a| hit.voxel[a] >= low[a] && hit.voxel[a] <= high[a]);
+
+            let expected: Vec<VoxelHit> =
+                VoxelRay::new(&origin, &direction, MIN, MAX, max_distance)
+                    .filter(|hit| !in_box(hit))
+                    .collect();
+            let mut ray = VoxelRay::new(&origin, &direction, MIN, MAX, max_distance);
+            let mut hits = vec![];
+            loop {
+                ray.skip_box(low, high);
+                match ray.next() {
+                    Some(hit) => hits.push(hit),
+                    None => break,
+                }
+            }
+
+            assert_eq!(
+                hits.len(),
+                expected.len(),
+                "origin {:?} direction {:?}",
+                origin,
+                direction
+            );
+            for (hit, expected) in hits.iter().zip(expected.iter()) {
+                assert_eq!(hit.voxel, expected.voxel);
+                assert_eq!(hit.face, expected.face);
+                assert!((hit.distance - expected.distance).abs() < 1e-4);
+            }
+            checked += 1;
+        }
+        assert!(checked > 1000);
+    }
+}
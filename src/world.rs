@@ -0,0 +1,157 @@
+use gl::types::*;
+
+#[cfg(test)]
+use crate::chunk::CHUNK_BLOCKS;
+use crate::chunk::{Chunk, AIR, CHUNK_X_SIZE, CHUNK_Y_SIZE, CHUNK_Z_SIZE};
+use crate::decoration::Structures;
+use crate::svo::Octree;
+
+// The chunks are laid out side by side in a grid along x and y, starting at
+// the origin
+pub const WORLD_X_CHUNKS: GLuint = 3;
+pub const WORLD_Y_CHUNKS: GLuint = 3;
+
+pub const WORLD_X_SIZE: GLuint = WORLD_X_CHUNKS * CHUNK_X_SIZE;
+pub const WORLD_Y_SIZE: GLuint = WORLD_Y_CHUNKS * CHUNK_Y_SIZE;
+pub const WORLD_Z_SIZE: GLuint = CHUNK_Z_SIZE;
+pub const WORLD_BLOCKS: usize = (WORLD_X_SIZE * WORLD_Y_SIZE * WORLD_Z_SIZE) as usize;
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Visibility {
+    // Rays cast from the camera every frame find the blocks to draw, which
+    // are then meshed
+    Raycast,
+    // The world is meshed once, the sections outside of the view or hidden
+    // behind others are culled on the GPU
+    Meshes,
+    // Nothing is meshed, rays are marched through the blocks on the GPU for
+    // every pixel, see raymarch.rs
+    Raymarch,
+}
+
+impl Visibility {
+    pub fn toggle(self) -> Self {
+        match self {
+            Visibility::Raycast => Visibility::Meshes,
+            Visibility::Meshes => Visibility::Raymarch,
+            Visibility::Raymarch => Visibility::Raycast,
+        }
+    }
+}
+
+// How the blocks of the chunks are kept in memory
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Storage {
+    // One block id per block, the fastest to query
+    Dense,
+    // Much smaller for mostly empty or uniform chunks, and lets rays cross
+    // empty space in large steps, see svo.rs
+    Sparse,
+}
+
+enum ChunkBlocks {
+    Dense(Chunk),
+    Sparse(Octree),
+}
+
+pub struct World {
+    // Indexed by chunk_y * WORLD_X_CHUNKS + chunk_x
+    chunks: Vec<ChunkBlocks>,
+}
+
+impl World {
+    pub fn new(storage: Storage, structures: &Structures) -> Self {
+        let mut chunks = Vec::with_capacity((WORLD_X_CHUNKS * WORLD_Y_CHUNKS) as usize);
+        for chunk_y in 0..WORLD_Y_CHUNKS {
+            for chunk_x in 0..WORLD_X_CHUNKS {
+                let chunk = Chunk::new(chunk_x, chunk_y, structures);
+                chunks.push(match storage {
+                    Storage::Dense => ChunkBlocks::Dense(chunk),
+                    Storage::Sparse => ChunkBlocks::Sparse(Octree::from_chunk(&chunk)),
+                });
+            }
+        }
+        Self { chunks }
+    }
+
+    // A world made only of air, in dense chunks, for the tests to put blocks in
+    #[cfg(test)]
+    pub fn empty() -> Self {
+        let chunks = (0..WORLD_X_CHUNKS * WORLD_Y_CHUNKS)
+            .map(|_| {
+                ChunkBlocks::Dense(Chunk {
+                    blocks: vec![AIR; CHUNK_BLOCKS],
+                })
+            })
+            .collect();
+        Self { chunks }
+    }
+
+    #[cfg(test)]
+    pub fn set(&mut self, x: GLuint, y: GLuint, z: GLuint, block: GLuint) {
+        let chunk =
+            &mut self.chunks[((y / CHUNK_Y_SIZE) * WORLD_X_CHUNKS + x / CHUNK_X_SIZE) as usize];
+        let (x, y) = (x % CHUNK_X_SIZE, y % CHUNK_Y_SIZE);
+        match chunk {
+            ChunkBlocks::Dense(chunk) => {
+                chunk.blocks[(z * CHUNK_Y_SIZE * CHUNK_X_SIZE + y * CHUNK_X_SIZE + x) as usize] =
+                    block
+            }
+            ChunkBlocks::Sparse(_) => unreachable!("the tests only change dense chunks"),
+        }
+    }
+
+    #[inline(always)]
+    fn chunk(&self, x: GLuint, y: GLuint) -> &ChunkBlocks {
+        &self.chunks[((y / CHUNK_Y_SIZE) * WORLD_X_CHUNKS + x / CHUNK_X_SIZE) as usize]
+    }
+
+    // The block at the given world coordinates, which must be inside the world
+    #[inline(always)]
+    pub fn get(&self, x: GLuint, y: GLuint, z: GLuint) -> GLuint {
+        match self.chunk(x, y) {
+            ChunkBlocks::Dense(chunk) => chunk.get(x % CHUNK_X_SIZE, y % CHUNK_Y_SIZE, z),
+            ChunkBlocks::Sparse(octree) => octree.get(x % CHUNK_X_SIZE, y % CHUNK_Y_SIZE, z),
+        }
+    }
+
+    // The first and last blocks of a box of AIR around the given block, which
+    // must be AIR too, that a ray can skip at once. Only known in sparse
+    // chunks.
+    pub fn empty_box(&self, x: GLuint, y: GLuint, z: GLuint) -> Option<([GLint; 3], [GLint; 3])> {
+        match self.chunk(x, y) {
+            ChunkBlocks::Dense(_) => None,
+            ChunkBlocks::Sparse(octree) => {
+                let (block, min, size) = octree.uniform_node(x % CHUNK_X_SIZE, y % CHUNK_Y_SIZE, z);
+                debug_assert_eq!(block, AIR);
+                let min = [
+                    (x - x % CHUNK_X_SIZE + min[0]) as GLint,
+                    (y - y % CHUNK_Y_SIZE + min[1]) as GLint,
+                    min[2] as GLint,
+                ];
+                let last = size as GLint - 1;
+                Some((min, [min[0] + last, min[1] + last, min[2] + last]))
+            }
+        }
+    }
+
+    pub fn contains(x: GLint, y: GLint, z: GLint) -> bool {
+        x >= 0
+            && y >= 0
+            && z >= 0
+            && x < WORLD_X_SIZE as GLint
+            && y < WORLD_Y_SIZE as GLint
+            && z < WORLD_Z_SIZE as GLint
+    }
+
+    // The (chunk_x, chunk_y) position of every chunk in the grid
+    pub fn chunk_positions() -> impl Iterator<Item = (GLuint, GLuint)> {
+        (0..WORLD_Y_CHUNKS)
+            .flat_map(|chunk_y| (0..WORLD_X_CHUNKS).map(move |chunk_x| (chunk_x, chunk_y)))
+    }
+
+    // The world coordinates of the first block of a chunk
+    pub fn chunk_origin(chunk_x: GLuint, chunk_y: GLuint) -> [GLuint; 3] {
+        [chunk_x * CHUNK_X_SIZE, chunk_y * CHUNK_Y_SIZE, 0]
+    }
+}